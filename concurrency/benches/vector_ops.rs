@@ -0,0 +1,65 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use mpsc::vector::{ops, scalar};
+use std::hint::black_box;
+
+const SIZES: &[usize] = &[64, 4096, 1 << 20];
+
+fn bench_dot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dot_f32");
+    for &n in SIZES {
+        let a = (0..n).map(|i| i as f32 * 0.5).collect::<Vec<_>>();
+        let b = (0..n).map(|i| 1.0 - i as f32 * 0.25).collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::new("scalar", n), &n, |bench, _| {
+            bench.iter(|| scalar::dot(black_box(&a), black_box(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("simd", n), &n, |bench, _| {
+            bench.iter(|| ops::dot(black_box(&a), black_box(&b)).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("dot_i64");
+    for &n in SIZES {
+        let a = (0..n as i64).collect::<Vec<_>>();
+        let b = (0..n as i64).rev().collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::new("scalar", n), &n, |bench, _| {
+            bench.iter(|| scalar::dot(black_box(&a), black_box(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("simd", n), &n, |bench, _| {
+            bench.iter(|| ops::dot(black_box(&a), black_box(&b)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_axpy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("axpy_f64");
+    for &n in SIZES {
+        let x = (0..n).map(|i| i as f64).collect::<Vec<_>>();
+        let mut y = vec![1.0; n];
+        group.bench_with_input(BenchmarkId::new("scalar", n), &n, |bench, _| {
+            bench.iter(|| scalar::axpy(black_box(2.0), black_box(&x), &mut y))
+        });
+        group.bench_with_input(BenchmarkId::new("simd", n), &n, |bench, _| {
+            bench.iter(|| ops::axpy(black_box(2.0), black_box(&x), &mut y).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_sum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sum_i32");
+    for &n in SIZES {
+        let a = (0..n).map(|i| (i % 128) as i32).collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::new("scalar", n), &n, |bench, _| {
+            bench.iter(|| scalar::sum(black_box(&a)))
+        });
+        group.bench_with_input(BenchmarkId::new("simd", n), &n, |bench, _| {
+            bench.iter(|| ops::sum(black_box(&a)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dot, bench_axpy, bench_sum);
+criterion_main!(benches);
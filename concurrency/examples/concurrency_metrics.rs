@@ -1,63 +1,58 @@
 use anyhow::Result;
-use mpsc::metrics::ConcurrencyMetrics;
+use mpsc::metrics::{JsonLinesSink, Registry, Reporter, Sink, StatsdSink, StdoutSink};
 use rand::Rng;
-use std::thread;
 use std::time::Duration;
+use tracing::info;
 
 const N: usize = 2;
 const M: usize = 4;
 
-fn task_worker(idx: usize, metrics: ConcurrencyMetrics) -> Result<()> {
-    thread::spawn(move || {
-        loop {
-            let mut rng = rand::rng();
-            thread::sleep(Duration::from_millis(rng.random_range(100..5000)));
-            metrics.inc(format!("call.thread.worker.{}", idx))?;
-        }
-        #[allow(unreachable_code)]
-        Ok::<_, anyhow::Error>(())
-    });
-    Ok(())
+async fn task_worker(idx: usize, registry: Registry) -> Result<()> {
+    let calls = registry.counter(format!("call.task.worker.{}", idx))?;
+    loop {
+        let sleep = rand::rng().random_range(100..5000);
+        tokio::time::sleep(Duration::from_millis(sleep)).await;
+        calls.inc();
+    }
 }
 
-fn request_worker(metrics: ConcurrencyMetrics) -> Result<()> {
-    thread::spawn(move || {
-        loop {
+async fn request_worker(registry: Registry) -> Result<()> {
+    let in_flight = registry.gauge("req.in_flight")?;
+    loop {
+        let (sleep, page) = {
             let mut rng = rand::rng();
-
-            thread::sleep(Duration::from_millis(rng.random_range(50..800)));
-            let page = rng.random_range(1..256);
-            metrics.inc(format!("req.page.{}", page))?;
-        }
-
-        #[allow(unreachable_code)]
-        Ok::<_, anyhow::Error>(())
-    });
-
-    Ok(())
-}
-fn main() -> Result<()> {
-    let metrics = ConcurrencyMetrics::new();
-    metrics.inc("req.page.1")?;
-    metrics.inc("call.thread.worker.1")?;
-
-    for _ in 0..100 {
-        metrics.inc("call.thread.worker.1")?;
+            (rng.random_range(50..800), rng.random_range(1..256))
+        };
+        in_flight.inc();
+        let timer = registry.timer("req.duration")?;
+        tokio::time::sleep(Duration::from_millis(sleep)).await;
+        timer.stop();
+        in_flight.dec();
+        registry.counter(format!("req.page.{}", page))?.inc();
     }
+}
 
-    println!("{:?}", metrics);
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
 
-    // start N workers and M requesters
+    let registry = Registry::new();
     for idx in 0..N {
-        task_worker(idx, metrics.clone())?;
+        tokio::spawn(task_worker(idx, registry.clone()));
     }
-
     for _ in 0..M {
-        request_worker(metrics.clone())?
+        tokio::spawn(request_worker(registry.clone()));
     }
 
-    loop {
-        thread::sleep(Duration::from_millis(1000));
-        println!("{}", metrics);
-    }
+    // `nc -ul 8125` shows what the statsd sink sends
+    let sinks: Vec<Box<dyn Sink>> = vec![
+        Box::new(StdoutSink::new()),
+        Box::new(JsonLinesSink::create("/tmp/concurrency_metrics.jsonl")?),
+        Box::new(StatsdSink::new("127.0.0.1:8125", "concurrency.")?),
+    ];
+    let reporter = Reporter::spawn(registry, Duration::from_secs(1), sinks);
+
+    tokio::signal::ctrl_c().await?;
+    info!("shutting down, flushing metrics");
+    reporter.shutdown().await
 }
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use mpsc::metrics::{MetricKey, Registry, metrics_router};
+use rand::Rng;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::info;
+
+const M: usize = 4;
+
+async fn request_worker(registry: Registry) -> Result<()> {
+    loop {
+        let (sleep, status) = {
+            let mut rng = rand::rng();
+            let status = if rng.random_range(0..10) == 0 {
+                "500"
+            } else {
+                "200"
+            };
+            (rng.random_range(50..800), status)
+        };
+
+        let timer = registry.timer("http_request_duration_seconds")?;
+        tokio::time::sleep(Duration::from_millis(sleep)).await;
+        timer.stop();
+
+        let key = MetricKey::new(
+            "http_requests_total",
+            &[("method", "GET"), ("status", status)],
+        );
+        registry.counter(key)?.inc();
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let registry = Registry::new();
+    registry.describe("http_requests_total", "Total number of HTTP requests.");
+    registry.describe(
+        "http_request_duration_seconds",
+        "HTTP request latency in seconds.",
+    );
+
+    for _ in 0..M {
+        tokio::spawn(request_worker(registry.clone()));
+    }
+
+    let addr = "0.0.0.0:9090";
+    let listener = TcpListener::bind(addr).await?;
+    info!("serving metrics on http://{}/metrics", addr);
+    axum::serve(listener, metrics_router(registry)).await?;
+    Ok(())
+}
@@ -1,6 +1,8 @@
 mod matrix;
 pub mod metrics;
-mod vector;
+pub mod pool;
+pub mod vector;
 
 pub use metrics::AtomicMetrics;
+pub use pool::{PoolError, ThreadPool};
 pub use vector::{Vector, dot_product};
@@ -1,43 +1,14 @@
-use crate::vector::{Vector, dot_product};
+use crate::pool;
+use crate::vector::Element;
+use crate::vector::ops::dot_unchecked;
 use anyhow::{Result, anyhow};
+use std::fmt;
 use std::fmt::{Debug, Formatter};
-use std::ops::{Add, AddAssign, Mul};
-use std::sync::mpsc;
-use std::{fmt, thread};
-
-const NUM_THREADS: usize = 4;
-pub struct MsgInput<T> {
-    idx: usize,
-    row: Vector<T>,
-    col: Vector<T>,
-}
-
-pub struct MsgOutput<T> {
-    idx: usize,
-    value: T,
-}
-
-pub struct Msg<T> {
-    pub input: MsgInput<T>,
-    // sender to send the result
-    pub sender: oneshot::Sender<MsgOutput<T>>,
-}
-
-impl<T> MsgInput<T> {
-    pub fn new(idx: usize, row: Vector<T>, col: Vector<T>) -> Self {
-        Self { idx, row, col }
-    }
-}
-
-impl<T> Msg<T> {
-    pub fn new(input: MsgInput<T>, sender: oneshot::Sender<MsgOutput<T>>) -> Self {
-        Self { input, sender }
-    }
-}
+use std::ops::Mul;
 
 impl<T> Mul for Matrix<T>
 where
-    T: Debug + Add<Output = T> + Mul<Output = T> + AddAssign + Copy + Default + Send + 'static,
+    T: Debug + Element,
 {
     type Output = Self;
 
@@ -55,7 +26,7 @@ pub struct Matrix<T> {
 
 pub fn matrix_multiply<T>(mxa: &Matrix<T>, mxb: &Matrix<T>) -> Result<Matrix<T>>
 where
-    T: Debug + Add<Output = T> + Mul<Output = T> + AddAssign + Copy + Default + Send + 'static,
+    T: Debug + Element,
 {
     if mxa.col != mxb.row {
         return Err(anyhow!(
@@ -63,54 +34,30 @@ where
         ));
     }
 
-    // generate 4 threads which receive msg and do dot product
-    let senders = (0..NUM_THREADS)
-        .map(|_| {
-            let (tx, rx) = mpsc::channel::<Msg<T>>();
-            thread::spawn(move || {
-                for msg in rx {
-                    let value = dot_product(msg.input.row, msg.input.col)?;
-                    if let Err(e) = msg.sender.send(MsgOutput {
-                        idx: msg.input.idx,
-                        value,
-                    }) {
-                        eprintln!("Send error: {:?}", e);
-                    }
-                }
-                Ok::<_, anyhow::Error>(())
-            });
-            tx
-        })
-        .collect::<Vec<_>>();
-
-    let matrix_len = mxa.row * mxb.col;
-    let mut data = vec![T::default(); matrix_len];
-    let mut receivers = Vec::with_capacity(matrix_len);
-    for i in 0..mxa.row {
-        for j in 0..mxb.col {
-            let row = Vector::new(&mxa.data[i * mxa.col..(i + 1) * mxa.col]);
-            let col_data = mxb.data[j..]
+    // transpose mxb once so every column is a contiguous slice
+    let columns = (0..mxb.col)
+        .map(|j| {
+            mxb.data[j..]
                 .iter()
                 .step_by(mxb.col)
                 .copied()
-                .collect::<Vec<_>>();
-            let idx = i * mxb.col + j;
-            let col = Vector::new(col_data);
-            let input = MsgInput::new(i * mxb.col + j, row, col);
-            let (tx, rx) = oneshot::channel();
-            let msg = Msg::new(input, tx);
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
 
-            if let Err(e) = senders[idx % NUM_THREADS].send(msg) {
-                eprintln!("Send error {:?}", e);
-            }
-            receivers.push(rx);
+    // one task per output row, each writes to its own chunk of `data`
+    let mut data = vec![T::default(); mxa.row * mxb.col];
+    pool::global().scope(|s| {
+        for (i, out) in data.chunks_mut(mxb.col.max(1)).enumerate() {
+            let row = &mxa.data[i * mxa.col..(i + 1) * mxa.col];
+            let columns = &columns;
+            s.spawn(move |_| {
+                for (value, col) in out.iter_mut().zip(columns.iter()) {
+                    *value = dot_unchecked(row, col);
+                }
+            });
         }
-    }
-
-    for rx in receivers {
-        let output = rx.recv()?;
-        data[output.idx] = output.value;
-    }
+    })?;
 
     Ok(Matrix {
         data,
@@ -0,0 +1,284 @@
+use super::{HistogramSnapshot, MetricKey, MetricKind, MetricSnapshot, Snapshot};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    // https://prometheus.io/docs/instrumenting/exposition_formats/
+    Prometheus,
+    // https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+    OpenMetrics,
+}
+
+impl Snapshot {
+    pub fn to_prometheus(&self) -> String {
+        render(self, Format::Prometheus)
+    }
+
+    pub fn to_openmetrics(&self) -> String {
+        render(self, Format::OpenMetrics)
+    }
+}
+
+fn render(snapshot: &Snapshot, format: Format) -> String {
+    let mut out = String::new();
+
+    // series are grouped by the name they are written under, since `req.page`
+    // and `req_page` are the same family once sanitized and a family may only
+    // have one TYPE line
+    let mut families: BTreeMap<String, Vec<(&MetricKey, &MetricSnapshot)>> = BTreeMap::new();
+    for (key, value) in snapshot.metrics.iter() {
+        let family = family_name(&sanitize_name(&key.name), kind_of(value), format);
+        families.entry(family).or_default().push((key, value));
+    }
+
+    for (family, series) in &families {
+        let help = series
+            .iter()
+            .find_map(|(key, _)| snapshot.help.get(&key.name));
+        if let Some(help) = help {
+            let _ = writeln!(out, "# HELP {} {}", family, escape_help(help, format));
+        }
+        let _ = writeln!(out, "# TYPE {} {}", family, kind_of(series[0].1));
+
+        for (key, value) in series {
+            let name = sanitize_name(&key.name);
+            match value {
+                MetricSnapshot::Counter(v) => {
+                    let sample = match format {
+                        Format::Prometheus => name,
+                        Format::OpenMetrics => format!("{}_total", family),
+                    };
+                    write_sample(&mut out, &sample, key, None, &v.to_string());
+                }
+                MetricSnapshot::Gauge(v) => {
+                    write_sample(&mut out, &name, key, None, &format_float(*v, format));
+                }
+                MetricSnapshot::Histogram(h) => write_histogram(&mut out, &name, key, h, format),
+            }
+        }
+    }
+
+    if format == Format::OpenMetrics {
+        out.push_str("# EOF\n");
+    }
+    out
+}
+
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    key: &MetricKey,
+    histogram: &HistogramSnapshot,
+    format: Format,
+) {
+    let bucket = format!("{}_bucket", name);
+    for (bound, count) in histogram.cumulative() {
+        let le = format_float(bound, format);
+        write_sample(out, &bucket, key, Some(&le), &count.to_string());
+    }
+    let sum = format_float(histogram.sum, format);
+    write_sample(out, &format!("{}_sum", name), key, None, &sum);
+    let count = histogram.count.to_string();
+    write_sample(out, &format!("{}_count", name), key, None, &count);
+}
+
+fn write_sample(out: &mut String, sample: &str, key: &MetricKey, le: Option<&str>, value: &str) {
+    let mut labels = key
+        .labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", sanitize_label(k), escape_label_value(v)))
+        .collect::<Vec<_>>();
+    if let Some(le) = le {
+        labels.push(format!("le=\"{}\"", le));
+    }
+
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", sample, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", sample, labels.join(","), value);
+    }
+}
+
+fn kind_of(value: &MetricSnapshot) -> MetricKind {
+    match value {
+        MetricSnapshot::Counter(_) => MetricKind::Counter,
+        MetricSnapshot::Gauge(_) => MetricKind::Gauge,
+        MetricSnapshot::Histogram(_) => MetricKind::Histogram,
+    }
+}
+
+// OpenMetrics counter families are named without the `_total` suffix
+fn family_name(name: &str, kind: MetricKind, format: Format) -> String {
+    match (format, kind) {
+        (Format::OpenMetrics, MetricKind::Counter) => {
+            name.strip_suffix("_total").unwrap_or(name).to_string()
+        }
+        _ => name.to_string(),
+    }
+}
+
+// metric names must match [a-zA-Z_:][a-zA-Z0-9_:]*, e.g. `req.page.1` becomes `req_page_1`
+pub(super) fn sanitize_name(name: &str) -> String {
+    sanitize(name, true)
+}
+
+// label names must match [a-zA-Z_][a-zA-Z0-9_]*
+fn sanitize_label(name: &str) -> String {
+    sanitize(name, false)
+}
+
+fn sanitize(name: &str, allow_colon: bool) -> String {
+    let mut ret = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if ret.is_empty() || ret.starts_with(|c: char| c.is_ascii_digit()) {
+        ret.insert(0, '_');
+    }
+    ret
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn escape_help(help: &str, format: Format) -> String {
+    let help = help.replace('\\', r"\\").replace('\n', r"\n");
+    match format {
+        Format::Prometheus => help,
+        Format::OpenMetrics => help.replace('"', r#"\""#),
+    }
+}
+
+fn format_float(v: f64, format: Format) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else if format == Format::OpenMetrics && v.fract() == 0.0 {
+        // OpenMetrics wants canonical floats, e.g. le="1.0" rather than le="1"
+        format!("{:.1}", v)
+    } else {
+        v.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Registry;
+    use anyhow::Result;
+
+    fn registry() -> Result<Registry> {
+        let registry = Registry::new();
+        registry.describe("http_requests_total", "Total HTTP requests.");
+        registry
+            .counter(MetricKey::new(
+                "http_requests_total",
+                &[("method", "GET"), ("status", "200")],
+            ))?
+            .inc_by(3);
+        registry
+            .counter(MetricKey::new(
+                "http_requests_total",
+                &[("method", "POST"), ("status", "500")],
+            ))?
+            .inc();
+        registry.gauge("req.in_flight")?.set(2.0);
+        let latency = registry.histogram_with_buckets("latency_seconds", &[0.1, 1.0])?;
+        latency.observe(0.05);
+        latency.observe(0.5);
+        Ok(registry)
+    }
+
+    #[test]
+    fn test_render_prometheus() -> Result<()> {
+        let expected = r#"# HELP http_requests_total Total HTTP requests.
+# TYPE http_requests_total counter
+http_requests_total{method="GET",status="200"} 3
+http_requests_total{method="POST",status="500"} 1
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le="0.1"} 1
+latency_seconds_bucket{le="1"} 2
+latency_seconds_bucket{le="+Inf"} 2
+latency_seconds_sum 0.55
+latency_seconds_count 2
+# TYPE req_in_flight gauge
+req_in_flight 2
+"#;
+        assert_eq!(registry()?.snapshot().to_prometheus(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_openmetrics() -> Result<()> {
+        let expected = r#"# HELP http_requests Total HTTP requests.
+# TYPE http_requests counter
+http_requests_total{method="GET",status="200"} 3
+http_requests_total{method="POST",status="500"} 1
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le="0.1"} 1
+latency_seconds_bucket{le="1.0"} 2
+latency_seconds_bucket{le="+Inf"} 2
+latency_seconds_sum 0.55
+latency_seconds_count 2
+# TYPE req_in_flight gauge
+req_in_flight 2.0
+# EOF
+"#;
+        assert_eq!(registry()?.snapshot().to_openmetrics(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_names_that_sanitize_alike_share_a_family() -> Result<()> {
+        let registry = Registry::new();
+        registry
+            .counter(MetricKey::new("req.page", &[("page", "1")]))?
+            .inc();
+        // sorts between the two, so the family isn't adjacent in the snapshot
+        registry.gauge("req_a")?.set(1.0);
+        registry
+            .counter(MetricKey::new("req_page", &[("page", "2")]))?
+            .inc_by(2);
+        let expected = r#"# TYPE req_a gauge
+req_a 1
+# TYPE req_page counter
+req_page{page="1"} 1
+req_page{page="2"} 2
+"#;
+        assert_eq!(registry.snapshot().to_prometheus(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kinds_conflict_by_exported_name() -> Result<()> {
+        let registry = Registry::new();
+        registry.counter("a.b")?.inc();
+        // `a_b` would share the counter's family under another TYPE
+        let err = registry.gauge("a_b").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "metric a_b is exported as a_b, which is already the counter a.b"
+        );
+        assert!(registry.histogram("a_b").is_err());
+        let out = registry.snapshot().to_prometheus();
+        assert_eq!(out, "# TYPE a_b counter\na_b 1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+    }
+}
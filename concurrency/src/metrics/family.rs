@@ -0,0 +1,312 @@
+use super::{Counter, Gauge, Histogram, Metric, MetricKey, MetricKind, Registry};
+use anyhow::{Result, anyhow};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// upper bound of distinct label sets per family, guards against unbounded
+// values such as user ids ending up in a label
+pub const DEFAULT_MAX_SERIES: usize = 1000;
+
+pub trait RegistryMetric: Sized {
+    const KIND: MetricKind;
+
+    fn from_metric(metric: Metric) -> Option<Self>;
+}
+
+#[derive(Debug)]
+pub(crate) struct Family {
+    pub(crate) label_names: Vec<String>,
+    buckets: Option<Vec<f64>>,
+    max_series: AtomicUsize,
+    series: AtomicUsize,
+}
+
+// a family of metrics sharing a name and label names, one series per label set:
+// `registry.counter_vec("requests", &["method", "path"])?.with_label_values(&["GET", "/"])?.inc()`
+#[derive(Debug)]
+pub struct MetricVec<M> {
+    registry: Registry,
+    name: String,
+    family: Arc<Family>,
+    _metric: PhantomData<M>,
+}
+
+pub type CounterVec = MetricVec<Counter>;
+pub type GaugeVec = MetricVec<Gauge>;
+pub type HistogramVec = MetricVec<Histogram>;
+
+impl Family {
+    pub(crate) fn new(label_names: &[&str], buckets: Option<&[f64]>) -> Self {
+        Self {
+            label_names: label_names.iter().map(|s| s.to_string()).collect(),
+            buckets: buckets.map(|b| b.to_vec()),
+            max_series: AtomicUsize::new(DEFAULT_MAX_SERIES),
+            series: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn reserve(&self, name: &str) -> Result<()> {
+        let max = self.max_series.load(Ordering::Relaxed);
+        let reserved = self
+            .series
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < max).then_some(n + 1)
+            });
+        if reserved.is_err() {
+            return Err(anyhow!(
+                "cardinality limit {} reached, refusing to create {}",
+                max,
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn series(&self) -> usize {
+        self.series.load(Ordering::Relaxed)
+    }
+
+    // undo a `reserve` whose series was never created
+    pub(crate) fn release(&self) {
+        self.series.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn check_buckets(&self, name: &str, buckets: Option<&[f64]>) -> Result<()> {
+        if self.buckets.as_deref() != buckets {
+            return Err(anyhow!(
+                "histogram {} already registered with buckets {:?}",
+                name,
+                self.buckets.as_deref().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<M> Clone for MetricVec<M> {
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry.clone(),
+            name: self.name.clone(),
+            family: self.family.clone(),
+            _metric: PhantomData,
+        }
+    }
+}
+
+impl<M: RegistryMetric> MetricVec<M> {
+    pub(crate) fn new(registry: Registry, name: String, family: Arc<Family>) -> Self {
+        Self {
+            registry,
+            name,
+            family,
+            _metric: PhantomData,
+        }
+    }
+
+    // the limit is shared by every handle of the family
+    pub fn with_max_series(self, max_series: usize) -> Self {
+        self.family.max_series.store(max_series, Ordering::Relaxed);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn label_names(&self) -> &[String] {
+        &self.family.label_names
+    }
+
+    pub fn series(&self) -> usize {
+        self.family.series.load(Ordering::Relaxed)
+    }
+
+    // values are matched to label names by position
+    pub fn with_label_values(&self, values: &[&str]) -> Result<M> {
+        if values.len() != self.family.label_names.len() {
+            return Err(anyhow!(
+                "metric {} expects {} label values, got {}",
+                self.name,
+                self.family.label_names.len(),
+                values.len()
+            ));
+        }
+        let labels = self
+            .family
+            .label_names
+            .iter()
+            .map(String::as_str)
+            .zip(values.iter().copied())
+            .collect::<Vec<_>>();
+        let key = MetricKey::new(self.name.clone(), &labels);
+        self.registry
+            .register(key, Some(&self.family), || match M::KIND {
+                MetricKind::Counter => Ok(Metric::Counter(Counter::new())),
+                MetricKind::Gauge => Ok(Metric::Gauge(Gauge::new())),
+                MetricKind::Histogram => {
+                    let buckets = self.family.buckets.as_deref().unwrap_or_default();
+                    Ok(Metric::Histogram(Histogram::new(buckets)?))
+                }
+            })
+    }
+}
+
+impl RegistryMetric for Counter {
+    const KIND: MetricKind = MetricKind::Counter;
+
+    fn from_metric(metric: Metric) -> Option<Self> {
+        match metric {
+            Metric::Counter(c) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl RegistryMetric for Gauge {
+    const KIND: MetricKind = MetricKind::Gauge;
+
+    fn from_metric(metric: Metric) -> Option<Self> {
+        match metric {
+            Metric::Gauge(g) => Some(g),
+            _ => None,
+        }
+    }
+}
+
+impl RegistryMetric for Histogram {
+    const KIND: MetricKind = MetricKind::Histogram;
+
+    fn from_metric(metric: Metric) -> Option<Self> {
+        match metric {
+            Metric::Histogram(h) => Some(h),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricSnapshot;
+
+    #[test]
+    fn test_counter_vec() -> Result<()> {
+        let registry = Registry::new();
+        let requests = registry.counter_vec("requests", &["method", "path"])?;
+        requests.with_label_values(&["GET", "/"])?.inc();
+        requests.with_label_values(&["GET", "/"])?.inc();
+        requests.with_label_values(&["POST", "/login"])?.inc();
+        assert!(requests.with_label_values(&["GET"]).is_err());
+
+        // the same family is returned by name
+        let again = registry.counter_vec("requests", &["method", "path"])?;
+        assert_eq!(again.series(), 2);
+        assert!(registry.counter_vec("requests", &["method"]).is_err());
+
+        let key = MetricKey::new("requests", &[("method", "GET"), ("path", "/")]);
+        assert_eq!(
+            registry.snapshot().metrics[&key],
+            MetricSnapshot::Counter(2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cardinality_limit() -> Result<()> {
+        let registry = Registry::new();
+        let users = registry
+            .gauge_vec("sessions", &["user"])?
+            .with_max_series(2);
+        users.with_label_values(&["alice"])?.set(1.0);
+        users.with_label_values(&["bob"])?.set(1.0);
+        assert!(users.with_label_values(&["carol"]).is_err());
+        // existing series are still reachable
+        users.with_label_values(&["alice"])?.inc();
+        assert_eq!(users.series(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_histogram_vec() -> Result<()> {
+        let registry = Registry::new();
+        let latency = registry.histogram_vec("latency", &["route"], &[0.1, 1.0])?;
+        latency.with_label_values(&["/"])?.observe(0.5);
+        assert_eq!(latency.with_label_values(&["/"])?.bounds(), &[0.1, 1.0]);
+        assert!(registry.histogram_vec("bad", &["route"], &[]).is_err());
+        // the same buckets return the family, different ones are refused
+        registry.histogram_vec("latency", &["route"], &[0.1, 1.0])?;
+        assert!(
+            registry
+                .histogram_vec("latency", &["route"], &[0.5, 1.0])
+                .is_err()
+        );
+        let key = MetricKey::new("latency", &[("route", "/login")]);
+        assert!(registry.histogram_with_buckets(key, &[0.5]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_labelled_keys_leave_no_family() -> Result<()> {
+        let registry = Registry::new();
+        let key = |labels: &[(&str, &str)]| MetricKey::new("latency", labels);
+        assert!(
+            registry
+                .histogram_with_buckets(key(&[("route", "/")]), &[])
+                .is_err()
+        );
+        // neither the labels nor the buckets of the failed call stuck
+        registry
+            .histogram_with_buckets(key(&[("host", "a")]), &[1.0])?
+            .observe(0.5);
+        assert!(registry.counter("latency").is_err());
+
+        let limited = registry
+            .counter_vec("logins", &["user"])?
+            .with_max_series(1);
+        limited.with_label_values(&["alice"])?.inc();
+        let bob = MetricKey::new("logins", &[("user", "bob")]);
+        assert!(registry.counter(bob.clone()).is_err());
+        assert!(registry.counter(bob).is_err());
+        assert_eq!(limited.series(), 1);
+
+        registry.histogram_vec("size", &["route"], &[0.1])?;
+        let route = MetricKey::new("size", &[("route", "/")]);
+        assert!(
+            registry
+                .histogram_with_buckets(route.clone(), &[0.5])
+                .is_err()
+        );
+        registry
+            .histogram_with_buckets(route, &[0.1])?
+            .observe(0.05);
+        Ok(())
+    }
+
+    #[test]
+    fn test_labelled_keys_share_the_family_limit() -> Result<()> {
+        let registry = Registry::new();
+        let users = registry
+            .counter_vec("logins", &["user"])?
+            .with_max_series(2);
+        users.with_label_values(&["alice"])?.inc();
+        registry.counter(MetricKey::new("logins", &[("user", "bob")]))?;
+        assert_eq!(users.series(), 2);
+        assert!(
+            registry
+                .counter(MetricKey::new("logins", &[("user", "carol")]))
+                .is_err()
+        );
+        // existing series stay reachable by key
+        registry.counter(MetricKey::new("logins", &[("user", "bob")]))?;
+        // the refused series left no reservation behind
+        assert_eq!(users.series(), 2);
+        assert!(
+            registry
+                .counter(MetricKey::new("logins", &[("host", "a")]))
+                .is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// a value that can go up and down, the f64 is stored as its bit pattern
+#[derive(Debug, Clone)]
+pub struct Gauge {
+    bits: Arc<AtomicU64>,
+}
+
+impl Gauge {
+    pub fn new() -> Self {
+        Self {
+            bits: Arc::new(AtomicU64::new(0f64.to_bits())),
+        }
+    }
+
+    pub fn set(&self, value: f64) {
+        self.bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    pub fn inc(&self) {
+        self.add(1.0);
+    }
+
+    pub fn dec(&self) {
+        self.add(-1.0);
+    }
+
+    pub fn add(&self, delta: f64) {
+        atomic_add_f64(&self.bits, delta);
+    }
+
+    pub fn sub(&self, delta: f64) {
+        self.add(-delta);
+    }
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn atomic_add_f64(bits: &AtomicU64, delta: f64) {
+    // the closure always returns Some, so fetch_update can't fail
+    let _ = bits.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+        Some((f64::from_bits(old) + delta).to_bits())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_gauge_set_and_add() {
+        let gauge = Gauge::new();
+        gauge.set(1.5);
+        gauge.add(2.0);
+        gauge.dec();
+        assert_eq!(gauge.get(), 2.5);
+    }
+
+    #[test]
+    fn test_gauge_concurrent_add() {
+        let gauge = Gauge::new();
+        let handles = (0..4)
+            .map(|_| {
+                let gauge = gauge.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        gauge.inc();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(gauge.get(), 4000.0);
+    }
+}
@@ -0,0 +1,213 @@
+use super::Timer;
+use super::gauge::atomic_add_f64;
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// latency buckets in seconds, same as the prometheus client defaults
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug)]
+struct HistogramInner {
+    // upper bounds, sorted ascending; the +Inf bucket is implicit
+    bounds: Vec<f64>,
+    // one slot per bound plus one for +Inf, not cumulative
+    counts: Vec<AtomicU64>,
+    sum: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    inner: Arc<HistogramInner>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    pub bounds: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Result<Self> {
+        validate_bounds(bounds)?;
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Ok(Self {
+            inner: Arc::new(HistogramInner {
+                bounds: bounds.to_vec(),
+                counts,
+                sum: AtomicU64::new(0f64.to_bits()),
+            }),
+        })
+    }
+
+    pub fn observe(&self, value: f64) {
+        let idx = bucket_index(&self.inner.bounds, value);
+        self.inner.counts[idx].fetch_add(1, Ordering::Relaxed);
+        atomic_add_f64(&self.inner.sum, value);
+    }
+
+    pub fn start_timer(&self) -> Timer {
+        Timer::new(self.clone())
+    }
+
+    pub fn bounds(&self) -> &[f64] {
+        &self.inner.bounds
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let counts = self
+            .inner
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        // derive count from the buckets so the snapshot always adds up
+        let count = counts.iter().sum();
+        HistogramSnapshot {
+            bounds: self.inner.bounds.clone(),
+            counts,
+            count,
+            sum: f64::from_bits(self.inner.sum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+pub(crate) fn validate_bounds(bounds: &[f64]) -> Result<()> {
+    if bounds.is_empty() {
+        return Err(anyhow!("histogram needs at least one bucket"));
+    }
+    if bounds.iter().any(|b| !b.is_finite()) {
+        return Err(anyhow!("histogram buckets must be finite"));
+    }
+    if bounds.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(anyhow!("histogram buckets must be strictly increasing"));
+    }
+    Ok(())
+}
+
+// a value falls into the first bucket whose upper bound is >= value
+pub(crate) fn bucket_index(bounds: &[f64], value: f64) -> usize {
+    bounds.partition_point(|b| *b < value)
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS).expect("default buckets are valid")
+    }
+}
+
+impl HistogramSnapshot {
+    // (upper bound, cumulative count) pairs, ending with the +Inf bucket
+    pub fn cumulative(&self) -> Vec<(f64, u64)> {
+        let mut acc = 0;
+        self.bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(self.counts.iter())
+            .map(|(bound, count)| {
+                acc += count;
+                (bound, acc)
+            })
+            .collect()
+    }
+
+    // observations made since `previous`; a snapshot with other buckets is
+    // not comparable, so the whole of `self` counts as new
+    pub fn delta(&self, previous: &HistogramSnapshot) -> HistogramSnapshot {
+        if self.bounds != previous.bounds {
+            return self.clone();
+        }
+        let counts = self
+            .counts
+            .iter()
+            .zip(&previous.counts)
+            .map(|(now, before)| now.saturating_sub(*before))
+            .collect();
+        HistogramSnapshot {
+            bounds: self.bounds.clone(),
+            counts,
+            count: self.count.saturating_sub(previous.count),
+            sum: self.sum - previous.sum,
+        }
+    }
+
+    // estimate the q-quantile (0 <= q <= 1) by linear interpolation inside
+    // the bucket holding the rank, the way prometheus' histogram_quantile does
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let rank = q * self.count as f64;
+        let cumulative = self.cumulative();
+        let idx = cumulative
+            .iter()
+            .position(|(_, count)| *count as f64 >= rank)
+            .unwrap_or(cumulative.len() - 1);
+
+        // the +Inf bucket has no upper bound, report the highest finite one
+        if idx == self.bounds.len() {
+            return self.bounds.last().copied();
+        }
+        let upper = self.bounds[idx];
+        let (lower, below) = match idx {
+            0 => (upper.min(0.0), 0),
+            _ => (self.bounds[idx - 1], cumulative[idx - 1].1),
+        };
+        let in_bucket = self.counts[idx];
+        if in_bucket == 0 {
+            return Some(upper);
+        }
+        Some(lower + (upper - lower) * (rank - below as f64) / in_bucket as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_observe() -> Result<()> {
+        let histogram = Histogram::new(&[1.0, 2.0, 5.0])?;
+        for value in [0.5, 1.0, 1.5, 3.0, 10.0] {
+            histogram.observe(value);
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.counts, vec![2, 1, 1, 1]);
+        assert_eq!(snapshot.count, 5);
+        assert_eq!(snapshot.sum, 16.0);
+        assert_eq!(
+            snapshot.cumulative(),
+            vec![(1.0, 2), (2.0, 3), (5.0, 4), (f64::INFINITY, 5)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_histogram_quantile() -> Result<()> {
+        let histogram = Histogram::new(&[1.0, 2.0, 4.0])?;
+        assert_eq!(histogram.snapshot().quantile(0.5), None);
+        for value in [0.5, 1.5, 1.5, 3.0] {
+            histogram.observe(value);
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.quantile(0.25), Some(1.0));
+        assert_eq!(snapshot.quantile(0.5), Some(1.5));
+        assert_eq!(snapshot.quantile(1.0), Some(4.0));
+
+        histogram.observe(100.0);
+        assert_eq!(histogram.snapshot().quantile(1.0), Some(4.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_histogram_invalid_buckets() {
+        assert!(Histogram::new(&[]).is_err());
+        assert!(Histogram::new(&[2.0, 1.0]).is_err());
+        assert!(Histogram::new(&[1.0, f64::NAN]).is_err());
+    }
+}
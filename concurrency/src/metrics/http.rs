@@ -0,0 +1,76 @@
+use super::Registry;
+use axum::Router;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::routing::get;
+
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// a router serving `GET /metrics`, merge it into an existing app:
+// `app.merge(metrics_router(registry))`
+pub fn metrics_router(registry: Registry) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(registry)
+}
+
+async fn metrics_handler(
+    State(registry): State<Registry>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let snapshot = registry.snapshot();
+    let wants_openmetrics = headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/openmetrics-text"));
+
+    if wants_openmetrics {
+        (
+            [(CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+            snapshot.to_openmetrics(),
+        )
+    } else {
+        (
+            [(CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+            snapshot.to_prometheus(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::body::to_bytes;
+    use axum::http::HeaderValue;
+
+    #[tokio::test]
+    async fn test_metrics_handler_negotiates_format() -> Result<()> {
+        let registry = Registry::new();
+        registry.counter("requests")?.inc();
+
+        let res = metrics_handler(State(registry.clone()), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(res.headers()[CONTENT_TYPE], PROMETHEUS_CONTENT_TYPE);
+        let body = to_bytes(res.into_body(), usize::MAX).await?;
+        assert_eq!(&body[..], b"# TYPE requests counter\nrequests 1\n");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/openmetrics-text; version=1.0.0"),
+        );
+        let res = metrics_handler(State(registry), headers)
+            .await
+            .into_response();
+        assert_eq!(res.headers()[CONTENT_TYPE], OPENMETRICS_CONTENT_TYPE);
+        let body = to_bytes(res.into_body(), usize::MAX).await?;
+        assert!(body.ends_with(b"requests_total 1\n# EOF\n"));
+        Ok(())
+    }
+}
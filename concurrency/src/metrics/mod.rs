@@ -1,5 +1,28 @@
 mod atomic_map;
 mod concurrency_map;
+mod counter;
+mod exposition;
+mod family;
+mod gauge;
+mod histogram;
+mod http;
+mod registry;
+mod reporter;
+mod sinks;
+mod timer;
+mod windowed;
 
 pub use atomic_map::AtomicMetrics;
 pub use concurrency_map::ConcurrencyMetrics;
+pub use counter::Counter;
+pub use family::{
+    CounterVec, DEFAULT_MAX_SERIES, GaugeVec, HistogramVec, MetricVec, RegistryMetric,
+};
+pub use gauge::Gauge;
+pub use histogram::{DEFAULT_BUCKETS, Histogram, HistogramSnapshot};
+pub use http::{OPENMETRICS_CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE, metrics_router};
+pub use registry::{Metric, MetricKey, MetricKind, MetricSnapshot, Registry, Snapshot};
+pub use reporter::{Report, Reporter, Sink};
+pub use sinks::{JsonLinesSink, MemorySink, StatsdSink, StdoutSink};
+pub use timer::Timer;
+pub use windowed::{WindowedCounter, WindowedHistogram};
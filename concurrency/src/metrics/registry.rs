@@ -0,0 +1,536 @@
+use super::exposition::sanitize_name;
+use super::family::{CounterVec, Family, GaugeVec, HistogramVec, MetricVec, RegistryMetric};
+use super::{Counter, DEFAULT_BUCKETS, Gauge, Histogram, HistogramSnapshot, Timer};
+use anyhow::{Result, anyhow};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+// a metric name plus its label pairs, labels are kept sorted by name
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MetricKey {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricSnapshot {
+    Counter(u64),
+    Gauge(f64),
+    Histogram(HistogramSnapshot),
+}
+
+// owns every named metric; handles returned from it are shared, so cloning
+// the registry or a handle never forks the underlying value
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    data: Arc<DashMap<MetricKey, Metric>>,
+    // every series of an exported metric name must share the same kind,
+    // kept with the name first registered under it
+    kinds: Arc<DashMap<String, (MetricKind, String)>>,
+    help: Arc<DashMap<String, String>>,
+    families: Arc<DashMap<String, Arc<Family>>>,
+}
+
+// a point-in-time copy of the registry, ordered by metric name then labels
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub metrics: BTreeMap<MetricKey, MetricSnapshot>,
+    pub help: BTreeMap<String, String>,
+}
+
+impl MetricKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl MetricKey {
+    pub fn new(name: impl Into<String>, labels: &[(&str, &str)]) -> Self {
+        let mut labels = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        labels.sort();
+        Self {
+            name: name.into(),
+            labels,
+        }
+    }
+}
+
+impl From<&str> for MetricKey {
+    fn from(name: &str) -> Self {
+        Self::new(name, &[])
+    }
+}
+
+impl From<String> for MetricKey {
+    fn from(name: String) -> Self {
+        Self::new(name, &[])
+    }
+}
+
+impl fmt::Display for MetricKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.labels.is_empty() {
+            let labels = self
+                .labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, v))
+                .collect::<Vec<_>>();
+            write!(f, "{{{}}}", labels.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Metric {
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            Metric::Counter(_) => MetricKind::Counter,
+            Metric::Gauge(_) => MetricKind::Gauge,
+            Metric::Histogram(_) => MetricKind::Histogram,
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricSnapshot {
+        match self {
+            Metric::Counter(c) => MetricSnapshot::Counter(c.get()),
+            Metric::Gauge(g) => MetricSnapshot::Gauge(g.get()),
+            Metric::Histogram(h) => MetricSnapshot::Histogram(h.snapshot()),
+        }
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // attach a description, rendered as `# HELP` by the exposition formats
+    pub fn describe(&self, name: impl Into<String>, help: impl Into<String>) {
+        self.help.insert(name.into(), help.into());
+    }
+
+    pub fn counter(&self, key: impl Into<MetricKey>) -> Result<Counter> {
+        self.register_key(key.into(), None, || Ok(Metric::Counter(Counter::new())))
+    }
+
+    pub fn gauge(&self, key: impl Into<MetricKey>) -> Result<Gauge> {
+        self.register_key(key.into(), None, || Ok(Metric::Gauge(Gauge::new())))
+    }
+
+    pub fn histogram(&self, key: impl Into<MetricKey>) -> Result<Histogram> {
+        self.histogram_with_buckets(key, DEFAULT_BUCKETS)
+    }
+
+    pub fn histogram_with_buckets(
+        &self,
+        key: impl Into<MetricKey>,
+        buckets: &[f64],
+    ) -> Result<Histogram> {
+        let key = key.into();
+        let histogram: Histogram = self.register_key(key.clone(), Some(buckets), || {
+            Ok(Metric::Histogram(Histogram::new(buckets)?))
+        })?;
+        if histogram.bounds() != buckets {
+            return Err(anyhow!(
+                "histogram {} already registered with different buckets",
+                key
+            ));
+        }
+        Ok(histogram)
+    }
+
+    pub fn counter_vec(&self, name: impl Into<String>, label_names: &[&str]) -> Result<CounterVec> {
+        self.metric_vec(name.into(), label_names, None)
+    }
+
+    pub fn gauge_vec(&self, name: impl Into<String>, label_names: &[&str]) -> Result<GaugeVec> {
+        self.metric_vec(name.into(), label_names, None)
+    }
+
+    pub fn histogram_vec(
+        &self,
+        name: impl Into<String>,
+        label_names: &[&str],
+        buckets: &[f64],
+    ) -> Result<HistogramVec> {
+        // validate the buckets up front rather than on the first series
+        Histogram::new(buckets)?;
+        self.metric_vec(name.into(), label_names, Some(buckets))
+    }
+
+    pub fn timer(&self, key: impl Into<MetricKey>) -> Result<Timer> {
+        Ok(self.histogram(key)?.start_timer())
+    }
+
+    pub fn get(&self, key: impl Into<MetricKey>) -> Option<Metric> {
+        self.data
+            .get(&key.into())
+            .map(|entry| entry.value().clone())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let metrics = self
+            .data
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().snapshot()))
+            .collect();
+        let help = self
+            .help
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        Snapshot { metrics, help }
+    }
+
+    // get or create the series for `key`; a new series counts against the
+    // family's cardinality limit when it belongs to one
+    pub(crate) fn register<M: RegistryMetric>(
+        &self,
+        key: MetricKey,
+        family: Option<&Family>,
+        make: impl FnOnce() -> Result<Metric>,
+    ) -> Result<M> {
+        self.check_kind(&key.name, M::KIND)?;
+        let name = key.to_string();
+        let metric = match self.data.entry(key) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let metric = make()?;
+                if let Some(family) = family {
+                    family.reserve(&name)?;
+                }
+                // the name only claims its kind once a series exists
+                if let Err(e) = self.record_kind(&entry.key().name, M::KIND) {
+                    if let Some(family) = family {
+                        family.release();
+                    }
+                    return Err(e);
+                }
+                entry.insert(metric).clone()
+            }
+        };
+        let kind = metric.kind();
+        M::from_metric(metric).ok_or_else(|| kind_mismatch(&name, M::KIND, kind))
+    }
+
+    // `register` for a series named by key; a labelled one joins the family of
+    // its name, which is created on the fly and dropped again if the series
+    // can't be
+    fn register_key<M: RegistryMetric>(
+        &self,
+        key: MetricKey,
+        buckets: Option<&[f64]>,
+        make: impl FnOnce() -> Result<Metric>,
+    ) -> Result<M> {
+        let Some((family, created)) = self.family_of(&key, M::KIND, buckets)? else {
+            return self.register(key, None, make);
+        };
+        let name = key.name.clone();
+        let ret = self.register(key, Some(&family), make);
+        if ret.is_err() && created && family.series() == 0 {
+            self.forget_family(&name, &family);
+        }
+        ret
+    }
+
+    fn metric_vec<M: RegistryMetric>(
+        &self,
+        name: String,
+        label_names: &[&str],
+        buckets: Option<&[f64]>,
+    ) -> Result<MetricVec<M>> {
+        self.check_kind(&name, M::KIND)?;
+        let mut created = false;
+        let family = self
+            .families
+            .entry(name.clone())
+            .or_insert_with(|| {
+                created = true;
+                Arc::new(Family::new(label_names, buckets))
+            })
+            .clone();
+        if family.label_names != label_names {
+            return Err(anyhow!(
+                "metric {} already registered with labels {:?}",
+                name,
+                family.label_names
+            ));
+        }
+        family.check_buckets(&name, buckets)?;
+        // another thread may have taken the name for another kind meanwhile
+        if let Err(e) = self.record_kind(&name, M::KIND) {
+            if created {
+                self.forget_family(&name, &family);
+            }
+            return Err(e);
+        }
+        Ok(MetricVec::new(self.clone(), name, family))
+    }
+
+    // a labelled series registered by key joins the family of its name, so it
+    // counts against the same cardinality limit as one made through a vec;
+    // also says whether the family was created here
+    fn family_of(
+        &self,
+        key: &MetricKey,
+        kind: MetricKind,
+        buckets: Option<&[f64]>,
+    ) -> Result<Option<(Arc<Family>, bool)>> {
+        if key.labels.is_empty() {
+            return Ok(None);
+        }
+        self.check_kind(&key.name, kind)?;
+        let label_names = key
+            .labels
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>();
+        let mut created = false;
+        let family = self
+            .families
+            .entry(key.name.clone())
+            .or_insert_with(|| {
+                created = true;
+                Arc::new(Family::new(&label_names, buckets))
+            })
+            .clone();
+        // keys sort their labels, families keep them in declaration order
+        let mut expected = family.label_names.clone();
+        expected.sort();
+        if expected != label_names {
+            return Err(anyhow!(
+                "metric {} already registered with labels {:?}",
+                key.name,
+                family.label_names
+            ));
+        }
+        family.check_buckets(&key.name, buckets)?;
+        Ok(Some((family, created)))
+    }
+
+    fn forget_family(&self, name: &str, family: &Arc<Family>) {
+        self.families
+            .remove_if(name, |_, existing| Arc::ptr_eq(existing, family));
+    }
+
+    // kinds are kept by exported name, `a.b` and `a_b` are one family there
+    // and a family has a single TYPE
+    fn check_kind(&self, name: &str, kind: MetricKind) -> Result<()> {
+        match self.kinds.get(&sanitize_name(name)) {
+            Some(existing) => kind_conflict(name, kind, &existing),
+            None => Ok(()),
+        }
+    }
+
+    // claim the kind, only called once the metric or family exists
+    fn record_kind(&self, name: &str, kind: MetricKind) -> Result<()> {
+        let existing = self
+            .kinds
+            .entry(sanitize_name(name))
+            .or_insert_with(|| (kind, name.to_string()));
+        kind_conflict(name, kind, &existing)
+    }
+}
+
+fn kind_conflict(name: &str, kind: MetricKind, existing: &(MetricKind, String)) -> Result<()> {
+    let (existing, first) = existing;
+    if *existing == kind {
+        return Ok(());
+    }
+    if first != name {
+        return Err(anyhow!(
+            "metric {} is exported as {}, which is already the {} {}",
+            name,
+            sanitize_name(name),
+            existing,
+            first
+        ));
+    }
+    Err(kind_mismatch(name, kind, *existing))
+}
+
+fn kind_mismatch(name: impl fmt::Display, wanted: MetricKind, found: MetricKind) -> anyhow::Error {
+    anyhow!("metric {} is a {}, not a {}", name, found, wanted)
+}
+
+impl Snapshot {
+    // what changed since `previous`: counters and histograms hold the increase
+    // and are left out when nothing happened, gauges keep their current value
+    pub fn delta(&self, previous: &Snapshot) -> Snapshot {
+        let metrics = self
+            .metrics
+            .iter()
+            .filter_map(|(key, now)| {
+                let delta = match (now, previous.metrics.get(key)) {
+                    (MetricSnapshot::Counter(v), Some(MetricSnapshot::Counter(before))) => {
+                        MetricSnapshot::Counter(v.saturating_sub(*before))
+                    }
+                    (MetricSnapshot::Histogram(h), Some(MetricSnapshot::Histogram(before))) => {
+                        MetricSnapshot::Histogram(h.delta(before))
+                    }
+                    (now, _) => now.clone(),
+                };
+                match &delta {
+                    MetricSnapshot::Counter(0) => None,
+                    MetricSnapshot::Histogram(h) if h.count == 0 => None,
+                    _ => Some((key.clone(), delta)),
+                }
+            })
+            .collect();
+        Snapshot {
+            metrics,
+            help: self.help.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Registry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.snapshot())
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.metrics.iter() {
+            match value {
+                MetricSnapshot::Counter(v) => writeln!(f, "{}: {}", key, v)?,
+                MetricSnapshot::Gauge(v) => writeln!(f, "{}: {}", key, v)?,
+                MetricSnapshot::Histogram(h) => {
+                    writeln!(f, "{}.count: {}", key, h.count)?;
+                    writeln!(f, "{}.sum: {}", key, h.sum)?;
+                    for (bound, count) in h.cumulative() {
+                        writeln!(f, "{}.le.{}: {}", key, bound, count)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_shares_handles() -> Result<()> {
+        let registry = Registry::new();
+        registry.counter("req")?.inc();
+        registry.counter("req")?.inc_by(2);
+        registry.gauge("temp")?.set(36.6);
+        registry.histogram("latency")?.observe(0.2);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.metrics[&"req".into()], MetricSnapshot::Counter(3));
+        assert_eq!(
+            snapshot.metrics[&"temp".into()],
+            MetricSnapshot::Gauge(36.6)
+        );
+        match &snapshot.metrics[&"latency".into()] {
+            MetricSnapshot::Histogram(h) => assert_eq!(h.count, 1),
+            other => panic!("unexpected metric {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_rejects_conflicts() -> Result<()> {
+        let registry = Registry::new();
+        registry.counter("req")?;
+        assert!(registry.gauge("req").is_err());
+
+        // a failed registration doesn't claim the name
+        assert!(registry.histogram_with_buckets("x", &[]).is_err());
+        registry.counter("x")?.inc();
+        assert!(registry.gauge_vec("x", &["a"]).is_err());
+
+        registry.histogram_with_buckets("latency", &[1.0, 2.0])?;
+        assert!(
+            registry
+                .histogram_with_buckets("latency", &[1.0, 2.0])
+                .is_ok()
+        );
+        assert!(registry.histogram("latency").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_labels() -> Result<()> {
+        let registry = Registry::new();
+        let get = MetricKey::new("http_requests", &[("status", "200"), ("method", "GET")]);
+        let post = MetricKey::new("http_requests", &[("method", "POST"), ("status", "200")]);
+        registry.counter(get.clone())?.inc();
+        registry.counter(post)?.inc_by(2);
+        assert!(registry.gauge(get.clone()).is_err());
+
+        assert_eq!(
+            get.to_string(),
+            r#"http_requests{method="GET",status="200"}"#
+        );
+        assert_eq!(registry.snapshot().metrics.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_delta() -> Result<()> {
+        let registry = Registry::new();
+        registry.counter("req")?.inc_by(5);
+        registry.counter("idle")?.inc();
+        registry.gauge("temp")?.set(20.0);
+        registry
+            .histogram_with_buckets("latency", &[1.0])?
+            .observe(0.5);
+        let before = registry.snapshot();
+
+        registry.counter("req")?.inc_by(2);
+        registry
+            .histogram_with_buckets("latency", &[1.0])?
+            .observe(3.0);
+        let delta = registry.snapshot().delta(&before);
+
+        assert_eq!(delta.metrics[&"req".into()], MetricSnapshot::Counter(2));
+        assert_eq!(delta.metrics[&"temp".into()], MetricSnapshot::Gauge(20.0));
+        assert!(!delta.metrics.contains_key(&"idle".into()));
+        match &delta.metrics[&"latency".into()] {
+            MetricSnapshot::Histogram(h) => {
+                assert_eq!((h.count, h.sum), (1, 3.0));
+                assert_eq!(h.counts, vec![0, 1]);
+            }
+            other => panic!("unexpected metric {:?}", other),
+        }
+        Ok(())
+    }
+}
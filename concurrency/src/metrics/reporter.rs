@@ -0,0 +1,225 @@
+use super::{Registry, Snapshot};
+use anyhow::{Result, anyhow};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+// what changed in the registry between two ticks of a reporter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub timestamp: SystemTime,
+    pub elapsed: Duration,
+    pub delta: Snapshot,
+}
+
+// a destination for reports; sinks are driven from a single task on tokio's
+// blocking pool, so they only need to be `Send`, may do blocking io and may
+// keep buffers that `flush` writes out
+pub trait Sink: Send {
+    fn report(&mut self, report: &Report) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// periodically snapshots a registry and pushes the deltas to its sinks;
+// the last report is sent on `shutdown` or when the reporter is dropped,
+// as long as the runtime running it is still up to do so
+pub struct Reporter {
+    stop: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<Result<()>>,
+}
+
+struct ReporterState {
+    registry: Registry,
+    sinks: Vec<Box<dyn Sink>>,
+    last: Snapshot,
+    last_at: Instant,
+    finished: bool,
+}
+
+impl Reporter {
+    // must be called from within a tokio runtime
+    pub fn spawn(registry: Registry, interval: Duration, sinks: Vec<Box<dyn Sink>>) -> Self {
+        let state = ReporterState {
+            registry,
+            sinks,
+            last: Snapshot::default(),
+            last_at: Instant::now(),
+            finished: false,
+        };
+        let (stop, stopped) = oneshot::channel();
+        let handle = tokio::spawn(run(state, interval, stopped));
+        Self {
+            stop: Some(stop),
+            handle,
+        }
+    }
+
+    // send a final report, flush every sink and wait for the task to finish
+    pub async fn shutdown(mut self) -> Result<()> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        (&mut self.handle)
+            .await
+            .map_err(|e| anyhow!("reporter task failed: {}", e))?
+    }
+}
+
+async fn run(
+    mut state: ReporterState,
+    interval: Duration,
+    mut stopped: oneshot::Receiver<()>,
+) -> Result<()> {
+    let start = tokio::time::Instant::now() + interval;
+    let mut ticker = tokio::time::interval_at(start, interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                state = blocking(move || {
+                    if let Err(e) = state.report() {
+                        warn!("metrics report failed: {:?}", e);
+                    }
+                    state
+                })
+                .await?;
+            }
+            // an explicit stop, or the reporter handle being dropped
+            _ = &mut stopped => break,
+        }
+    }
+    blocking(move || state.finish()).await?
+}
+
+// sinks write to files and sockets, keep that off the runtime's workers
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| anyhow!("metrics report task failed: {}", e))
+}
+
+impl ReporterState {
+    // every sink gets the report even if an earlier one fails
+    fn report(&mut self) -> Result<()> {
+        let now = self.registry.snapshot();
+        let report = Report {
+            timestamp: SystemTime::now(),
+            elapsed: self.last_at.elapsed(),
+            delta: now.delta(&self.last),
+        };
+        self.last = now;
+        self.last_at = Instant::now();
+
+        let mut result = Ok(());
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.report(&report) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        let mut result = self.report();
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.flush() {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+// the runtime drops the task without polling it again when it shuts down;
+// the final report would block whichever thread drops it, so it is lost
+impl Drop for ReporterState {
+    fn drop(&mut self) {
+        if !self.finished {
+            warn!("metrics reporter stopped with its runtime, the last report is lost");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{MemorySink, MetricSnapshot};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reporter_sends_deltas() -> Result<()> {
+        let registry = Registry::new();
+        let sink = MemorySink::new();
+        let reporter = Reporter::spawn(
+            registry.clone(),
+            Duration::from_secs(10),
+            vec![Box::new(sink.clone())],
+        );
+
+        let counter = registry.counter("req")?;
+        counter.inc_by(3);
+        tokio::time::sleep(Duration::from_secs(15)).await;
+        counter.inc();
+        reporter.shutdown().await?;
+
+        let reports = sink.reports();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(
+            reports[0].delta.metrics[&"req".into()],
+            MetricSnapshot::Counter(3)
+        );
+        assert_eq!(
+            reports[1].delta.metrics[&"req".into()],
+            MetricSnapshot::Counter(1)
+        );
+        assert!(sink.flushed());
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dropped_reporter_sends_final_report() -> Result<()> {
+        let registry = Registry::new();
+        let sink = MemorySink::new();
+        let reporter = Reporter::spawn(
+            registry.clone(),
+            Duration::from_secs(3600),
+            vec![Box::new(sink.clone())],
+        );
+        registry.counter("req")?.inc();
+        drop(reporter);
+        while !sink.flushed() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(sink.reports().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_runtime_shutdown_does_no_io_on_drop() -> Result<()> {
+        let registry = Registry::new();
+        let sink = MemorySink::new();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let reporter = runtime.block_on(async {
+            Reporter::spawn(
+                registry.clone(),
+                Duration::from_secs(3600),
+                vec![Box::new(sink.clone())],
+            )
+        });
+        registry.counter("req")?.inc();
+        drop(runtime);
+
+        // without `shutdown` the last report goes down with the runtime
+        assert!(sink.reports().is_empty());
+        assert!(!sink.flushed());
+        drop(reporter);
+        Ok(())
+    }
+}
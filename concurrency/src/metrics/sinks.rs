@@ -0,0 +1,360 @@
+use super::{MetricKey, MetricSnapshot, Report, Sink};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+// stay under a typical ethernet mtu so packets are not fragmented
+const STATSD_MAX_PACKET: usize = 1432;
+
+// prints every report in the same `key: value` form as `Registry`'s Display
+#[derive(Debug, Default)]
+pub struct StdoutSink;
+
+// appends one json object per report to a file
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    writer: BufWriter<File>,
+}
+
+// sends reports as statsd lines over udp, labels become dogstatsd tags
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+// keeps every report in memory, clones share the same reports
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    reports: Arc<Mutex<Vec<Report>>>,
+    flushed: Arc<AtomicBool>,
+}
+
+impl StdoutSink {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Sink for StdoutSink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(
+            stdout,
+            "# {} metrics over {:.1}s",
+            report.delta.metrics.len(),
+            report.elapsed.as_secs_f64()
+        )?;
+        write!(stdout, "{}", report.delta)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
+
+impl JsonLinesSink {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+impl Sink for JsonLinesSink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        let timestamp = report.timestamp.duration_since(UNIX_EPOCH)?.as_secs_f64();
+        let metrics = report
+            .delta
+            .metrics
+            .iter()
+            .map(|(key, value)| metric_json(key, value))
+            .collect::<Vec<_>>();
+        let line = json!({
+            "timestamp": timestamp,
+            "elapsed": report.elapsed.as_secs_f64(),
+            "metrics": metrics,
+        });
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn metric_json(key: &MetricKey, value: &MetricSnapshot) -> Value {
+    let labels = key
+        .labels
+        .iter()
+        .map(|(k, v)| (k.clone(), Value::from(v.as_str())))
+        .collect::<serde_json::Map<_, _>>();
+    let mut metric = match value {
+        MetricSnapshot::Counter(v) => json!({ "type": "counter", "value": v }),
+        MetricSnapshot::Gauge(v) => json!({ "type": "gauge", "value": v }),
+        MetricSnapshot::Histogram(h) => json!({
+            "type": "histogram",
+            "count": h.count,
+            "sum": h.sum,
+            "bounds": h.bounds,
+            "counts": h.counts,
+        }),
+    };
+    metric["name"] = Value::from(key.name.as_str());
+    metric["labels"] = Value::Object(labels);
+    metric
+}
+
+impl StatsdSink {
+    // `prefix` is prepended to every metric name, e.g. "myapp."
+    pub fn new(addr: impl ToSocketAddrs, prefix: impl Into<String>) -> Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("no address to send statsd metrics to"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.into(),
+        })
+    }
+
+    // statsd sums `|c` values itself, so counters and histograms must be the
+    // per-flush increase: `report.delta`, never a cumulative snapshot
+    fn lines(&self, report: &Report) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (key, value) in report.delta.metrics.iter() {
+            let name = format!("{}{}", self.prefix, statsd_escape(&key.name));
+            let tags = statsd_tags(key);
+            match value {
+                MetricSnapshot::Counter(v) => lines.push(format!("{}:{}|c{}", name, v, tags)),
+                // a signed value adjusts a statsd gauge, so a negative one is
+                // sent as a reset to 0 first, kept in the same datagram
+                MetricSnapshot::Gauge(v) if *v < 0.0 => {
+                    lines.push(format!("{}:0|g{}\n{}:{}|g{}", name, tags, name, v, tags))
+                }
+                MetricSnapshot::Gauge(v) => lines.push(format!("{}:{}|g{}", name, v, tags)),
+                MetricSnapshot::Histogram(h) => {
+                    lines.push(format!("{}.count:{}|c{}", name, h.count, tags));
+                    lines.push(format!("{}.sum:{}|c{}", name, h.sum, tags));
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl Sink for StatsdSink {
+    // lines are packed into as few datagrams as fit under the mtu
+    fn report(&mut self, report: &Report) -> Result<()> {
+        let mut packet = String::new();
+        for line in self.lines(report) {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > STATSD_MAX_PACKET {
+                self.socket.send(packet.as_bytes())?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        if !packet.is_empty() {
+            self.socket.send(packet.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn statsd_tags(key: &MetricKey) -> String {
+    if key.labels.is_empty() {
+        return String::new();
+    }
+    let tags = key
+        .labels
+        .iter()
+        .map(|(k, v)| format!("{}:{}", statsd_escape(k), statsd_escape(v)))
+        .collect::<Vec<_>>();
+    format!("|#{}", tags.join(","))
+}
+
+// `:`, `|`, `@`, `,` and `#` are separators in the line protocol
+fn statsd_escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ':' | '|' | '@' | ',' | '#' | '\n' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reports(&self) -> Vec<Report> {
+        self.reports
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn flushed(&self) -> bool {
+        self.flushed.load(Ordering::Acquire)
+    }
+}
+
+impl Sink for MemorySink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        self.reports
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(report.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flushed.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Registry;
+    use std::time::{Duration, SystemTime};
+
+    fn sample_report() -> Result<Report> {
+        let registry = Registry::new();
+        registry
+            .counter(MetricKey::new("requests", &[("status", "200")]))?
+            .inc_by(4);
+        registry.gauge("queue.depth")?.set(2.5);
+        registry
+            .histogram_with_buckets("latency", &[0.1, 1.0])?
+            .observe(0.5);
+        Ok(Report {
+            timestamp: SystemTime::now(),
+            elapsed: Duration::from_secs(10),
+            delta: registry.snapshot(),
+        })
+    }
+
+    #[test]
+    fn test_statsd_sink() -> Result<()> {
+        let listener = UdpSocket::bind("127.0.0.1:0")?;
+        listener.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut sink = StatsdSink::new(listener.local_addr()?, "app.")?;
+        sink.report(&sample_report()?)?;
+
+        let mut buf = [0u8; STATSD_MAX_PACKET];
+        let n = listener.recv(&mut buf)?;
+        let packet = std::str::from_utf8(&buf[..n])?;
+        assert_eq!(
+            packet.lines().collect::<Vec<_>>(),
+            vec![
+                "app.latency.count:1|c",
+                "app.latency.sum:0.5|c",
+                "app.queue.depth:2.5|g",
+                "app.requests:4|c|#status:200",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_statsd_counts_are_per_flush() -> Result<()> {
+        let listener = UdpSocket::bind("127.0.0.1:0")?;
+        let sink = StatsdSink::new(listener.local_addr()?, "")?;
+        let registry = Registry::new();
+        let latency = registry.histogram_with_buckets("latency", &[1.0])?;
+        let requests = registry.counter("requests")?;
+        let mut last = registry.snapshot();
+        let mut flush = || {
+            let now = registry.snapshot();
+            let report = Report {
+                timestamp: SystemTime::now(),
+                elapsed: Duration::from_secs(10),
+                delta: now.delta(&last),
+            };
+            last = now;
+            sink.lines(&report)
+        };
+
+        latency.observe(0.5);
+        requests.inc_by(3);
+        let first = vec!["latency.count:1|c", "latency.sum:0.5|c", "requests:3|c"];
+        assert_eq!(flush(), first);
+        latency.observe(0.25);
+        requests.inc();
+        let second = vec!["latency.count:1|c", "latency.sum:0.25|c", "requests:1|c"];
+        assert_eq!(flush(), second);
+        assert!(flush().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_statsd_negative_gauge() -> Result<()> {
+        let listener = UdpSocket::bind("127.0.0.1:0")?;
+        let sink = StatsdSink::new(listener.local_addr()?, "")?;
+        let registry = Registry::new();
+        registry
+            .gauge(MetricKey::new("balance", &[("account", "a")]))?
+            .set(-3.5);
+        registry.gauge("temp")?.set(0.0);
+        let report = Report {
+            timestamp: SystemTime::now(),
+            elapsed: Duration::from_secs(10),
+            delta: registry.snapshot(),
+        };
+        assert_eq!(
+            sink.lines(&report),
+            vec![
+                "balance:0|g|#account:a\nbalance:-3.5|g|#account:a",
+                "temp:0|g"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines_sink() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("metrics.jsonl");
+        let mut sink = JsonLinesSink::create(&path)?;
+        sink.report(&sample_report()?)?;
+        sink.report(&sample_report()?)?;
+        sink.flush()?;
+
+        let content = std::fs::read_to_string(&path)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let report: Value = serde_json::from_str(lines[0])?;
+        assert_eq!(report["elapsed"], 10.0);
+        let metrics = report["metrics"].as_array().expect("metrics array");
+        assert_eq!(metrics[0]["type"], "histogram");
+        assert_eq!(metrics[0]["counts"], json!([0, 1, 0]));
+        assert_eq!(metrics[2]["labels"]["status"], "200");
+        assert_eq!(metrics[2]["value"], 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use super::Histogram;
+use std::time::{Duration, Instant};
+
+// records the elapsed seconds into the histogram when dropped
+#[derive(Debug)]
+pub struct Timer {
+    histogram: Option<Histogram>,
+    start: Instant,
+}
+
+impl Timer {
+    pub fn new(histogram: Histogram) -> Self {
+        Self {
+            histogram: Some(histogram),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // record now and return the elapsed time
+    pub fn stop(mut self) -> Duration {
+        self.record()
+    }
+
+    // drop the timer without recording anything
+    pub fn discard(mut self) {
+        self.histogram.take();
+    }
+
+    fn record(&mut self) -> Duration {
+        let elapsed = self.start.elapsed();
+        if let Some(histogram) = self.histogram.take() {
+            histogram.observe(elapsed.as_secs_f64());
+        }
+        elapsed
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_records_once() {
+        let histogram = Histogram::default();
+        {
+            let _timer = histogram.start_timer();
+        }
+        let timer = histogram.start_timer();
+        timer.stop();
+        histogram.start_timer().discard();
+
+        assert_eq!(histogram.snapshot().count, 2);
+    }
+}
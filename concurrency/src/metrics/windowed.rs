@@ -0,0 +1,228 @@
+use super::HistogramSnapshot;
+use super::histogram::{bucket_index, validate_bounds};
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// a ring of per-second slots, each tagged with the second it belongs to so
+// stale slots are recycled lazily instead of by a background ticker
+#[derive(Debug)]
+struct Ring<T> {
+    slots: Vec<(u64, T)>,
+    empty: T,
+}
+
+// counts events over the last `capacity` seconds, e.g. requests/sec over 60s
+#[derive(Debug, Clone)]
+pub struct WindowedCounter {
+    ring: Arc<Mutex<Ring<u64>>>,
+}
+
+#[derive(Debug, Clone)]
+struct HistogramSlot {
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+// a histogram over the last `capacity` seconds, e.g. p99 latency over 5 min
+#[derive(Debug, Clone)]
+pub struct WindowedHistogram {
+    bounds: Arc<Vec<f64>>,
+    ring: Arc<Mutex<Ring<HistogramSlot>>>,
+}
+
+impl<T: Clone> Ring<T> {
+    fn new(capacity: Duration, empty: T) -> Result<Self> {
+        let secs = capacity.as_secs();
+        if secs == 0 {
+            return Err(anyhow!("window capacity must be at least one second"));
+        }
+        Ok(Self {
+            slots: vec![(u64::MAX, empty.clone()); secs as usize],
+            empty,
+        })
+    }
+
+    fn slot_mut(&mut self, now: u64) -> &mut T {
+        let len = self.slots.len() as u64;
+        let slot = &mut self.slots[(now % len) as usize];
+        if slot.0 != now {
+            *slot = (now, self.empty.clone());
+        }
+        &mut slot.1
+    }
+
+    // slots within (now - window, now], a window larger than the ring is clamped
+    fn window(&self, window: Duration, now: u64) -> impl Iterator<Item = &T> {
+        let secs = window.as_secs().clamp(1, self.slots.len() as u64);
+        let start = now.saturating_sub(secs - 1);
+        self.slots
+            .iter()
+            .filter(move |(sec, _)| (start..=now).contains(sec))
+            .map(|(_, v)| v)
+    }
+
+    fn window_secs(&self, window: Duration) -> u64 {
+        window.as_secs().clamp(1, self.slots.len() as u64)
+    }
+}
+
+impl WindowedCounter {
+    pub fn new(capacity: Duration) -> Result<Self> {
+        Ok(Self {
+            ring: Arc::new(Mutex::new(Ring::new(capacity, 0)?)),
+        })
+    }
+
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        self.inc_by_at(n, now_secs());
+    }
+
+    pub fn sum(&self, window: Duration) -> u64 {
+        self.sum_at(window, now_secs())
+    }
+
+    // average events per second over the window
+    pub fn rate(&self, window: Duration) -> f64 {
+        self.rate_at(window, now_secs())
+    }
+
+    fn inc_by_at(&self, n: u64, now: u64) {
+        let mut ring = self.ring.lock().expect("windowed counter lock poisoned");
+        *ring.slot_mut(now) += n;
+    }
+
+    fn sum_at(&self, window: Duration, now: u64) -> u64 {
+        let ring = self.ring.lock().expect("windowed counter lock poisoned");
+        ring.window(window, now).sum()
+    }
+
+    fn rate_at(&self, window: Duration, now: u64) -> f64 {
+        let ring = self.ring.lock().expect("windowed counter lock poisoned");
+        let sum: u64 = ring.window(window, now).sum();
+        sum as f64 / ring.window_secs(window) as f64
+    }
+}
+
+impl WindowedHistogram {
+    pub fn new(bounds: &[f64], capacity: Duration) -> Result<Self> {
+        validate_bounds(bounds)?;
+        let empty = HistogramSlot {
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+        };
+        Ok(Self {
+            bounds: Arc::new(bounds.to_vec()),
+            ring: Arc::new(Mutex::new(Ring::new(capacity, empty)?)),
+        })
+    }
+
+    pub fn observe(&self, value: f64) {
+        self.observe_at(value, now_secs());
+    }
+
+    // merge the slots of the window into one snapshot
+    pub fn snapshot(&self, window: Duration) -> HistogramSnapshot {
+        self.snapshot_at(window, now_secs())
+    }
+
+    pub fn quantile(&self, q: f64, window: Duration) -> Option<f64> {
+        self.snapshot(window).quantile(q)
+    }
+
+    // average observations per second over the window
+    pub fn rate(&self, window: Duration) -> f64 {
+        let secs = {
+            let ring = self.ring.lock().expect("windowed histogram lock poisoned");
+            ring.window_secs(window)
+        };
+        self.snapshot(window).count as f64 / secs as f64
+    }
+
+    fn observe_at(&self, value: f64, now: u64) {
+        let idx = bucket_index(&self.bounds, value);
+        let mut ring = self.ring.lock().expect("windowed histogram lock poisoned");
+        let slot = ring.slot_mut(now);
+        slot.counts[idx] += 1;
+        slot.sum += value;
+    }
+
+    fn snapshot_at(&self, window: Duration, now: u64) -> HistogramSnapshot {
+        let ring = self.ring.lock().expect("windowed histogram lock poisoned");
+        let mut counts = vec![0; self.bounds.len() + 1];
+        let mut sum = 0.0;
+        for slot in ring.window(window, now) {
+            for (total, count) in counts.iter_mut().zip(slot.counts.iter()) {
+                *total += count;
+            }
+            sum += slot.sum;
+        }
+        HistogramSnapshot {
+            bounds: self.bounds.to_vec(),
+            count: counts.iter().sum(),
+            counts,
+            sum,
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_windowed_counter_rate() -> Result<()> {
+        let counter = WindowedCounter::new(MINUTE)?;
+        for sec in 100..130 {
+            counter.inc_by_at(2, sec);
+        }
+        assert_eq!(counter.sum_at(Duration::from_secs(10), 129), 20);
+        assert_eq!(counter.rate_at(Duration::from_secs(10), 129), 2.0);
+        assert_eq!(counter.rate_at(MINUTE, 129), 1.0);
+        // slots older than the window are ignored, even before they are recycled
+        assert_eq!(counter.sum_at(MINUTE, 200), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_windowed_counter_recycles_slots() -> Result<()> {
+        let counter = WindowedCounter::new(Duration::from_secs(2))?;
+        counter.inc_by_at(5, 10);
+        counter.inc_by_at(1, 12);
+        assert_eq!(counter.sum_at(Duration::from_secs(2), 12), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_windowed_histogram_quantile() -> Result<()> {
+        let histogram = WindowedHistogram::new(&[0.1, 0.5, 1.0], Duration::from_secs(300))?;
+        // slow requests a while ago, fast ones recently
+        for _ in 0..10 {
+            histogram.observe_at(0.9, 1000);
+        }
+        for _ in 0..10 {
+            histogram.observe_at(0.05, 1200);
+        }
+        let recent = histogram.snapshot_at(MINUTE, 1200);
+        assert_eq!(recent.count, 10);
+        assert_eq!(recent.quantile(0.99), Some(0.099));
+
+        let five_minutes = histogram.snapshot_at(Duration::from_secs(300), 1200);
+        assert_eq!(five_minutes.count, 20);
+        assert!(five_minutes.quantile(0.99).unwrap() > 0.5);
+        Ok(())
+    }
+}
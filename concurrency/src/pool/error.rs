@@ -0,0 +1,38 @@
+use std::any::Any;
+use std::fmt;
+use std::fmt::Formatter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    // the task panicked, carries the panic message
+    Panicked(String),
+    // the task was dropped before it could run
+    Cancelled,
+    // the pool no longer accepts tasks
+    ShutDown,
+    // the submission queue is at capacity, only returned by `try_submit`
+    QueueFull,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Panicked(msg) => write!(f, "task panicked: {}", msg),
+            PoolError::Cancelled => write!(f, "task was cancelled"),
+            PoolError::ShutDown => write!(f, "thread pool is shut down"),
+            PoolError::QueueFull => write!(f, "thread pool queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
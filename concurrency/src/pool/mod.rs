@@ -0,0 +1,20 @@
+mod error;
+mod scope;
+mod thread_pool;
+
+pub use error::PoolError;
+pub use scope::Scope;
+pub use thread_pool::{JoinHandle, ThreadPool};
+
+use std::sync::OnceLock;
+use std::thread;
+
+static GLOBAL: OnceLock<ThreadPool> = OnceLock::new();
+
+// a process wide pool with one worker per available cpu
+pub fn global() -> &'static ThreadPool {
+    GLOBAL.get_or_init(|| {
+        let threads = thread::available_parallelism().map_or(4, |n| n.get());
+        ThreadPool::new(threads).expect("failed to start the global thread pool")
+    })
+}
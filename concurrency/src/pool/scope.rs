@@ -0,0 +1,99 @@
+use super::error::{PoolError, panic_message};
+use super::thread_pool::{Job, Shared};
+use std::marker::PhantomData;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// created by `ThreadPool::scope`, tasks spawned on it may borrow anything
+// that outlives the `scope` call
+pub struct Scope<'scope> {
+    shared: Arc<Shared>,
+    state: Arc<ScopeState>,
+    // invariant over 'scope, like std::thread::Scope
+    _marker: PhantomData<&'scope mut &'scope ()>,
+}
+
+struct ScopeState {
+    pending: AtomicUsize,
+    // the first failure of a spawned task
+    error: Mutex<Option<PoolError>>,
+    lock: Mutex<()>,
+    done: Condvar,
+}
+
+impl<'scope> Scope<'scope> {
+    pub(crate) fn new(shared: Arc<Shared>) -> Self {
+        Self {
+            shared,
+            state: Arc::new(ScopeState {
+                pending: AtomicUsize::new(0),
+                error: Mutex::new(None),
+                lock: Mutex::new(()),
+                done: Condvar::new(),
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce(&Scope<'scope>) + Send + 'scope,
+    {
+        self.state.pending.fetch_add(1, Ordering::SeqCst);
+        let shared = self.shared.clone();
+        let state = self.state.clone();
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            let scope = Scope {
+                shared,
+                state: state.clone(),
+                _marker: PhantomData,
+            };
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(|| f(&scope))) {
+                state.fail(PoolError::Panicked(panic_message(payload)));
+            }
+            state.complete();
+        });
+        // SAFETY: `ThreadPool::scope` doesn't return before `pending` drops
+        // back to zero, so whatever the job borrows for 'scope outlives it
+        let job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+
+        if let Err(e) = self.shared.push(job, true) {
+            // the job was dropped without running, account for it here
+            self.state.fail(e);
+            self.state.complete();
+        }
+    }
+
+    pub(crate) fn wait(&self) {
+        let state = &self.state;
+        self.shared.wait_until(
+            || state.pending.load(Ordering::SeqCst) == 0,
+            &state.lock,
+            &state.done,
+        );
+    }
+
+    pub(crate) fn into_result(self) -> Result<(), PoolError> {
+        match self.state.error.lock().expect("scope lock poisoned").take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ScopeState {
+    fn fail(&self, e: PoolError) {
+        self.error
+            .lock()
+            .expect("scope lock poisoned")
+            .get_or_insert(e);
+    }
+
+    fn complete(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            drop(self.lock.lock().expect("scope lock poisoned"));
+            self.done.notify_all();
+        }
+    }
+}
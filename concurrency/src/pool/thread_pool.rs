@@ -0,0 +1,476 @@
+use super::error::{PoolError, panic_message};
+use super::scope::Scope;
+use anyhow::{Result, anyhow};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use std::cell::RefCell;
+use std::iter;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// how many tasks per thread may wait in the submission queue by default
+const QUEUE_PER_THREAD: usize = 256;
+
+static NEXT_POOL_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static WORKER: RefCell<Option<WorkerContext>> = const { RefCell::new(None) };
+}
+
+struct WorkerContext {
+    pool_id: usize,
+    index: usize,
+    local: Worker<Task>,
+}
+
+pub(crate) struct Task {
+    job: Job,
+    // submitted from outside the pool, so it holds a slot of the bounded queue
+    counted: bool,
+}
+
+pub(crate) struct Shared {
+    id: usize,
+    // tasks from outside the pool land here, workers push to their own deque
+    injector: Injector<Task>,
+    stealers: Vec<Stealer<Task>>,
+    capacity: usize,
+    queued: Mutex<usize>,
+    not_full: Condvar,
+    sleep: Mutex<()>,
+    wake: Condvar,
+    shutdown: AtomicBool,
+}
+
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+pub struct JoinHandle<T> {
+    shared: Arc<Shared>,
+    rx: oneshot::Receiver<thread::Result<T>>,
+}
+
+impl Shared {
+    pub(crate) fn push(&self, job: Job, block: bool) -> Result<(), PoolError> {
+        if self.shutdown.load(Ordering::Acquire) {
+            return Err(PoolError::ShutDown);
+        }
+
+        // tasks spawned by a worker go to its own deque and skip the bound,
+        // blocking a worker on its own queue could deadlock the pool
+        let mut job = Some(job);
+        self.with_local(|local| {
+            if let Some(job) = job.take() {
+                local.push(Task {
+                    job,
+                    counted: false,
+                });
+            }
+        });
+        if let Some(job) = job {
+            self.acquire_slot(block)?;
+            self.injector.push(Task { job, counted: true });
+        }
+
+        drop(self.sleep.lock().expect("pool sleep lock poisoned"));
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    fn acquire_slot(&self, block: bool) -> Result<(), PoolError> {
+        let mut queued = self.queued.lock().expect("pool queue lock poisoned");
+        while *queued >= self.capacity {
+            if !block {
+                return Err(PoolError::QueueFull);
+            }
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(PoolError::ShutDown);
+            }
+            queued = self
+                .not_full
+                .wait(queued)
+                .expect("pool queue lock poisoned");
+        }
+        *queued += 1;
+        Ok(())
+    }
+
+    fn release_slot(&self) {
+        let mut queued = self.queued.lock().expect("pool queue lock poisoned");
+        *queued -= 1;
+        self.not_full.notify_one();
+    }
+
+    fn run(&self, task: Task) {
+        if task.counted {
+            self.release_slot();
+        }
+        // jobs report their own panics, this only keeps the worker alive
+        let _ = catch_unwind(AssertUnwindSafe(task.job));
+    }
+
+    // run `f` with the deque of the current thread if it is one of our workers
+    fn with_local<R>(&self, f: impl FnOnce(&Worker<Task>) -> R) -> Option<R> {
+        WORKER.with(|w| match &*w.borrow() {
+            Some(ctx) if ctx.pool_id == self.id => Some(f(&ctx.local)),
+            _ => None,
+        })
+    }
+
+    fn find_task(&self) -> Option<Task> {
+        WORKER.with(|w| {
+            let w = w.borrow();
+            let ctx = w.as_ref().filter(|ctx| ctx.pool_id == self.id)?;
+            ctx.local.pop().or_else(|| {
+                iter::repeat_with(|| {
+                    self.injector.steal_batch_and_pop(&ctx.local).or_else(|| {
+                        self.stealers
+                            .iter()
+                            .enumerate()
+                            .filter(|(i, _)| *i != ctx.index)
+                            .map(|(_, s)| s.steal())
+                            .collect::<Steal<_>>()
+                    })
+                })
+                .find(|s| !s.is_retry())
+                .and_then(|s| s.success())
+            })
+        })
+    }
+
+    fn has_work(&self) -> bool {
+        !self.injector.is_empty() || self.stealers.iter().any(|s| !s.is_empty())
+    }
+
+    // block until `done` holds; a worker keeps running tasks meanwhile so
+    // waiting on nested work never starves the pool
+    pub(crate) fn wait_until(&self, done: impl Fn() -> bool, lock: &Mutex<()>, cvar: &Condvar) {
+        let in_worker = self.with_local(|_| ()).is_some();
+        while !done() {
+            if let Some(task) = self.find_task() {
+                self.run(task);
+                continue;
+            }
+            let guard = lock.lock().expect("pool wait lock poisoned");
+            if done() {
+                break;
+            }
+            let timeout = if in_worker { 1 } else { 50 };
+            let _ = cvar.wait_timeout(guard, Duration::from_millis(timeout));
+        }
+    }
+
+    fn worker_loop(&self) {
+        loop {
+            if let Some(task) = self.find_task() {
+                self.run(task);
+                continue;
+            }
+            let guard = self.sleep.lock().expect("pool sleep lock poisoned");
+            if self.has_work() {
+                continue;
+            }
+            if self.shutdown.load(Ordering::Acquire) {
+                break;
+            }
+            let _ = self.wake.wait_timeout(guard, Duration::from_millis(100));
+        }
+    }
+}
+
+impl ThreadPool {
+    pub fn new(num_threads: usize) -> Result<Self> {
+        Self::with_capacity(num_threads, num_threads * QUEUE_PER_THREAD)
+    }
+
+    // `capacity` bounds the tasks waiting to start, `submit` blocks beyond it
+    pub fn with_capacity(num_threads: usize, capacity: usize) -> Result<Self> {
+        if num_threads == 0 || capacity == 0 {
+            return Err(anyhow!(
+                "thread pool needs at least one thread and one queue slot"
+            ));
+        }
+
+        let workers = (0..num_threads)
+            .map(|_| Worker::new_lifo())
+            .collect::<Vec<_>>();
+        let shared = Arc::new(Shared {
+            id: NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed),
+            injector: Injector::new(),
+            stealers: workers.iter().map(Worker::stealer).collect(),
+            capacity,
+            queued: Mutex::new(0),
+            not_full: Condvar::new(),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        });
+
+        // if a spawn fails, dropping the pool shuts down the workers already
+        // started instead of leaking them
+        let mut pool = Self {
+            shared,
+            threads: Vec::with_capacity(num_threads),
+        };
+        for (index, local) in workers.into_iter().enumerate() {
+            let shared = pool.shared.clone();
+            let handle = thread::Builder::new()
+                .name(format!("pool-{}-worker-{}", shared.id, index))
+                .spawn(move || {
+                    WORKER.with(|w| {
+                        *w.borrow_mut() = Some(WorkerContext {
+                            pool_id: shared.id,
+                            index,
+                            local,
+                        })
+                    });
+                    shared.worker_loop();
+                })?;
+            pool.threads.push(handle);
+        }
+        Ok(pool)
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    // queue a task, blocking while the submission queue is full
+    pub fn submit<F, T>(&self, f: F) -> Result<JoinHandle<T>, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.submit_inner(f, true)
+    }
+
+    // like `submit`, but fails with `PoolError::QueueFull` instead of blocking
+    pub fn try_submit<F, T>(&self, f: F) -> Result<JoinHandle<T>, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.submit_inner(f, false)
+    }
+
+    fn submit_inner<F, T>(&self, f: F, block: bool) -> Result<JoinHandle<T>, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job = Box::new(move || {
+            let _ = tx.send(catch_unwind(AssertUnwindSafe(f)));
+        });
+        self.shared.push(job, block)?;
+        Ok(JoinHandle {
+            shared: self.shared.clone(),
+            rx,
+        })
+    }
+
+    // run `f` with a scope whose tasks may borrow from the caller's stack,
+    // returns once every spawned task has finished
+    pub fn scope<'scope, F, R>(&self, f: F) -> Result<R, PoolError>
+    where
+        F: FnOnce(&Scope<'scope>) -> R,
+    {
+        let scope = Scope::new(self.shared.clone());
+        let ret = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+        let ret = ret.map_err(|payload| PoolError::Panicked(panic_message(payload)))?;
+        scope.into_result().map(|_| ret)
+    }
+
+    // run both closures, potentially in parallel, and return both results
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> Result<(RA, RB), PoolError>
+    where
+        A: FnOnce() -> RA,
+        B: FnOnce() -> RB + Send,
+        RB: Send,
+    {
+        let mut rb = None;
+        let ra = self.scope(|s| {
+            s.spawn(|_| rb = Some(b()));
+            a()
+        })?;
+        Ok((ra, rb.ok_or(PoolError::Cancelled)?))
+    }
+
+    // stop accepting tasks, let the queued ones finish and join the workers
+    pub fn shutdown(mut self) {
+        self.shutdown_inner();
+    }
+
+    fn shutdown_inner(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        drop(self.shared.sleep.lock().expect("pool sleep lock poisoned"));
+        self.shared.wake.notify_all();
+        self.shared.not_full.notify_all();
+
+        let current = thread::current().id();
+        for handle in self.threads.drain(..) {
+            // dropping the pool from one of its own tasks must not join itself
+            if handle.thread().id() != current {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown_inner();
+    }
+}
+
+impl<T> JoinHandle<T> {
+    // a worker of the same pool runs queued tasks while it waits, so a task
+    // may join another one without deadlocking the pool, even a 1-thread one
+    pub fn join(self) -> Result<T, PoolError> {
+        if self.shared.with_local(|_| ()).is_some() {
+            // nothing notifies these, the wait polls the channel instead
+            let (lock, cvar) = (Mutex::new(()), Condvar::new());
+            self.shared.wait_until(
+                || self.rx.has_message() || self.rx.is_closed(),
+                &lock,
+                &cvar,
+            );
+        }
+        match self.rx.recv() {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(payload)) => Err(PoolError::Panicked(panic_message(payload))),
+            Err(_) => Err(PoolError::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_submit_and_join() -> Result<()> {
+        let pool = ThreadPool::new(4)?;
+        let handles = (0..100)
+            .map(|i| pool.submit(move || i * 2))
+            .collect::<Result<Vec<_>, _>>()?;
+        let sum = handles
+            .into_iter()
+            .map(|h| h.join())
+            .sum::<Result<i32, _>>()?;
+        assert_eq!(sum, 9900);
+        Ok(())
+    }
+
+    #[test]
+    fn test_panic_is_reported() -> Result<()> {
+        let pool = ThreadPool::new(1)?;
+        let handle = pool.submit(|| panic!("boom"))?;
+        assert_eq!(
+            handle.join(),
+            Err::<(), _>(PoolError::Panicked("boom".into()))
+        );
+        // the worker survived the panic
+        assert_eq!(pool.submit(|| 42)?.join(), Ok(42));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_submit_backpressure() -> Result<()> {
+        let pool = ThreadPool::with_capacity(1, 1)?;
+        let (tx, rx) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        let blocker = pool.submit(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        })?;
+        started_rx.recv()?;
+
+        let queued = pool.submit(|| 1)?;
+        assert_eq!(pool.try_submit(|| 2).err(), Some(PoolError::QueueFull));
+
+        tx.send(())?;
+        blocker.join()?;
+        assert_eq!(queued.join()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scope_borrows_stack() -> Result<()> {
+        let pool = ThreadPool::new(4)?;
+        let mut data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        pool.scope(|s| {
+            for chunk in data.chunks_mut(2) {
+                s.spawn(move |_| chunk.iter_mut().for_each(|v| *v *= 10));
+            }
+        })?;
+        assert_eq!(data, vec![10, 20, 30, 40, 50, 60, 70, 80]);
+
+        let ret = pool.scope(|s| {
+            s.spawn(|_| panic!("scoped boom"));
+        });
+        assert_eq!(ret, Err(PoolError::Panicked("scoped boom".into())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_recursion() -> Result<()> {
+        fn fib(pool: &ThreadPool, n: u64) -> u64 {
+            if n < 2 {
+                return n;
+            }
+            let (a, b) = pool.join(|| fib(pool, n - 1), || fib(pool, n - 2)).unwrap();
+            a + b
+        }
+
+        let pool = ThreadPool::new(4)?;
+        let n = pool.submit(|| 20)?.join()?;
+        assert_eq!(fib(&pool, n), 6765);
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_from_a_worker() -> Result<()> {
+        let pool = Arc::new(ThreadPool::new(1)?);
+        let inner = pool.clone();
+        // the only worker waits on a task queued behind it
+        let outer = pool.submit(move || {
+            let handle = inner.submit(|| 21).unwrap();
+            handle.join().unwrap() * 2
+        })?;
+        assert_eq!(outer.join()?, 42);
+
+        let queued = pool.submit(|| 1)?;
+        let inner = pool.clone();
+        let outer = pool.submit(move || queued.join().unwrap() + 1)?;
+        assert_eq!(outer.join()?, 2);
+
+        let inner_join = pool.submit(move || inner.join(|| 1, || 2).unwrap())?;
+        assert_eq!(inner_join.join()?, (1, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shutdown_drains_queue() -> Result<()> {
+        let pool = ThreadPool::new(2)?;
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..50 {
+            let counter = counter.clone();
+            pool.submit(move || {
+                thread::sleep(Duration::from_millis(1));
+                counter.fetch_add(1, Ordering::Relaxed);
+            })?;
+        }
+        pool.shutdown();
+        assert_eq!(counter.load(Ordering::Relaxed), 50);
+        Ok(())
+    }
+}
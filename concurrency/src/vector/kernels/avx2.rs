@@ -0,0 +1,209 @@
+// AVX2/FMA kernels, only call them after checking the cpu supports both
+use std::arch::x86_64::*;
+
+macro_rules! avx2_kernels {
+    ($m:ident, $t:ty, $lanes:literal, $load:ident, $store:ident, $splat:ident, $zero:ident, $add:ident, $mul:ident, $madd:ident) => {
+        pub(crate) mod $m {
+            use super::*;
+            use crate::vector::kernels::portable::{Lane, reduce};
+
+            #[target_feature(enable = "avx2,fma")]
+            pub(crate) fn dot(a: &[$t], b: &[$t]) -> $t {
+                let (ac, ar) = a.as_chunks::<$lanes>();
+                let (bc, br) = b.as_chunks::<$lanes>();
+                let mut acc = $zero();
+                for (x, y) in ac.iter().zip(bc) {
+                    acc = $madd($load(x), $load(y), acc);
+                }
+                let mut lanes = [<$t>::default(); $lanes];
+                $store(acc, &mut lanes);
+                ar.iter()
+                    .zip(br)
+                    .fold(reduce(&lanes), |sum, (x, y)| sum.lane_add(x.lane_mul(*y)))
+            }
+
+            #[target_feature(enable = "avx2,fma")]
+            pub(crate) fn sum(a: &[$t]) -> $t {
+                let (chunks, rest) = a.as_chunks::<$lanes>();
+                let mut acc = $zero();
+                for x in chunks {
+                    acc = $add($load(x), acc);
+                }
+                let mut lanes = [<$t>::default(); $lanes];
+                $store(acc, &mut lanes);
+                rest.iter().fold(reduce(&lanes), |sum, x| sum.lane_add(*x))
+            }
+
+            #[target_feature(enable = "avx2,fma")]
+            pub(crate) fn axpy(alpha: $t, x: &[$t], y: &mut [$t]) {
+                let (xc, xr) = x.as_chunks::<$lanes>();
+                let (yc, yr) = y.as_chunks_mut::<$lanes>();
+                let va = $splat(alpha);
+                for (y, x) in yc.iter_mut().zip(xc) {
+                    let v = $madd(va, $load(x), $load(y));
+                    $store(v, y);
+                }
+                for (y, x) in yr.iter_mut().zip(xr) {
+                    *y = alpha.lane_mul(*x).lane_add(*y);
+                }
+            }
+
+            #[target_feature(enable = "avx2,fma")]
+            pub(crate) fn add(a: &[$t], b: &[$t], out: &mut [$t]) {
+                let (ac, ar) = a.as_chunks::<$lanes>();
+                let (bc, br) = b.as_chunks::<$lanes>();
+                let (oc, or) = out.as_chunks_mut::<$lanes>();
+                for ((out, a), b) in oc.iter_mut().zip(ac).zip(bc) {
+                    $store($add($load(a), $load(b)), out);
+                }
+                for ((out, a), b) in or.iter_mut().zip(ar).zip(br) {
+                    *out = a.lane_add(*b);
+                }
+            }
+
+            #[target_feature(enable = "avx2,fma")]
+            pub(crate) fn mul(a: &[$t], b: &[$t], out: &mut [$t]) {
+                let (ac, ar) = a.as_chunks::<$lanes>();
+                let (bc, br) = b.as_chunks::<$lanes>();
+                let (oc, or) = out.as_chunks_mut::<$lanes>();
+                for ((out, a), b) in oc.iter_mut().zip(ac).zip(bc) {
+                    $store($mul($load(a), $load(b)), out);
+                }
+                for ((out, a), b) in or.iter_mut().zip(ar).zip(br) {
+                    *out = a.lane_mul(*b);
+                }
+            }
+        }
+    };
+}
+
+avx2_kernels!(
+    float32,
+    f32,
+    8,
+    load_ps,
+    store_ps,
+    _mm256_set1_ps,
+    _mm256_setzero_ps,
+    _mm256_add_ps,
+    _mm256_mul_ps,
+    _mm256_fmadd_ps
+);
+avx2_kernels!(
+    float64,
+    f64,
+    4,
+    load_pd,
+    store_pd,
+    _mm256_set1_pd,
+    _mm256_setzero_pd,
+    _mm256_add_pd,
+    _mm256_mul_pd,
+    _mm256_fmadd_pd
+);
+avx2_kernels!(
+    int32,
+    i32,
+    8,
+    load_epi32,
+    store_epi32,
+    _mm256_set1_epi32,
+    _mm256_setzero_si256,
+    _mm256_add_epi32,
+    _mm256_mullo_epi32,
+    madd_epi32
+);
+avx2_kernels!(
+    int64,
+    i64,
+    4,
+    load_epi64,
+    store_epi64,
+    _mm256_set1_epi64x,
+    _mm256_setzero_si256,
+    _mm256_add_epi64,
+    mullo_epi64,
+    madd_epi64
+);
+
+// the loads and stores take fixed size arrays so they can never go out of bounds
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn load_ps(x: &[f32; 8]) -> __m256 {
+    // SAFETY: x is valid for reading 8 f32
+    unsafe { _mm256_loadu_ps(x.as_ptr()) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn store_ps(v: __m256, out: &mut [f32; 8]) {
+    // SAFETY: out is valid for writing 8 f32
+    unsafe { _mm256_storeu_ps(out.as_mut_ptr(), v) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn load_pd(x: &[f64; 4]) -> __m256d {
+    // SAFETY: x is valid for reading 4 f64
+    unsafe { _mm256_loadu_pd(x.as_ptr()) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn store_pd(v: __m256d, out: &mut [f64; 4]) {
+    // SAFETY: out is valid for writing 4 f64
+    unsafe { _mm256_storeu_pd(out.as_mut_ptr(), v) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn load_epi32(x: &[i32; 8]) -> __m256i {
+    // SAFETY: x is valid for reading 32 bytes, loadu has no alignment requirement
+    unsafe { _mm256_loadu_si256(x.as_ptr().cast()) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn store_epi32(v: __m256i, out: &mut [i32; 8]) {
+    // SAFETY: out is valid for writing 32 bytes
+    unsafe { _mm256_storeu_si256(out.as_mut_ptr().cast(), v) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn load_epi64(x: &[i64; 4]) -> __m256i {
+    // SAFETY: x is valid for reading 32 bytes, loadu has no alignment requirement
+    unsafe { _mm256_loadu_si256(x.as_ptr().cast()) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn store_epi64(v: __m256i, out: &mut [i64; 4]) {
+    // SAFETY: out is valid for writing 32 bytes
+    unsafe { _mm256_storeu_si256(out.as_mut_ptr().cast(), v) }
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn madd_epi32(a: __m256i, b: __m256i, c: __m256i) -> __m256i {
+    _mm256_add_epi32(_mm256_mullo_epi32(a, b), c)
+}
+
+// AVX2 has no 64-bit multiply, build it from 32x32->64 products:
+// a * b mod 2^64 = lo(a) * lo(b) + ((hi(a) * lo(b) + lo(a) * hi(b)) << 32)
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn mullo_epi64(a: __m256i, b: __m256i) -> __m256i {
+    let lo = _mm256_mul_epu32(a, b);
+    let a_hi = _mm256_srli_epi64::<32>(a);
+    let b_hi = _mm256_srli_epi64::<32>(b);
+    let cross = _mm256_add_epi64(_mm256_mul_epu32(a_hi, b), _mm256_mul_epu32(a, b_hi));
+    _mm256_add_epi64(lo, _mm256_slli_epi64::<32>(cross))
+}
+
+#[inline]
+#[target_feature(enable = "avx2,fma")]
+fn madd_epi64(a: __m256i, b: __m256i, c: __m256i) -> __m256i {
+    _mm256_add_epi64(mullo_epi64(a, b), c)
+}
@@ -0,0 +1,60 @@
+#[cfg(target_arch = "x86_64")]
+mod avx2;
+#[cfg(target_arch = "aarch64")]
+mod neon;
+pub(crate) mod portable;
+
+// element types with dedicated kernels; callers guarantee equal lengths
+pub(crate) trait Kernels: Sized {
+    fn dot(a: &[Self], b: &[Self]) -> Self;
+    fn sum(a: &[Self]) -> Self;
+    fn axpy(alpha: Self, x: &[Self], y: &mut [Self]);
+    fn add(a: &[Self], b: &[Self], out: &mut [Self]);
+    fn mul(a: &[Self], b: &[Self], out: &mut [Self]);
+}
+
+// pick the best kernel the running cpu supports, feature detection is cached
+// by std so this is a couple of atomic loads per call
+macro_rules! dispatch {
+    ($m:ident, $op:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("avx2") && std::arch::is_x86_feature_detected!("fma") {
+            // SAFETY: the cpu supports the features the kernel is compiled for
+            return unsafe { avx2::$m::$op($($arg),*) };
+        }
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            // SAFETY: the cpu supports the features the kernel is compiled for
+            return unsafe { neon::$m::$op($($arg),*) };
+        }
+        portable::$op($($arg),*)
+    }};
+}
+
+macro_rules! impl_kernels {
+    ($($t:ty => $m:ident),*) => {$(
+        impl Kernels for $t {
+            fn dot(a: &[$t], b: &[$t]) -> $t {
+                dispatch!($m, dot(a, b))
+            }
+
+            fn sum(a: &[$t]) -> $t {
+                dispatch!($m, sum(a))
+            }
+
+            fn axpy(alpha: $t, x: &[$t], y: &mut [$t]) {
+                dispatch!($m, axpy(alpha, x, y))
+            }
+
+            fn add(a: &[$t], b: &[$t], out: &mut [$t]) {
+                dispatch!($m, add(a, b, out))
+            }
+
+            fn mul(a: &[$t], b: &[$t], out: &mut [$t]) {
+                dispatch!($m, mul(a, b, out))
+            }
+        }
+    )*};
+}
+
+impl_kernels!(f32 => float32, f64 => float64, i32 => int32, i64 => int64);
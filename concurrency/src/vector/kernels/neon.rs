@@ -0,0 +1,186 @@
+// NEON kernels, only call them after checking the cpu supports neon
+use std::arch::aarch64::*;
+
+macro_rules! neon_kernels {
+    ($m:ident, $t:ty, $lanes:literal, $load:ident, $store:ident, $splat:ident, $add:ident, $mul:ident, $madd:ident) => {
+        pub(crate) mod $m {
+            use super::*;
+            use crate::vector::kernels::portable::{Lane, reduce};
+
+            #[target_feature(enable = "neon")]
+            pub(crate) fn dot(a: &[$t], b: &[$t]) -> $t {
+                let (ac, ar) = a.as_chunks::<$lanes>();
+                let (bc, br) = b.as_chunks::<$lanes>();
+                let mut acc = $splat(<$t>::default());
+                for (x, y) in ac.iter().zip(bc) {
+                    acc = $madd(acc, $load(x), $load(y));
+                }
+                let mut lanes = [<$t>::default(); $lanes];
+                $store(acc, &mut lanes);
+                ar.iter()
+                    .zip(br)
+                    .fold(reduce(&lanes), |sum, (x, y)| sum.lane_add(x.lane_mul(*y)))
+            }
+
+            #[target_feature(enable = "neon")]
+            pub(crate) fn sum(a: &[$t]) -> $t {
+                let (chunks, rest) = a.as_chunks::<$lanes>();
+                let mut acc = $splat(<$t>::default());
+                for x in chunks {
+                    acc = $add($load(x), acc);
+                }
+                let mut lanes = [<$t>::default(); $lanes];
+                $store(acc, &mut lanes);
+                rest.iter().fold(reduce(&lanes), |sum, x| sum.lane_add(*x))
+            }
+
+            #[target_feature(enable = "neon")]
+            pub(crate) fn axpy(alpha: $t, x: &[$t], y: &mut [$t]) {
+                let (xc, xr) = x.as_chunks::<$lanes>();
+                let (yc, yr) = y.as_chunks_mut::<$lanes>();
+                let va = $splat(alpha);
+                for (y, x) in yc.iter_mut().zip(xc) {
+                    let v = $madd($load(y), va, $load(x));
+                    $store(v, y);
+                }
+                for (y, x) in yr.iter_mut().zip(xr) {
+                    *y = alpha.lane_mul(*x).lane_add(*y);
+                }
+            }
+
+            #[target_feature(enable = "neon")]
+            pub(crate) fn add(a: &[$t], b: &[$t], out: &mut [$t]) {
+                let (ac, ar) = a.as_chunks::<$lanes>();
+                let (bc, br) = b.as_chunks::<$lanes>();
+                let (oc, or) = out.as_chunks_mut::<$lanes>();
+                for ((out, a), b) in oc.iter_mut().zip(ac).zip(bc) {
+                    $store($add($load(a), $load(b)), out);
+                }
+                for ((out, a), b) in or.iter_mut().zip(ar).zip(br) {
+                    *out = a.lane_add(*b);
+                }
+            }
+
+            #[target_feature(enable = "neon")]
+            pub(crate) fn mul(a: &[$t], b: &[$t], out: &mut [$t]) {
+                let (ac, ar) = a.as_chunks::<$lanes>();
+                let (bc, br) = b.as_chunks::<$lanes>();
+                let (oc, or) = out.as_chunks_mut::<$lanes>();
+                for ((out, a), b) in oc.iter_mut().zip(ac).zip(bc) {
+                    $store($mul($load(a), $load(b)), out);
+                }
+                for ((out, a), b) in or.iter_mut().zip(ar).zip(br) {
+                    *out = a.lane_mul(*b);
+                }
+            }
+        }
+    };
+}
+
+neon_kernels!(
+    float32,
+    f32,
+    4,
+    load_f32,
+    store_f32,
+    vdupq_n_f32,
+    vaddq_f32,
+    vmulq_f32,
+    vfmaq_f32
+);
+neon_kernels!(
+    float64,
+    f64,
+    2,
+    load_f64,
+    store_f64,
+    vdupq_n_f64,
+    vaddq_f64,
+    vmulq_f64,
+    vfmaq_f64
+);
+neon_kernels!(
+    int32,
+    i32,
+    4,
+    load_s32,
+    store_s32,
+    vdupq_n_s32,
+    vaddq_s32,
+    vmulq_s32,
+    vmlaq_s32
+);
+
+// NEON has no 64-bit multiply, i64 keeps using the portable kernels
+pub(crate) mod int64 {
+    use crate::vector::kernels::portable;
+
+    #[target_feature(enable = "neon")]
+    pub(crate) fn dot(a: &[i64], b: &[i64]) -> i64 {
+        portable::dot(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(crate) fn sum(a: &[i64]) -> i64 {
+        portable::sum(a)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(crate) fn axpy(alpha: i64, x: &[i64], y: &mut [i64]) {
+        portable::axpy(alpha, x, y)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(crate) fn add(a: &[i64], b: &[i64], out: &mut [i64]) {
+        portable::add(a, b, out)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(crate) fn mul(a: &[i64], b: &[i64], out: &mut [i64]) {
+        portable::mul(a, b, out)
+    }
+}
+
+// the loads and stores take fixed size arrays so they can never go out of bounds
+
+#[inline]
+#[target_feature(enable = "neon")]
+fn load_f32(x: &[f32; 4]) -> float32x4_t {
+    // SAFETY: x is valid for reading 4 f32
+    unsafe { vld1q_f32(x.as_ptr()) }
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+fn store_f32(v: float32x4_t, out: &mut [f32; 4]) {
+    // SAFETY: out is valid for writing 4 f32
+    unsafe { vst1q_f32(out.as_mut_ptr(), v) }
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+fn load_f64(x: &[f64; 2]) -> float64x2_t {
+    // SAFETY: x is valid for reading 2 f64
+    unsafe { vld1q_f64(x.as_ptr()) }
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+fn store_f64(v: float64x2_t, out: &mut [f64; 2]) {
+    // SAFETY: out is valid for writing 2 f64
+    unsafe { vst1q_f64(out.as_mut_ptr(), v) }
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+fn load_s32(x: &[i32; 4]) -> int32x4_t {
+    // SAFETY: x is valid for reading 4 i32
+    unsafe { vld1q_s32(x.as_ptr()) }
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+fn store_s32(v: int32x4_t, out: &mut [i32; 4]) {
+    // SAFETY: out is valid for writing 4 i32
+    unsafe { vst1q_s32(out.as_mut_ptr(), v) }
+}
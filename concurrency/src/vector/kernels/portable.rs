@@ -0,0 +1,93 @@
+// plain rust kernels written so llvm can vectorize them on any target;
+// eight independent accumulators break the dependency chain between
+// iterations, which is what lets the reductions use simd registers
+const LANES: usize = 8;
+
+// integer lanes wrap on overflow, the same way the simd instructions do
+pub(crate) trait Lane: Copy + Default {
+    fn lane_add(self, other: Self) -> Self;
+    fn lane_mul(self, other: Self) -> Self;
+}
+
+macro_rules! impl_float_lane {
+    ($($t:ty),*) => {$(
+        impl Lane for $t {
+            #[inline(always)]
+            fn lane_add(self, other: Self) -> Self {
+                self + other
+            }
+
+            #[inline(always)]
+            fn lane_mul(self, other: Self) -> Self {
+                self * other
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_int_lane {
+    ($($t:ty),*) => {$(
+        impl Lane for $t {
+            #[inline(always)]
+            fn lane_add(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+
+            #[inline(always)]
+            fn lane_mul(self, other: Self) -> Self {
+                self.wrapping_mul(other)
+            }
+        }
+    )*};
+}
+
+impl_float_lane!(f32, f64);
+impl_int_lane!(i32, i64);
+
+#[inline]
+pub(crate) fn reduce<T: Lane>(lanes: &[T]) -> T {
+    lanes.iter().fold(T::default(), |acc, v| acc.lane_add(*v))
+}
+
+pub(crate) fn dot<T: Lane>(a: &[T], b: &[T]) -> T {
+    let (ac, ar) = a.as_chunks::<LANES>();
+    let (bc, br) = b.as_chunks::<LANES>();
+    let mut acc = [T::default(); LANES];
+    for (x, y) in ac.iter().zip(bc) {
+        for ((acc, x), y) in acc.iter_mut().zip(x).zip(y) {
+            *acc = acc.lane_add(x.lane_mul(*y));
+        }
+    }
+    ar.iter()
+        .zip(br)
+        .fold(reduce(&acc), |sum, (x, y)| sum.lane_add(x.lane_mul(*y)))
+}
+
+pub(crate) fn sum<T: Lane>(a: &[T]) -> T {
+    let (chunks, rest) = a.as_chunks::<LANES>();
+    let mut acc = [T::default(); LANES];
+    for x in chunks {
+        for (acc, x) in acc.iter_mut().zip(x) {
+            *acc = acc.lane_add(*x);
+        }
+    }
+    rest.iter().fold(reduce(&acc), |sum, x| sum.lane_add(*x))
+}
+
+pub(crate) fn axpy<T: Lane>(alpha: T, x: &[T], y: &mut [T]) {
+    for (y, x) in y.iter_mut().zip(x) {
+        *y = alpha.lane_mul(*x).lane_add(*y);
+    }
+}
+
+pub(crate) fn add<T: Lane>(a: &[T], b: &[T], out: &mut [T]) {
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = a.lane_add(*b);
+    }
+}
+
+pub(crate) fn mul<T: Lane>(a: &[T], b: &[T], out: &mut [T]) {
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = a.lane_mul(*b);
+    }
+}
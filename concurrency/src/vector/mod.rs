@@ -0,0 +1,93 @@
+mod kernels;
+pub mod ops;
+pub mod scalar;
+
+use crate::pool::{self, PoolError, ThreadPool};
+use anyhow::{Result, anyhow};
+use std::ops::{Add, AddAssign, Deref, Mul};
+
+// anything the vector ops can work on
+pub trait Element:
+    Copy + Default + Add<Output = Self> + Mul<Output = Self> + AddAssign + Send + Sync + 'static
+{
+}
+
+impl<T> Element for T where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign + Send + Sync + 'static
+{
+}
+
+pub trait Float {
+    fn sqrt(self) -> Self;
+}
+
+impl Float for f32 {
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+}
+
+impl Float for f64 {
+    fn sqrt(self) -> Self {
+        f64::sqrt(self)
+    }
+}
+
+pub struct Vector<T> {
+    pub data: Vec<T>,
+}
+
+impl<T> Deref for Vector<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl<T> Vector<T> {
+    pub fn new(data: impl Into<Vec<T>>) -> Self {
+        Self { data: data.into() }
+    }
+}
+
+// below this length splitting the work costs more than it saves
+const SPLIT_THRESHOLD: usize = 4096;
+
+// pretend this is a heavy operation
+pub fn dot_product<T: Element>(mxa: &[T], mxb: &[T]) -> Result<T> {
+    if mxa.len() != mxb.len() {
+        return Err(anyhow!(
+            "第一个矩阵的列数（column）和第二个矩阵的行数（row）不相同！"
+        ));
+    }
+
+    Ok(par_dot(pool::global(), mxa, mxb)?)
+}
+
+// fork/join over halves until the pieces are small enough
+fn par_dot<T: Element>(pool: &ThreadPool, a: &[T], b: &[T]) -> Result<T, PoolError> {
+    if a.len() <= SPLIT_THRESHOLD {
+        return Ok(ops::dot_unchecked(a, b));
+    }
+    let mid = a.len() / 2;
+    let (left, right) = pool.join(
+        || par_dot(pool, &a[..mid], &b[..mid]),
+        || par_dot(pool, &a[mid..], &b[mid..]),
+    )?;
+    Ok(left? + right?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_product() -> Result<()> {
+        let a = (1..=10_000).collect::<Vec<i64>>();
+        let b = vec![2; 10_000];
+        assert_eq!(dot_product(&Vector::new(a), &Vector::new(b))?, 100_010_000);
+        assert!(dot_product(&[1], &[1, 2]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+// vector ops over borrowed slices; f32/f64/i32/i64 run simd kernels (integer
+// kernels wrap on overflow), every other type falls back to `scalar`
+use super::kernels::Kernels;
+use super::{Element, Float, scalar};
+use anyhow::{Result, anyhow};
+use std::any::{Any, TypeId};
+
+// run `$body` with `$u` bound to T's concrete type when T has simd kernels
+macro_rules! fast_path {
+    ($t:ty, |$u:ident| $body:expr) => {
+        fast_path!(@each $t, $u, $body, f32, f64, i32, i64)
+    };
+    (@each $t:ty, $u:ident, $body:expr, $($ty:ty),*) => {$(
+        if TypeId::of::<$t>() == TypeId::of::<$ty>() {
+            type $u = $ty;
+            return $body;
+        }
+    )*};
+}
+
+pub fn dot<T: Element>(a: &[T], b: &[T]) -> Result<T> {
+    check_len(a.len(), b.len())?;
+    Ok(dot_unchecked(a, b))
+}
+
+pub fn sum<T: Element>(a: &[T]) -> T {
+    fast_path!(T, |U| same(U::sum(cast::<T, U>(a))));
+    scalar::sum(a)
+}
+
+// euclidean length
+pub fn norm<T: Element + Float>(a: &[T]) -> T {
+    dot_unchecked(a, a).sqrt()
+}
+
+// y = alpha * x + y
+pub fn axpy<T: Element>(alpha: T, x: &[T], y: &mut [T]) -> Result<()> {
+    check_len(x.len(), y.len())?;
+    fast_path!(T, |U| {
+        U::axpy(same(alpha), cast::<T, U>(x), cast_mut::<T, U>(y));
+        Ok(())
+    });
+    scalar::axpy(alpha, x, y);
+    Ok(())
+}
+
+// out = a + b, element-wise
+pub fn add<T: Element>(a: &[T], b: &[T], out: &mut [T]) -> Result<()> {
+    check_len(a.len(), b.len())?;
+    check_len(a.len(), out.len())?;
+    fast_path!(T, |U| {
+        U::add(cast::<T, U>(a), cast::<T, U>(b), cast_mut::<T, U>(out));
+        Ok(())
+    });
+    scalar::add(a, b, out);
+    Ok(())
+}
+
+// out = a * b, element-wise
+pub fn mul<T: Element>(a: &[T], b: &[T], out: &mut [T]) -> Result<()> {
+    check_len(a.len(), b.len())?;
+    check_len(a.len(), out.len())?;
+    fast_path!(T, |U| {
+        U::mul(cast::<T, U>(a), cast::<T, U>(b), cast_mut::<T, U>(out));
+        Ok(())
+    });
+    scalar::mul(a, b, out);
+    Ok(())
+}
+
+pub(crate) fn dot_unchecked<T: Element>(a: &[T], b: &[T]) -> T {
+    fast_path!(T, |U| same(U::dot(cast::<T, U>(a), cast::<T, U>(b))));
+    scalar::dot(a, b)
+}
+
+fn check_len(a: usize, b: usize) -> Result<()> {
+    if a != b {
+        return Err(anyhow!("vector length mismatch: {} vs {}", a, b));
+    }
+    Ok(())
+}
+
+fn cast<T: 'static, U: 'static>(s: &[T]) -> &[U] {
+    assert_eq!(TypeId::of::<T>(), TypeId::of::<U>());
+    // SAFETY: T and U are the same type
+    unsafe { &*(s as *const [T] as *const [U]) }
+}
+
+fn cast_mut<T: 'static, U: 'static>(s: &mut [T]) -> &mut [U] {
+    assert_eq!(TypeId::of::<T>(), TypeId::of::<U>());
+    // SAFETY: T and U are the same type
+    unsafe { &mut *(s as *mut [T] as *mut [U]) }
+}
+
+fn same<T: Copy + 'static, U: Copy + 'static>(v: T) -> U {
+    *(&v as &dyn Any)
+        .downcast_ref::<U>()
+        .expect("T and U are the same type")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn close(a: f64, b: f64, scale: f64) -> bool {
+        (a - b).abs() <= 1e-9 * scale.max(1.0)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_f64_matches_scalar(
+            pairs in prop::collection::vec((-1e3f64..1e3, -1e3f64..1e3), 0..200),
+            alpha in -10f64..10.0,
+        ) {
+            let (a, b): (Vec<f64>, Vec<f64>) = pairs.into_iter().unzip();
+            let scale = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum::<f64>();
+            prop_assert!(close(dot(&a, &b).unwrap(), scalar::dot(&a, &b), scale));
+            prop_assert!(close(sum(&a), scalar::sum(&a), a.iter().map(|x| x.abs()).sum()));
+
+            let mut out = vec![0.0; a.len()];
+            let mut expected = vec![0.0; a.len()];
+            add(&a, &b, &mut out).unwrap();
+            scalar::add(&a, &b, &mut expected);
+            prop_assert_eq!(&out, &expected);
+            mul(&a, &b, &mut out).unwrap();
+            scalar::mul(&a, &b, &mut expected);
+            prop_assert_eq!(&out, &expected);
+
+            let mut y = b.clone();
+            let mut expected = b.clone();
+            axpy(alpha, &a, &mut y).unwrap();
+            scalar::axpy(alpha, &a, &mut expected);
+            for (y, e) in y.iter().zip(&expected) {
+                // fma rounds once where the scalar version rounds twice
+                prop_assert!(close(*y, *e, (alpha * 1e3).abs() + 1e3));
+            }
+        }
+
+        #[test]
+        fn prop_f32_matches_scalar(
+            pairs in prop::collection::vec((-100f32..100.0, -100f32..100.0), 0..200),
+        ) {
+            let (a, b): (Vec<f32>, Vec<f32>) = pairs.into_iter().unzip();
+            let scale = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum::<f32>();
+            let diff = (dot(&a, &b).unwrap() - scalar::dot(&a, &b)).abs();
+            prop_assert!(diff <= 1e-4 * scale.max(1.0));
+            let norm = norm(&a);
+            prop_assert!((norm - scalar::dot(&a, &a).sqrt()).abs() <= 1e-3 * norm.max(1.0));
+        }
+
+        #[test]
+        fn prop_i32_matches_scalar(
+            pairs in prop::collection::vec((-1000i32..1000, -1000i32..1000), 0..200),
+            alpha in -100i32..100,
+        ) {
+            let (a, b): (Vec<i32>, Vec<i32>) = pairs.into_iter().unzip();
+            prop_assert_eq!(dot(&a, &b).unwrap(), scalar::dot(&a, &b));
+            prop_assert_eq!(sum(&a), scalar::sum(&a));
+
+            let mut y = b.clone();
+            let mut expected = b.clone();
+            axpy(alpha, &a, &mut y).unwrap();
+            scalar::axpy(alpha, &a, &mut expected);
+            prop_assert_eq!(y, expected);
+        }
+
+        #[test]
+        fn prop_i64_matches_scalar(
+            pairs in prop::collection::vec((-(1i64 << 40)..(1 << 40), -1000i64..1000), 0..200),
+        ) {
+            let (a, b): (Vec<i64>, Vec<i64>) = pairs.into_iter().unzip();
+            prop_assert_eq!(dot(&a, &b).unwrap(), scalar::dot(&a, &b));
+            prop_assert_eq!(sum(&a), scalar::sum(&a));
+
+            let mut out = vec![0; a.len()];
+            let mut expected = vec![0; a.len()];
+            mul(&a, &b, &mut out).unwrap();
+            scalar::mul(&a, &b, &mut expected);
+            prop_assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_generic_fallback() -> Result<()> {
+        let a = [1u8, 2, 3];
+        let b = [4u8, 5, 6];
+        assert_eq!(dot(&a, &b)?, 32);
+        let mut out = [0u8; 3];
+        add(&a, &b, &mut out)?;
+        assert_eq!(out, [5, 7, 9]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        assert!(dot(&[1.0f32, 2.0], &[1.0]).is_err());
+        assert!(axpy(2, &[1, 2], &mut [1]).is_err());
+        assert!(add(&[1, 2], &[1, 2], &mut [0]).is_err());
+    }
+}
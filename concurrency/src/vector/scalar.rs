@@ -0,0 +1,38 @@
+// straightforward one-element-at-a-time versions of the vector ops, used for
+// types without simd kernels and as the reference in tests and benchmarks;
+// like the kernels they expect slices of equal length
+use super::Element;
+
+pub fn dot<T: Element>(a: &[T], b: &[T]) -> T {
+    let mut sum = T::default();
+    for (x, y) in a.iter().zip(b.iter()) {
+        sum += *x * *y;
+    }
+    sum
+}
+
+pub fn sum<T: Element>(a: &[T]) -> T {
+    let mut sum = T::default();
+    for x in a {
+        sum += *x;
+    }
+    sum
+}
+
+pub fn axpy<T: Element>(alpha: T, x: &[T], y: &mut [T]) {
+    for (y, x) in y.iter_mut().zip(x) {
+        *y = alpha * *x + *y;
+    }
+}
+
+pub fn add<T: Element>(a: &[T], b: &[T], out: &mut [T]) {
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = *a + *b;
+    }
+}
+
+pub fn mul<T: Element>(a: &[T], b: &[T], out: &mut [T]) {
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = *a * *b;
+    }
+}
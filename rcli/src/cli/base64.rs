@@ -1,4 +1,5 @@
 use super::verify_file;
+use crate::error::{RcliError, bail};
 use clap::Parser;
 use std::fmt;
 use std::fmt::Formatter;
@@ -34,7 +35,7 @@ pub enum Base64Format {
     UrlSafe,
 }
 
-fn parse_base64_format(format: &str) -> Result<Base64Format, anyhow::Error> {
+fn parse_base64_format(format: &str) -> Result<Base64Format, RcliError> {
     format.parse()
 }
 
@@ -48,13 +49,13 @@ impl From<Base64Format> for &'static str {
 }
 
 impl FromStr for Base64Format {
-    type Err = anyhow::Error;
+    type Err = RcliError;
 
     fn from_str(format: &str) -> Result<Self, Self::Err> {
         match format {
             "standard" => Ok(Base64Format::Standard),
             "urlsafe" => Ok(Base64Format::UrlSafe),
-            _ => anyhow::bail!("Unsupported format: {}", format),
+            _ => bail!(InvalidArgument, "Unsupported format: {}", format),
         }
     }
 }
@@ -0,0 +1,98 @@
+use super::verify_file;
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub struct EncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = parse_codec, default_value = "base64")]
+    pub codec: Codec,
+    #[arg(short, long, help = "Break encoded lines after N columns")]
+    pub wrap: Option<usize>,
+}
+
+#[derive(Debug, Parser)]
+pub struct DecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        default_value = "-",
+        help = "Decoded bytes are written as is"
+    )]
+    pub output: String,
+    #[arg(short, long, value_parser = parse_codec, default_value = "base64")]
+    pub codec: Codec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Hex,
+    Base32,
+    Base32Crockford,
+    // bitcoin alphabet, which has to hold the whole input in memory
+    Base58,
+    Base64,
+    Base64NoPad,
+    Base64Url,
+    Base64UrlNoPad,
+    Ascii85,
+    Z85,
+    Percent,
+}
+
+fn parse_codec(codec: &str) -> Result<Codec, RcliError> {
+    codec.parse()
+}
+
+impl From<Codec> for &'static str {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::Hex => "hex",
+            Codec::Base32 => "base32",
+            Codec::Base32Crockford => "base32-crockford",
+            Codec::Base58 => "base58",
+            Codec::Base64 => "base64",
+            Codec::Base64NoPad => "base64-nopad",
+            Codec::Base64Url => "base64url",
+            Codec::Base64UrlNoPad => "base64url-nopad",
+            Codec::Ascii85 => "ascii85",
+            Codec::Z85 => "z85",
+            Codec::Percent => "percent",
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = RcliError;
+
+    fn from_str(codec: &str) -> Result<Self, Self::Err> {
+        match codec.to_ascii_lowercase().as_str() {
+            "hex" | "base16" => Ok(Codec::Hex),
+            "base32" => Ok(Codec::Base32),
+            "base32-crockford" | "crockford" => Ok(Codec::Base32Crockford),
+            "base58" => Ok(Codec::Base58),
+            "base64" => Ok(Codec::Base64),
+            "base64-nopad" => Ok(Codec::Base64NoPad),
+            "base64url" => Ok(Codec::Base64Url),
+            "base64url-nopad" | "urlsafe" => Ok(Codec::Base64UrlNoPad),
+            "ascii85" | "base85" => Ok(Codec::Ascii85),
+            "z85" => Ok(Codec::Z85),
+            "percent" | "url" => Ok(Codec::Percent),
+            _ => bail!(InvalidArgument, "Unsupported codec: {}", codec),
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -0,0 +1,13 @@
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub enum ConfigSubCommand {
+    #[command(about = "Print the merged configuration and where each value comes from")]
+    Show(ConfigShowOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct ConfigShowOpts {
+    #[arg(short, long, help = "Also print the built-in defaults")]
+    pub all: bool,
+}
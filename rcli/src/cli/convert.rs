@@ -0,0 +1,76 @@
+use super::verify_file;
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub struct ConvertOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long, value_parser = parse_data_format, help = "Input format, guessed from the input extension when omitted")]
+    pub from: Option<DataFormat>,
+    #[arg(long, value_parser = parse_data_format, help = "Output format, guessed from the output extension when omitted")]
+    pub to: Option<DataFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Yaml,
+    Toml,
+}
+
+fn parse_data_format(format: &str) -> Result<DataFormat, RcliError> {
+    format.parse()
+}
+
+impl DataFormat {
+    pub fn from_path(path: &str) -> Option<DataFormat> {
+        let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "jsonl" => Some(DataFormat::Ndjson),
+            "yml" => Some(DataFormat::Yaml),
+            ext => ext.parse().ok(),
+        }
+    }
+}
+
+impl From<DataFormat> for &'static str {
+    fn from(format: DataFormat) -> Self {
+        match format {
+            DataFormat::Csv => "csv",
+            DataFormat::Json => "json",
+            DataFormat::Ndjson => "ndjson",
+            DataFormat::Yaml => "yaml",
+            DataFormat::Toml => "toml",
+        }
+    }
+}
+
+impl FromStr for DataFormat {
+    type Err = RcliError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(DataFormat::Csv),
+            "json" => Ok(DataFormat::Json),
+            "ndjson" | "jsonl" => Ok(DataFormat::Ndjson),
+            "yaml" | "yml" => Ok(DataFormat::Yaml),
+            "toml" => Ok(DataFormat::Toml),
+            _ => bail!(InvalidArgument, "Unsupported format: {}", format),
+        }
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -1,13 +1,23 @@
+use super::convert::DataFormat;
 use super::verify_file;
-use clap::Parser;
+use crate::error::{RcliError, Result, bail, err};
+use clap::{ArgAction, Parser};
 use std::fmt;
 use std::fmt::Formatter;
 use std::str::FromStr;
 
-fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
+fn parse_format(format: &str) -> Result<OutputFormat, RcliError> {
     format.parse()
 }
 
+fn parse_data_format(format: &str) -> Result<DataFormat, RcliError> {
+    format.parse()
+}
+
+fn parse_schema(schema: &str) -> Result<Schema, RcliError> {
+    schema.parse()
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
@@ -19,14 +29,14 @@ impl From<OutputFormat> for &'static str {
 }
 
 impl FromStr for OutputFormat {
-    type Err = anyhow::Error;
+    type Err = RcliError;
 
     fn from_str(format: &str) -> Result<Self, Self::Err> {
         match format {
             "json" => Ok(OutputFormat::Json),
             "yaml" => Ok(OutputFormat::Yaml),
             "toml" => Ok(OutputFormat::Toml),
-            _ => anyhow::bail!("Unsupported format: {}", format),
+            _ => bail!(InvalidArgument, "Unsupported format: {}", format),
         }
     }
 }
@@ -37,18 +47,113 @@ impl fmt::Display for OutputFormat {
     }
 }
 
+impl From<ColumnType> for &'static str {
+    fn from(column_type: ColumnType) -> Self {
+        match column_type {
+            ColumnType::Null => "null",
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = RcliError;
+
+    fn from_str(column_type: &str) -> Result<Self, Self::Err> {
+        match column_type {
+            "null" => Ok(ColumnType::Null),
+            "bool" | "boolean" => Ok(ColumnType::Bool),
+            "int" | "integer" => Ok(ColumnType::Integer),
+            "float" | "number" => Ok(ColumnType::Float),
+            "date" => Ok(ColumnType::Date),
+            "string" | "str" => Ok(ColumnType::String),
+            _ => bail!(InvalidArgument, "Unsupported column type: {}", column_type),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// `name:type,name:type`, columns left out are still inferred
+impl FromStr for Schema {
+    type Err = RcliError;
+
+    fn from_str(schema: &str) -> Result<Self, Self::Err> {
+        let columns = schema
+            .split(',')
+            .filter(|column| !column.trim().is_empty())
+            .map(|column| {
+                let (name, column_type) = column
+                    .rsplit_once(':')
+                    .ok_or_else(|| err!(InvalidArgument, "Expected name:type, got: {}", column))?;
+                Ok((name.trim().to_string(), column_type.trim().parse()?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Schema(columns))
+    }
+}
+
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+    #[arg(short, long, value_parser = verify_file, required = true)]
+    pub input: Option<String>,
     #[arg(short, long)]
     pub output: Option<String>,
     #[arg(long, value_parser = parse_format,  default_value = "json")]
     pub format: OutputFormat,
     #[arg(short, long, default_value_t = ',')]
     pub delimiter: char,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub header: bool,
+    #[arg(long, value_parser = parse_schema, help = "Column types, e.g. id:integer,born:date")]
+    pub schema: Option<Schema>,
+}
+
+#[derive(Debug, Parser)]
+pub enum CsvSubCommand {
+    #[command(about = "Filter, project, sort and aggregate CSV records")]
+    Query(CsvQueryOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long, value_parser = parse_data_format, default_value = "csv")]
+    pub format: DataFormat,
+    #[arg(short, long, default_value_t = ',')]
+    pub delimiter: char,
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub header: bool,
+    #[arg(
+        long,
+        help = "Columns, expressions or aggregates, e.g. \"country, avg(age) as age\""
+    )]
+    pub select: Option<String>,
+    #[arg(
+        long = "where",
+        help = "Keep records matching, e.g. \"age > 30 && country == 'CN'\""
+    )]
+    pub filter: Option<String>,
+    #[arg(long, help = "Output columns to sort by, e.g. \"age desc, name\"")]
+    pub sort: Option<String>,
+    #[arg(long)]
+    pub limit: Option<usize>,
+    #[arg(long, help = "Group records by these expressions, e.g. \"country\"")]
+    pub group_by: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -57,3 +162,16 @@ pub enum OutputFormat {
     Yaml,
     Toml,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Null,
+    Bool,
+    Integer,
+    Float,
+    Date,
+    String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema(pub Vec<(String, ColumnType)>);
@@ -0,0 +1,77 @@
+use super::verify_file;
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub struct DiffOpts {
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+    #[arg(short, long, value_parser = parse_diff_format, default_value = "text")]
+    pub format: DiffFormat,
+    #[arg(
+        short,
+        long,
+        help = "Match array items by this field instead of by position, e.g. id or name"
+    )]
+    pub key: Option<String>,
+    #[arg(long, help = "Don't color the text output")]
+    pub no_color: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct PatchOpts {
+    #[arg(value_parser = verify_file)]
+    pub input: String,
+    #[arg(
+        value_parser = verify_file,
+        help = "A JSON Patch (an array of operations) or a JSON Merge Patch (an object)"
+    )]
+    pub patch: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Text,
+    JsonPatch,
+    MergePatch,
+}
+
+fn parse_diff_format(format: &str) -> Result<DiffFormat, RcliError> {
+    format.parse()
+}
+
+impl From<DiffFormat> for &'static str {
+    fn from(format: DiffFormat) -> Self {
+        match format {
+            DiffFormat::Text => "text",
+            DiffFormat::JsonPatch => "json-patch",
+            DiffFormat::MergePatch => "merge-patch",
+        }
+    }
+}
+
+impl FromStr for DiffFormat {
+    type Err = RcliError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(DiffFormat::Text),
+            "json-patch" | "patch" => Ok(DiffFormat::JsonPatch),
+            "merge-patch" | "merge" => Ok(DiffFormat::MergePatch),
+            _ => bail!(InvalidArgument, "Unsupported diff format: {}", format),
+        }
+    }
+}
+
+impl fmt::Display for DiffFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
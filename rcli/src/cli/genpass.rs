@@ -1,15 +1,39 @@
-use clap::Parser;
+use clap::{ArgAction, Parser};
 
 #[derive(Debug, Parser)]
 pub struct GenPassOpts {
     #[arg(short, long, default_value_t = 16)]
     pub length: u8,
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, default_value_t = true, action = ArgAction::Set)]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub lowercase: bool,
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, default_value_t = true, action = ArgAction::Set)]
     pub number: bool,
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, default_value_t = true, action = ArgAction::Set)]
     pub symbol: bool,
+    #[arg(
+        long,
+        help = "Leave out 0, O, 1, l, I and o, the default sets already lack all but 1 and l"
+    )]
+    pub exclude_similar: bool,
+    #[arg(long, help = "Pick characters only from this set")]
+    pub charset: Option<String>,
+    #[arg(
+        short,
+        long,
+        conflicts_with = "charset",
+        help = "Generate a passphrase of N words from the EFF large wordlist"
+    )]
+    pub words: Option<u8>,
+    #[arg(
+        long,
+        default_value = "-",
+        help = "Put between the words of a passphrase"
+    )]
+    pub separator: String,
+    #[arg(long, help = "Capitalize every word of a passphrase")]
+    pub capitalize: bool,
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u16,
 }
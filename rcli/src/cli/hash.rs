@@ -0,0 +1,62 @@
+use super::verify_file;
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub struct HashOpts {
+    #[arg(value_parser = verify_file, default_value = "-", help = "Files or directories, - for stdin")]
+    pub inputs: Vec<String>,
+    #[arg(short, long, value_parser = parse_hash_algorithm, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        conflicts_with = "inputs",
+        help = "Verify the checksums listed in a sha256sum style file"
+    )]
+    pub check: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Sha512,
+}
+
+fn parse_hash_algorithm(algorithm: &str) -> Result<HashAlgorithm, RcliError> {
+    algorithm.parse()
+}
+
+impl From<HashAlgorithm> for &'static str {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = RcliError;
+
+    fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+        match algorithm {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            _ => bail!(InvalidArgument, "Unsupported hash algorithm: {}", algorithm),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -0,0 +1,38 @@
+use super::verify_path;
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub enum HttpSubCommand {
+    #[command(about = "Serve a directory over HTTP")]
+    Serve(HttpServeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct HttpServeOpts {
+    #[arg(short, long, value_parser = verify_path, default_value = ".")]
+    pub dir: PathBuf,
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+    #[arg(long, default_value = "0.0.0.0", help = "Address to listen on")]
+    pub bind: IpAddr,
+    #[arg(
+        long,
+        env = "RCLI_HTTP_AUTH",
+        hide_env_values = true,
+        value_parser = parse_credentials,
+        help = "Require basic auth with these credentials, as user:password"
+    )]
+    pub auth: Option<(String, String)>,
+}
+
+fn parse_credentials(s: &str) -> Result<(String, String), RcliError> {
+    match s.split_once(':') {
+        Some((user, password)) if !user.is_empty() && !password.is_empty() => {
+            Ok((user.to_string(), password.to_string()))
+        }
+        _ => bail!(InvalidArgument, "expected user:password"),
+    }
+}
@@ -0,0 +1,85 @@
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub enum IdSubCommand {
+    #[command(about = "Generate UUIDs, ULIDs, nanoids or snowflake IDs")]
+    Gen(IdGenOpts),
+    #[command(about = "Show the kind, version, variant and timestamp of an ID")]
+    Inspect(IdInspectOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct IdGenOpts {
+    #[arg(short, long, value_parser = parse_id_kind, default_value = "uuid7")]
+    pub kind: IdKind,
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u32,
+    #[arg(short, long, default_value_t = 21, help = "Length of a nanoid")]
+    pub length: usize,
+    #[arg(
+        short,
+        long,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u16).range(0..1024),
+        help = "Machine ID of a snowflake, 0 to 1023"
+    )]
+    pub machine: u16,
+}
+
+#[derive(Debug, Parser)]
+pub struct IdInspectOpts {
+    pub id: String,
+    #[arg(short, long, value_parser = parse_id_kind, help = "Guessed from the ID when omitted")]
+    pub kind: Option<IdKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdKind {
+    Uuid4,
+    Uuid7,
+    Ulid,
+    Nanoid,
+    // twitter's layout: 41 bits of milliseconds, 10 of machine, 12 of sequence
+    Snowflake,
+}
+
+fn parse_id_kind(kind: &str) -> Result<IdKind, RcliError> {
+    kind.parse()
+}
+
+impl From<IdKind> for &'static str {
+    fn from(kind: IdKind) -> Self {
+        match kind {
+            IdKind::Uuid4 => "uuid4",
+            IdKind::Uuid7 => "uuid7",
+            IdKind::Ulid => "ulid",
+            IdKind::Nanoid => "nanoid",
+            IdKind::Snowflake => "snowflake",
+        }
+    }
+}
+
+impl FromStr for IdKind {
+    type Err = RcliError;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind.to_ascii_lowercase().as_str() {
+            "uuid4" | "uuidv4" | "v4" => Ok(IdKind::Uuid4),
+            "uuid7" | "uuidv7" | "v7" | "uuid" => Ok(IdKind::Uuid7),
+            "ulid" => Ok(IdKind::Ulid),
+            "nanoid" => Ok(IdKind::Nanoid),
+            "snowflake" => Ok(IdKind::Snowflake),
+            _ => bail!(InvalidArgument, "Unsupported ID kind: {}", kind),
+        }
+    }
+}
+
+impl fmt::Display for IdKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -0,0 +1,167 @@
+use super::verify_file;
+use crate::error::{RcliError, bail, err};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+pub enum JwtSubCommand {
+    #[command(about = "Sign a JWT")]
+    Sign(JwtSignOpts),
+    #[command(about = "Verify a JWT's signature and claims")]
+    Verify(JwtVerifyOpts),
+    #[command(about = "Print a JWT's header and claims without verifying it")]
+    Decode(JwtDecodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtSignOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    #[arg(long, value_parser = parse_jwt_algorithm, default_value = "eddsa")]
+    pub alg: JwtAlgorithm,
+    #[arg(long)]
+    pub sub: Option<String>,
+    #[arg(long)]
+    pub aud: Option<String>,
+    #[arg(long)]
+    pub iss: Option<String>,
+    #[arg(long, value_parser = parse_duration, help = "Lifetime, e.g. 30m, 12h or 14d")]
+    pub exp: Option<Duration>,
+    #[arg(long, help = "Extra claims as a JSON object")]
+    pub claims: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtVerifyOpts {
+    #[arg(default_value = "-", help = "The token, - for stdin")]
+    pub token: String,
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    #[arg(long, value_parser = parse_jwt_algorithm, default_value = "eddsa")]
+    pub alg: JwtAlgorithm,
+    #[arg(long)]
+    pub aud: Option<String>,
+    #[arg(long)]
+    pub iss: Option<String>,
+    #[arg(long, value_parser = parse_duration, default_value = "60s", help = "Allowed clock skew")]
+    pub leeway: Duration,
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtDecodeOpts {
+    #[arg(default_value = "-", help = "The token, - for stdin")]
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    EdDsa,
+    Hs256,
+    Es256,
+}
+
+fn parse_jwt_algorithm(alg: &str) -> Result<JwtAlgorithm, RcliError> {
+    alg.parse()
+}
+
+// the names used in the JWS "alg" header
+impl From<JwtAlgorithm> for &'static str {
+    fn from(alg: JwtAlgorithm) -> Self {
+        match alg {
+            JwtAlgorithm::EdDsa => "EdDSA",
+            JwtAlgorithm::Hs256 => "HS256",
+            JwtAlgorithm::Es256 => "ES256",
+        }
+    }
+}
+
+impl FromStr for JwtAlgorithm {
+    type Err = RcliError;
+
+    fn from_str(alg: &str) -> Result<Self, Self::Err> {
+        match alg.to_ascii_lowercase().as_str() {
+            "eddsa" | "ed25519" => Ok(JwtAlgorithm::EdDsa),
+            "hs256" => Ok(JwtAlgorithm::Hs256),
+            "es256" => Ok(JwtAlgorithm::Es256),
+            _ => bail!(InvalidArgument, "Unsupported JWT algorithm: {}", alg),
+        }
+    }
+}
+
+impl fmt::Display for JwtAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// "90s", "15m", "1h30m", "14d", "2w"
+pub fn parse_duration(s: &str) -> Result<Duration, RcliError> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => bail!(
+                InvalidArgument,
+                "Invalid duration {}: unknown unit {}",
+                s,
+                c
+            ),
+        };
+        let value: u64 = number.parse().map_err(|_| {
+            err!(
+                InvalidArgument,
+                "Invalid duration {}: a number must precede {}",
+                s,
+                c
+            )
+        })?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| err!(InvalidArgument, "Invalid duration {}: too long", s))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        bail!(
+            InvalidArgument,
+            "Invalid duration {}: missing unit after {}",
+            s,
+            number
+        );
+    }
+    if s.trim().is_empty() {
+        bail!(InvalidArgument, "Invalid duration: empty");
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(
+            parse_duration("14d").unwrap(),
+            Duration::from_secs(14 * 86400)
+        );
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("18446744073709551615w").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use super::verify_file;
+use crate::error::{RcliError, bail};
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub enum KeySubCommand {
+    #[command(about = "Convert an Ed25519 key between raw, PEM, OpenSSH and JWK")]
+    Convert(KeyConvertOpts),
+    #[command(about = "Show the fingerprints of an Ed25519 key")]
+    Fingerprint(KeyFingerprintOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyConvertOpts {
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        default_value = "-",
+        help = "Key in any supported format, raw files ending in .pk are public keys"
+    )]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long = "to", value_parser = parse_key_format)]
+    pub format: KeyFormat,
+    #[arg(long, help = "Write only the public key")]
+    pub public: bool,
+    #[arg(long, default_value = "", help = "Comment of OpenSSH keys")]
+    pub comment: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyFingerprintOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    Raw,
+    Pem,
+    Openssh,
+    Jwk,
+}
+
+fn parse_key_format(format: &str) -> Result<KeyFormat, RcliError> {
+    format.parse()
+}
+
+impl From<KeyFormat> for &'static str {
+    fn from(format: KeyFormat) -> Self {
+        match format {
+            KeyFormat::Raw => "raw",
+            KeyFormat::Pem => "pem",
+            KeyFormat::Openssh => "openssh",
+            KeyFormat::Jwk => "jwk",
+        }
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = RcliError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "raw" => Ok(KeyFormat::Raw),
+            "pem" | "pkcs8" => Ok(KeyFormat::Pem),
+            "openssh" | "ssh" => Ok(KeyFormat::Openssh),
+            "jwk" => Ok(KeyFormat::Jwk),
+            _ => bail!(InvalidArgument, "Unsupported key format: {}", format),
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -1,13 +1,32 @@
 mod base64;
+mod codec;
+mod config;
+pub mod convert;
 pub mod csv;
+mod diff;
 pub mod genpass;
+mod hash;
+mod http;
+mod id;
+mod jwt;
+mod key;
+mod query;
+mod schema;
 pub mod text;
+mod time;
 
 pub use self::{
-    base64::Base64Format, base64::Base64SubCommand, csv::OutputFormat, text::TextSignFormat,
-    text::TextSignOpts,
+    base64::Base64Format, base64::Base64SubCommand, codec::Codec, codec::DecodeOpts,
+    codec::EncodeOpts, config::ConfigShowOpts, config::ConfigSubCommand, convert::DataFormat,
+    csv::ColumnType, csv::CsvSubCommand, csv::OutputFormat, csv::Schema, diff::DiffFormat,
+    diff::DiffOpts, diff::PatchOpts, hash::HashAlgorithm, http::HttpServeOpts,
+    http::HttpSubCommand, id::IdGenOpts, id::IdInspectOpts, id::IdKind, id::IdSubCommand,
+    jwt::JwtAlgorithm, jwt::JwtSignOpts, jwt::JwtSubCommand, jwt::JwtVerifyOpts, key::KeyFormat,
+    key::KeySubCommand, query::QueryOpts, query::SetOpts, schema::SchemaInferOpts,
+    schema::SchemaSubCommand, schema::SchemaValidateOpts, text::KeyGenFormat, text::TextSignFormat,
+    text::TextSignOpts, time::EpochUnit, time::TimeFormat, time::TimeOpts, time::Zone,
 };
-use self::{csv::CsvOpts, genpass::GenPassOpts};
+use self::{convert::ConvertOpts, csv::CsvOpts, genpass::GenPassOpts, hash::HashOpts};
 use crate::cli::text::TextSubCommand;
 use clap::Parser;
 use std::path::{Path, PathBuf};
@@ -34,12 +53,72 @@ pub fn verify_path(path: &str) -> Result<PathBuf, &'static str> {
 pub enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert CSV to other formats")]
     Csv(CsvOpts),
+    #[command(
+        name = "convert",
+        about = "Convert between CSV, JSON, NDJSON, YAML and TOML"
+    )]
+    Convert(ConvertOpts),
     #[command(name = "genpass", about = "Generate a random password")]
     GenPass(GenPassOpts),
+    #[command(
+        name = "hash",
+        about = "Print or check blake3, sha256 or sha512 checksums"
+    )]
+    Hash(HashOpts),
+    #[command(
+        name = "query",
+        about = "Query JSON, YAML or TOML with a jq-like filter"
+    )]
+    Query(QueryOpts),
+    #[command(name = "set", about = "Set a value in a JSON, YAML or TOML file")]
+    Set(SetOpts),
+    #[command(
+        name = "diff",
+        about = "Compare two JSON, YAML or TOML documents, whatever their formats"
+    )]
+    Diff(DiffOpts),
+    #[command(
+        name = "patch",
+        about = "Apply a JSON Patch or JSON Merge Patch to a JSON, YAML or TOML document"
+    )]
+    Patch(PatchOpts),
+    #[command(
+        subcommand,
+        about = "Infer JSON Schemas from data, or validate data against them"
+    )]
+    Schema(SchemaSubCommand),
+    #[command(
+        subcommand,
+        about = "Generate and inspect UUIDs, ULIDs, nanoids and snowflakes"
+    )]
+    Id(IdSubCommand),
+    #[command(
+        name = "time",
+        about = "Convert between epoch times, RFC 3339 and RFC 2822 across time zones"
+    )]
+    Time(TimeOpts),
+    #[command(
+        subcommand,
+        about = "Show the defaults set by config files and RCLI_* variables"
+    )]
+    Config(ConfigSubCommand),
     #[command(subcommand)]
     Base64(Base64SubCommand),
+    #[command(
+        name = "encode",
+        about = "Encode as hex, base32, base58, base64, ascii85, z85 or percent"
+    )]
+    Encode(EncodeOpts),
+    #[command(name = "decode", about = "Decode what `rcli encode` writes")]
+    Decode(DecodeOpts),
     #[command(subcommand)]
     Text(TextSubCommand),
+    #[command(subcommand, about = "Convert keys and show their fingerprints")]
+    Key(KeySubCommand),
+    #[command(subcommand, about = "Sign, verify and decode JSON Web Tokens")]
+    Jwt(JwtSubCommand),
+    #[command(subcommand, about = "Serve files over HTTP")]
+    Http(HttpSubCommand),
 }
 
 #[derive(Debug, Parser)]
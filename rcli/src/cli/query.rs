@@ -0,0 +1,38 @@
+use super::convert::DataFormat;
+use super::verify_file;
+use crate::error::RcliError;
+use clap::Parser;
+
+fn parse_data_format(format: &str) -> Result<DataFormat, RcliError> {
+    format.parse()
+}
+
+#[derive(Debug, Parser)]
+pub struct QueryOpts {
+    #[arg(help = "e.g. '.users[].email', '.items[?price > 10] | length' or 'map(.name)'")]
+    pub filter: String,
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = parse_data_format, help = "Input format, guessed from the input extension when omitted")]
+    pub from: Option<DataFormat>,
+    #[arg(short, long, help = "Print strings without quotes")]
+    pub raw: bool,
+    #[arg(short, long, help = "Print each result on one line")]
+    pub compact: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct SetOpts {
+    #[arg(help = "e.g. '.server.port', '.users[0].name' or '.items[?price > 10].sale'")]
+    pub path: String,
+    #[arg(help = "A JSON value, anything that isn't valid JSON is a string")]
+    pub value: String,
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = parse_data_format, help = "Input format, guessed from the input extension when omitted")]
+    pub from: Option<DataFormat>,
+    #[arg(short, long, help = "Always set the value as a string")]
+    pub string: bool,
+    #[arg(short, long, help = "Write here instead of editing the input in place")]
+    pub output: Option<String>,
+}
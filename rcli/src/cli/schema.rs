@@ -0,0 +1,34 @@
+use super::convert::DataFormat;
+use super::verify_file;
+use crate::error::RcliError;
+use clap::Parser;
+
+fn parse_data_format(format: &str) -> Result<DataFormat, RcliError> {
+    format.parse()
+}
+
+#[derive(Debug, Parser)]
+pub enum SchemaSubCommand {
+    #[command(about = "Print a JSON Schema (draft 2020-12) that the input matches")]
+    Infer(SchemaInferOpts),
+    #[command(about = "Check every record of the input against a JSON Schema")]
+    Validate(SchemaValidateOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct SchemaInferOpts {
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = parse_data_format, help = "Input format, guessed from the input extension when omitted")]
+    pub from: Option<DataFormat>,
+}
+
+#[derive(Debug, Parser)]
+pub struct SchemaValidateOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub schema: String,
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = parse_data_format, help = "Input format, guessed from the input extension when omitted")]
+    pub from: Option<DataFormat>,
+}
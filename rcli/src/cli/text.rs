@@ -1,4 +1,5 @@
 use super::{verify_file, verify_path};
+use crate::error::{RcliError, bail};
 use clap::Parser;
 use std::fmt;
 use std::fmt::Formatter;
@@ -13,6 +14,14 @@ pub enum TextSubCommand {
     Verify(TextVerifyOpts),
     #[command(about = "Generate a new key")]
     Generate(TextKeyGenerateOpts),
+    #[command(about = "Encrypt a file with ChaCha20-Poly1305")]
+    Encrypt(TextEncryptOpts),
+    #[command(about = "Decrypt a file encrypted by `rcli text encrypt`")]
+    Decrypt(TextDecryptOpts),
+    #[command(about = "Encrypt a file to the X25519 public keys of its recipients")]
+    Seal(TextSealOpts),
+    #[command(about = "Decrypt a sealed file with an X25519 private key")]
+    Open(TextOpenOpts),
 }
 
 #[derive(Debug, Parser)]
@@ -23,41 +32,114 @@ pub struct TextSignOpts {
     pub key: String,
     #[arg(long, value_parser = parse_format, default_value = "blake3")]
     pub format: TextSignFormat,
+    #[arg(
+        short,
+        long,
+        help = "Where to write the signature, <input>.sig by default, stdout for stdin"
+    )]
+    pub output: Option<String>,
+    #[arg(short, long, help = "Trusted comment, covered by the signature")]
+    pub comment: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct TextVerifyOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
-    #[arg(long, value_parser = parse_format, default_value = "blake3")]
-    pub format: TextSignFormat,
-    #[arg(short, long)]
-    pub sig: String,
+    #[arg(short, long, value_parser = verify_file, help = "Signature file, <input>.sig by default")]
+    pub sig: Option<String>,
+    #[arg(short, long, value_parser = verify_file, help = "Use this key instead of the keyring")]
+    pub key: Option<String>,
+    #[arg(
+        long,
+        env = "RCLI_KEYRING",
+        help = "Directory of keys, ~/.config/rcli/keyring by default"
+    )]
+    pub keyring: Option<PathBuf>,
 }
 
 #[derive(Debug, Parser)]
 pub struct TextKeyGenerateOpts {
-    #[arg(short, long, default_value = "blake3", value_parser = parse_format)]
-    pub format: TextSignFormat,
+    #[arg(short, long, default_value = "blake3", value_parser = parse_key_gen_format)]
+    pub format: KeyGenFormat,
     #[arg(short, long, value_parser = verify_path)]
     pub output: PathBuf,
 }
 
+#[derive(Debug, Parser)]
+pub struct TextEncryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = verify_file, conflicts_with = "passphrase")]
+    pub key: Option<String>,
+    #[arg(long, env = "RCLI_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+    #[arg(long, help = "Write base64 text instead of raw bytes")]
+    pub base64: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextDecryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = verify_file, conflicts_with = "passphrase")]
+    pub key: Option<String>,
+    #[arg(long, env = "RCLI_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextSealOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(
+        short,
+        long = "recipient",
+        required = true,
+        help = "Public key file, or the key in base64"
+    )]
+    pub recipients: Vec<String>,
+    #[arg(long, help = "Write base64 text instead of raw bytes")]
+    pub base64: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextOpenOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long = "identity", required = true, value_parser = verify_file)]
+    pub identities: Vec<String>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum TextSignFormat {
     Blake3,
     Ed25519,
 }
 
+// the signing formats plus X25519, whose keys only seal and open
+#[derive(Debug, Clone, Copy)]
+pub enum KeyGenFormat {
+    Blake3,
+    Ed25519,
+    X25519,
+}
+
 impl fmt::Display for TextSignFormat {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
 
-fn parse_format(format: &str) -> Result<TextSignFormat, anyhow::Error> {
+fn parse_format(format: &str) -> Result<TextSignFormat, RcliError> {
     format.parse()
 }
 
@@ -71,13 +153,46 @@ impl From<TextSignFormat> for &'static str {
 }
 
 impl FromStr for TextSignFormat {
-    type Err = anyhow::Error;
+    type Err = RcliError;
 
     fn from_str(format: &str) -> Result<Self, Self::Err> {
         match format {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::Ed25519),
-            _ => anyhow::bail!("Unsupported format: {}", format),
+            _ => bail!(InvalidArgument, "Unsupported format: {}", format),
+        }
+    }
+}
+
+fn parse_key_gen_format(format: &str) -> Result<KeyGenFormat, RcliError> {
+    format.parse()
+}
+
+impl From<KeyGenFormat> for &'static str {
+    fn from(format: KeyGenFormat) -> Self {
+        match format {
+            KeyGenFormat::Blake3 => "blake3",
+            KeyGenFormat::Ed25519 => "ed25519",
+            KeyGenFormat::X25519 => "x25519",
         }
     }
 }
+
+impl FromStr for KeyGenFormat {
+    type Err = RcliError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "blake3" => Ok(KeyGenFormat::Blake3),
+            "ed25519" => Ok(KeyGenFormat::Ed25519),
+            "x25519" => Ok(KeyGenFormat::X25519),
+            _ => bail!(InvalidArgument, "Unsupported format: {}", format),
+        }
+    }
+}
+
+impl fmt::Display for KeyGenFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
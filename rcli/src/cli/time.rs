@@ -0,0 +1,172 @@
+use crate::error::{RcliError, bail, err};
+use chrono::FixedOffset;
+use chrono_tz::Tz;
+use clap::Parser;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Parser)]
+pub struct TimeOpts {
+    #[arg(
+        default_value = "now",
+        allow_hyphen_values = true,
+        help = "Epoch seconds, ms, us or ns, RFC 3339, RFC 2822, a date, or now with offsets like now-7d+2h"
+    )]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_epoch_unit,
+        help = "Unit of an epoch input, guessed from its size when omitted"
+    )]
+    pub unit: Option<EpochUnit>,
+    #[arg(
+        long,
+        value_parser = parse_zone,
+        default_value = "UTC",
+        help = "Time zone of an input without an offset"
+    )]
+    pub from_tz: Zone,
+    #[arg(
+        short = 'z',
+        long,
+        value_parser = parse_zone,
+        default_value = "UTC",
+        help = "Time zone to show, e.g. Asia/Shanghai, +08:00 or local; repeat for several"
+    )]
+    pub tz: Vec<Zone>,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_time_format,
+        help = "Print only this: s, ms, us, ns, rfc3339, rfc2822 or human"
+    )]
+    pub to: Option<TimeFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpochUnit {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFormat {
+    Epoch(EpochUnit),
+    Rfc3339,
+    Rfc2822,
+    // relative to now, like 3 days ago
+    Human,
+}
+
+fn parse_zone(zone: &str) -> Result<Zone, RcliError> {
+    zone.parse()
+}
+
+fn parse_epoch_unit(unit: &str) -> Result<EpochUnit, RcliError> {
+    unit.parse()
+}
+
+fn parse_time_format(format: &str) -> Result<TimeFormat, RcliError> {
+    format.parse()
+}
+
+impl FromStr for Zone {
+    type Err = RcliError;
+
+    fn from_str(zone: &str) -> Result<Self, Self::Err> {
+        if zone.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if let Ok(tz) = Tz::from_str_insensitive(zone) {
+            return Ok(Zone::Named(tz));
+        }
+        zone.parse()
+            .map(Zone::Fixed)
+            .map_err(|_| err!(InvalidArgument, "Unknown time zone: {}", zone))
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Zone::Local => write!(f, "local"),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+            Zone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl From<EpochUnit> for &'static str {
+    fn from(unit: EpochUnit) -> Self {
+        match unit {
+            EpochUnit::Seconds => "s",
+            EpochUnit::Millis => "ms",
+            EpochUnit::Micros => "us",
+            EpochUnit::Nanos => "ns",
+        }
+    }
+}
+
+impl FromStr for EpochUnit {
+    type Err = RcliError;
+
+    fn from_str(unit: &str) -> Result<Self, Self::Err> {
+        match unit {
+            "s" | "sec" | "seconds" => Ok(EpochUnit::Seconds),
+            "ms" | "millis" => Ok(EpochUnit::Millis),
+            "us" | "micros" => Ok(EpochUnit::Micros),
+            "ns" | "nanos" => Ok(EpochUnit::Nanos),
+            _ => bail!(InvalidArgument, "Unsupported epoch unit: {}", unit),
+        }
+    }
+}
+
+impl fmt::Display for EpochUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<TimeFormat> for &'static str {
+    fn from(format: TimeFormat) -> Self {
+        match format {
+            TimeFormat::Epoch(unit) => unit.into(),
+            TimeFormat::Rfc3339 => "rfc3339",
+            TimeFormat::Rfc2822 => "rfc2822",
+            TimeFormat::Human => "human",
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = RcliError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "rfc3339" | "iso8601" => Ok(TimeFormat::Rfc3339),
+            "rfc2822" => Ok(TimeFormat::Rfc2822),
+            "human" => Ok(TimeFormat::Human),
+            format => format
+                .parse()
+                .map(TimeFormat::Epoch)
+                .map_err(|_| err!(InvalidArgument, "Unsupported time format: {}", format)),
+        }
+    }
+}
+
+impl fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
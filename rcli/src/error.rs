@@ -0,0 +1,127 @@
+use std::error::Error as StdError;
+use thiserror::Error;
+
+// the library error behind one of ours, kept for `source()` so the message
+// doesn't have to repeat it
+pub type Cause = Box<dyn StdError + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum RcliError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("TOML error: {0}")]
+    TomlRead(#[from] toml::de::Error),
+    #[error("TOML error: {0}")]
+    TomlWrite(#[from] toml::ser::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    // a flag or option value that doesn't parse
+    #[error("{message}")]
+    InvalidArgument {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+    // input that isn't what the subcommand reads
+    #[error("{message}")]
+    InvalidInput {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+    // a query, set path or csv expression
+    #[error("{message}")]
+    InvalidQuery {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+    #[error("{message}")]
+    InvalidKey {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+    #[error("{message}")]
+    InvalidConfig {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+    // wrong key, or the data was tampered with
+    #[error("{message}")]
+    DecryptionFailed {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+    #[error("{message}")]
+    VerificationFailed {
+        message: String,
+        #[source]
+        source: Option<Cause>,
+    },
+}
+
+pub type Result<T, E = RcliError> = std::result::Result<T, E>;
+
+// the message followed by every cause, "a: b: c", for places that print
+// errors themselves instead of returning them to main
+pub fn error_chain(e: &(dyn StdError + 'static)) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+// err!(InvalidInput, "bad {}", x) builds an RcliError::InvalidInput,
+// err!(InvalidInput, source = e, "bad {}", x) keeps `e` as its cause
+macro_rules! err {
+    ($kind:ident, source = $source:expr, $($arg:tt)+) => {
+        $crate::error::RcliError::$kind {
+            message: format!($($arg)+),
+            source: Some($source.into()),
+        }
+    };
+    ($kind:ident, $($arg:tt)+) => {
+        $crate::error::RcliError::$kind {
+            message: format!($($arg)+),
+            source: None,
+        }
+    };
+}
+
+macro_rules! bail {
+    ($kind:ident, $($arg:tt)+) => {
+        return Err($crate::error::err!($kind, $($arg)+))
+    };
+}
+
+pub(crate) use {bail, err};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_causes_are_kept() {
+        let cause = "x".parse::<u64>().unwrap_err();
+        let e = err!(InvalidInput, source = cause.clone(), "Invalid snowflake");
+        assert_eq!(e.to_string(), "Invalid snowflake");
+        let source = e.source().expect("a source");
+        assert_eq!(source.to_string(), cause.to_string());
+        assert_eq!(
+            error_chain(&e),
+            "Invalid snowflake: invalid digit found in string"
+        );
+        assert!(err!(InvalidInput, "no cause").source().is_none());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod cli;
+pub mod error;
 pub mod process;
-mod utils;
+
+pub use error::{RcliError, Result};
@@ -1,67 +1,498 @@
-use clap::Parser;
+// the binary only turns arguments into readers and writers for the
+// functions in rcli::process, and prints what they return
+use anyhow::{Context, anyhow};
+use clap::{CommandFactory, FromArgMatches};
 use rcli::cli::text::TextSubCommand;
-use rcli::cli::{Base64SubCommand, TextSignFormat};
+use rcli::cli::{
+    Base64SubCommand, ConfigSubCommand, CsvSubCommand, DataFormat, HttpSubCommand, IdSubCommand,
+    JwtSubCommand, KeyGenFormat, KeySubCommand, SchemaSubCommand,
+};
+use rcli::error::error_chain;
 use rcli::process::{
-    process_decode, process_encode, process_text_generate, process_text_sign, process_text_verify,
+    Config, Identity, Recipient, Secret, SignatureEnvelope, SignedData, default_keyring,
+    hash_reader, input_format, process_codec_decode, process_codec_encode, process_config_show,
+    process_convert, process_csv_query, process_decode, process_diff, process_encode, process_hash,
+    process_hash_check, process_http_serve, process_id_gen, process_id_inspect, process_jwt_decode,
+    process_jwt_sign, process_jwt_verify, process_key_convert, process_key_fingerprint,
+    process_patch, process_query, process_schema_infer, process_schema_validate, process_set,
+    process_text_decrypt, process_text_encrypt, process_text_generate, process_text_open,
+    process_text_seal, process_text_sign, process_text_verify, process_time, read_value,
 };
-use rcli::{cli::Opts, cli::SubCommand, process::process_csv, process::process_genpass};
-use std::fs;
+use rcli::{cli::Opts, cli::SubCommand, process::process_csv, process::process_genpass_opts};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
 
 fn main() -> anyhow::Result<()> {
-    let cli = Opts::parse();
+    // config files and RCLI_* variables become the defaults of the options.
+    // Their errors wait until clap has handled --help and --version
+    let mut config = Config::load(&Opts::command());
+    let matches = config.apply(Opts::command()).get_matches();
+    let cli = Opts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if !matches!(cli.cmd, SubCommand::Config(_)) {
+        config.check()?;
+    }
     match cli.cmd {
-        SubCommand::Csv(opts) => {
-            let output = if let Some(output) = opts.output {
-                output.clone()
+        SubCommand::Csv(opts) => match opts.cmd {
+            Some(CsvSubCommand::Query(opts)) => {
+                let reader = open(&opts.input)?;
+                with_output(&opts.output, |writer| {
+                    process_csv_query(reader, writer, &opts)
+                })?;
+            }
+            None => {
+                let input = opts.input.ok_or_else(|| anyhow!("--input is required"))?;
+                let output = if let Some(output) = opts.output {
+                    output.clone()
+                } else {
+                    format!("output.{}", opts.format)
+                };
+                let reader = open(&input)?;
+                with_output(&output, |writer| {
+                    process_csv(
+                        reader,
+                        writer,
+                        opts.format,
+                        opts.delimiter,
+                        opts.header,
+                        opts.schema,
+                    )
+                })?;
+            }
+        },
+        SubCommand::Convert(opts) => {
+            let from = opts
+                .from
+                .or_else(|| DataFormat::from_path(&opts.input))
+                .ok_or_else(|| anyhow!("Cannot tell the format of {}, use --from", opts.input))?;
+            let to = opts
+                .to
+                .or_else(|| DataFormat::from_path(&opts.output))
+                .ok_or_else(|| anyhow!("Cannot tell the format of {}, use --to", opts.output))?;
+            let reader = open(&opts.input)?;
+            with_output(&opts.output, |writer| {
+                process_convert(reader, writer, from, to)
+            })?;
+        }
+        SubCommand::GenPass(opts) => {
+            // the estimates go to stderr so the passwords can be piped
+            for generated in process_genpass_opts(&opts)? {
+                println!("{}", generated.password);
+                eprintln!(
+                    "entropy: {:.1} bits, strength: {}/4, cracked in {}",
+                    generated.entropy, generated.score, generated.crack_time
+                );
+            }
+        }
+        SubCommand::Hash(opts) => {
+            if let Some(checklist) = opts.check {
+                let results = process_hash_check(open(&checklist)?, opts.algorithm)?;
+                let failed = results
+                    .iter()
+                    .filter(|(_, r)| !matches!(r, Ok(true)))
+                    .count();
+                for (path, result) in results {
+                    match result {
+                        Ok(true) => println!("{}: OK", path),
+                        Ok(false) => println!("{}: FAILED", path),
+                        Err(e) => println!("{}: FAILED open or read ({})", path, e),
+                    }
+                }
+                if failed > 0 {
+                    anyhow::bail!("{} computed checksum(s) did NOT match", failed);
+                }
+            } else {
+                for input in &opts.inputs {
+                    let checksums = if input == "-" {
+                        vec![(input.clone(), hash_reader(opts.algorithm, io::stdin())?)]
+                    } else {
+                        process_hash(std::slice::from_ref(input), opts.algorithm)?
+                    };
+                    for (path, checksum) in checksums {
+                        println!("{}  {}", checksum, path);
+                    }
+                }
+            }
+        }
+        SubCommand::Query(opts) => {
+            let format = input_format(&opts.input, opts.from)?;
+            for value in process_query(&opts.filter, open(&opts.input)?, format)? {
+                match value {
+                    serde_json::Value::String(s) if opts.raw => println!("{}", s),
+                    value if opts.compact => println!("{}", value),
+                    value => println!("{}", serde_json::to_string_pretty(&value)?),
+                }
+            }
+        }
+        SubCommand::Set(opts) => {
+            let value = if opts.string {
+                serde_json::Value::String(opts.value)
             } else {
-                format!("output.{}", opts.format)
+                serde_json::from_str(&opts.value).unwrap_or(serde_json::Value::String(opts.value))
             };
-            process_csv(&opts.input, output, opts.format)?;
+            let format = input_format(&opts.input, opts.from)?;
+            let mut content = Vec::new();
+            process_set(&opts.path, value, open(&opts.input)?, &mut content, format)?;
+            match opts.output.as_deref().unwrap_or(&opts.input) {
+                "-" => io::stdout().write_all(&content)?,
+                output => replace_file(Path::new(output), &content)?,
+            }
         }
-        SubCommand::GenPass(opts) => {
-            let password = process_genpass(
-                opts.length,
-                opts.lowercase,
-                opts.uppercase,
-                opts.number,
-                opts.symbol,
+        SubCommand::Diff(opts) => {
+            let color = !opts.no_color
+                && std::io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none();
+            let (output, changed) = process_diff(
+                &read_document(&opts.old)?,
+                &read_document(&opts.new)?,
+                opts.key.as_deref(),
+                opts.format,
+                color,
+            )?;
+            print!("{}", output);
+            // like diff(1), exit 1 when the documents differ
+            if changed {
+                std::process::exit(1);
+            }
+        }
+        SubCommand::Patch(opts) => {
+            let format = input_format(&opts.input, None)?;
+            let patch = read_document(&opts.patch)?;
+            let reader = open(&opts.input)?;
+            with_output(&opts.output, |writer| {
+                process_patch(reader, format, &patch, writer)
+            })?;
+        }
+        SubCommand::Schema(subcmd) => match subcmd {
+            SchemaSubCommand::Infer(opts) => {
+                let format = input_format(&opts.input, opts.from)?;
+                let schema = process_schema_infer(open(&opts.input)?, format)?;
+                println!("{}", serde_json::to_string_pretty(&schema)?);
+            }
+            SchemaSubCommand::Validate(opts) => {
+                let schema = read_document(&opts.schema)?;
+                let format = input_format(&opts.input, opts.from)?;
+                let (records, violations) =
+                    process_schema_validate(&schema, open(&opts.input)?, format)?;
+                for violation in &violations {
+                    println!("{}", violation);
+                }
+                if !violations.is_empty() {
+                    eprintln!("{} violations in {} records", violations.len(), records);
+                    std::process::exit(1);
+                }
+                eprintln!("{} records are valid", records);
+            }
+        },
+        SubCommand::Id(subcmd) => match subcmd {
+            IdSubCommand::Gen(opts) => {
+                for id in process_id_gen(opts.kind, opts.count, opts.length, opts.machine)? {
+                    println!("{}", id);
+                }
+            }
+            IdSubCommand::Inspect(opts) => {
+                for (label, value) in process_id_inspect(&opts.id, opts.kind)? {
+                    println!("{:<9}  {}", label, value);
+                }
+            }
+        },
+        SubCommand::Time(opts) => {
+            print!(
+                "{}",
+                process_time(&opts.input, opts.unit, opts.from_tz, &opts.tz, opts.to)?
+            );
+        }
+        SubCommand::Config(ConfigSubCommand::Show(opts)) => {
+            print!(
+                "{}",
+                process_config_show(&config, &Opts::command(), opts.all)
             );
-            if let Ok(password) = password {
-                println!("{}", password);
+            let errors = config.errors();
+            if !errors.is_empty() {
+                let errors = errors.iter().map(|e| error_chain(e)).collect::<Vec<_>>();
+                anyhow::bail!("{}", errors.join("\n"));
             }
         }
         SubCommand::Base64(subcmd) => match subcmd {
             Base64SubCommand::Encode(opts) => {
-                process_encode(opts.input, opts.format)?;
+                let reader = open(&opts.input)?;
+                with_output("-", |writer| process_encode(reader, writer, opts.format))?;
             }
             Base64SubCommand::Decode(opts) => {
-                process_decode(opts.input, opts.format)?;
+                let reader = open(&opts.input)?;
+                with_output("-", |writer| process_decode(reader, writer, opts.format))?;
             }
         },
+        SubCommand::Encode(opts) => {
+            let reader = open(&opts.input)?;
+            with_output(&opts.output, |writer| {
+                process_codec_encode(reader, writer, opts.codec, opts.wrap)
+            })?;
+        }
+        SubCommand::Decode(opts) => {
+            let reader = open(&opts.input)?;
+            with_output(&opts.output, |writer| {
+                process_codec_decode(reader, writer, opts.codec)
+            })?;
+        }
         SubCommand::Text(subcmd) => match subcmd {
             TextSubCommand::Sign(opts) => {
-                let sig = process_text_sign(opts.input, opts.key, opts.format)?;
-                println!("sig: {}", sig);
+                let output = match opts.output {
+                    Some(output) => output,
+                    None if opts.input == "-" => "-".to_string(),
+                    None => format!("{}.sig", opts.input),
+                };
+                let key = fs::read(&opts.key)?;
+                let envelope =
+                    process_text_sign(signed_data(&opts.input), &key, opts.format, opts.comment)?;
+                let envelope = serde_json::to_string_pretty(&envelope)?;
+                if output == "-" {
+                    println!("{}", envelope);
+                } else {
+                    fs::write(&output, envelope + "\n")?;
+                    eprintln!("signature written to {}", output);
+                }
             }
             TextSubCommand::Verify(opts) => {
-                let verified = process_text_verify(opts.input, opts.key, opts.format, opts.sig)?;
+                let sig = match opts.sig {
+                    Some(sig) => sig,
+                    None if opts.input == "-" => anyhow::bail!("--sig is required for stdin"),
+                    None => format!("{}.sig", opts.input),
+                };
+                let envelope: SignatureEnvelope = serde_json::from_str(&fs::read_to_string(&sig)?)
+                    .with_context(|| format!("{} is not a signature file", sig))?;
+                let keyring = opts.keyring.unwrap_or_else(default_keyring);
+                let key = opts.key.map(fs::read).transpose()?;
+                let verified = process_text_verify(
+                    signed_data(&opts.input),
+                    &envelope,
+                    key.as_deref(),
+                    &keyring,
+                )?;
                 println!("verified: {}", verified);
+                if verified {
+                    println!("key id: {} ({})", envelope.key_id, envelope.algorithm);
+                    println!("timestamp: {}", envelope.timestamp);
+                    if let Some(comment) = envelope.trusted_comment {
+                        println!("trusted comment: {}", comment);
+                    }
+                } else {
+                    std::process::exit(1);
+                }
             }
             TextSubCommand::Generate(opts) => {
                 let key = process_text_generate(opts.format)?;
+                let dir = &opts.output;
                 match opts.format {
-                    TextSignFormat::Blake3 => {
-                        let filename = opts.output.join("blake3.txt");
-                        fs::write(filename, &key[0])?;
+                    KeyGenFormat::Blake3 => {
+                        write_key_file(&dir.join("blake3.txt"), &key[0], true)?;
                     }
-                    TextSignFormat::Ed25519 => {
-                        let filename = &opts.output;
-                        fs::write(filename.join("ed25519.sk"), &key[0])?;
-                        fs::write(filename.join("ed25519.pk"), &key[1])?;
+                    KeyGenFormat::Ed25519 => {
+                        write_key_file(&dir.join("ed25519.sk"), &key[0], true)?;
+                        write_key_file(&dir.join("ed25519.pk"), &key[1], false)?;
+                    }
+                    KeyGenFormat::X25519 => {
+                        write_key_file(&dir.join("x25519.sk"), &key[0], true)?;
+                        write_key_file(&dir.join("x25519.pk"), &key[1], false)?;
                     }
                 }
             }
+            TextSubCommand::Encrypt(opts) => {
+                let secret = Secret::resolve(opts.key, opts.passphrase, true)?;
+                let reader = open(&opts.input)?;
+                with_output(&opts.output, |writer| {
+                    process_text_encrypt(reader, writer, &secret, opts.base64)
+                })?;
+            }
+            TextSubCommand::Decrypt(opts) => {
+                let secret = Secret::resolve(opts.key, opts.passphrase, false)?;
+                let reader = open(&opts.input)?;
+                with_output(&opts.output, |writer| {
+                    process_text_decrypt(reader, writer, &secret)
+                })?;
+            }
+            TextSubCommand::Seal(opts) => {
+                let recipients = opts
+                    .recipients
+                    .iter()
+                    .map(|spec| Recipient::load(spec))
+                    .collect::<rcli::Result<Vec<_>>>()?;
+                let reader = open(&opts.input)?;
+                with_output(&opts.output, |writer| {
+                    process_text_seal(reader, writer, &recipients, opts.base64)
+                })?;
+            }
+            TextSubCommand::Open(opts) => {
+                let identities = opts
+                    .identities
+                    .iter()
+                    .map(|spec| Identity::load(spec))
+                    .collect::<rcli::Result<Vec<_>>>()?;
+                let reader = open(&opts.input)?;
+                with_output(&opts.output, |writer| {
+                    process_text_open(reader, writer, &identities)
+                })?;
+            }
         },
+        SubCommand::Key(subcmd) => match subcmd {
+            KeySubCommand::Convert(opts) => {
+                let key = process_key_convert(
+                    &read_key(&opts.input)?,
+                    opts.input.ends_with(".pk"),
+                    opts.format,
+                    opts.public,
+                    &opts.comment,
+                )?;
+                if opts.output == "-" {
+                    std::io::stdout().write_all(&key)?;
+                } else {
+                    write_key_file(Path::new(&opts.output), &key, !opts.public)?;
+                }
+            }
+            KeySubCommand::Fingerprint(opts) => {
+                let fingerprints =
+                    process_key_fingerprint(&read_key(&opts.input)?, opts.input.ends_with(".pk"))?;
+                println!("key id: {}", fingerprints.key_id);
+                println!("openssh: {}", fingerprints.openssh);
+                println!("jwk thumbprint: {}", fingerprints.jwk);
+            }
+        },
+        SubCommand::Jwt(subcmd) => match subcmd {
+            JwtSubCommand::Sign(opts) => {
+                let key = Zeroizing::new(fs::read(&opts.key)?);
+                println!("{}", process_jwt_sign(&key, &opts)?);
+            }
+            JwtSubCommand::Verify(opts) => {
+                let key = Zeroizing::new(fs::read(&opts.key)?);
+                let token = process_jwt_verify(&read_token(&opts.token)?, &key, &opts)
+                    .context("token is invalid")?;
+                println!("verified: true");
+                println!("{}", serde_json::to_string_pretty(&token.claims)?);
+            }
+            JwtSubCommand::Decode(opts) => {
+                let token = process_jwt_decode(&read_token(&opts.token)?)?;
+                println!("{}", serde_json::to_string_pretty(&token.header)?);
+                println!("{}", serde_json::to_string_pretty(&token.claims)?);
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)?
+                    .as_secs();
+                for line in token.times(now) {
+                    println!("# {}", line);
+                }
+            }
+        },
+        SubCommand::Http(subcmd) => match subcmd {
+            HttpSubCommand::Serve(opts) => {
+                tracing_subscriber::fmt::init();
+                let addr = std::net::SocketAddr::new(opts.bind, opts.port);
+                tokio::runtime::Runtime::new()?
+                    .block_on(process_http_serve(opts.dir, addr, opts.auth))?;
+            }
+        },
+    }
+    Ok(())
+}
+
+// "-" is stdin
+fn open(input: &str) -> anyhow::Result<Box<dyn Read>> {
+    let reader: Box<dyn Read> = if input == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(input).with_context(|| format!("Cannot open {}", input))?)
+    };
+    Ok(reader)
+}
+
+// write to stdout or a file, a half written file is removed on error so a
+// tampered ciphertext never leaves partial plaintext behind
+fn with_output(
+    output: &str,
+    f: impl FnOnce(&mut dyn Write) -> rcli::Result<()>,
+) -> anyhow::Result<()> {
+    if output == "-" {
+        let mut stdout = io::stdout().lock();
+        f(&mut stdout)?;
+        return Ok(stdout.flush()?);
+    }
+    let mut writer = BufWriter::new(File::create(output)?);
+    let result = f(&mut writer).and_then(|_| Ok(writer.flush()?));
+    if result.is_err() {
+        drop(writer);
+        let _ = fs::remove_file(output);
+    }
+    Ok(result?)
+}
+
+// write next to `path` and rename over it once everything is written, keeping
+// the permissions of the file being replaced
+fn replace_file(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Cannot write to {}", dir.display()))?;
+    tmp.write_all(content)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        tmp.as_file().set_permissions(metadata.permissions())?;
+    }
+    tmp.persist(path)?;
+    Ok(())
+}
+
+// a JSON, YAML or TOML document, by its extension
+fn read_document(path: &str) -> anyhow::Result<Value> {
+    let format = input_format(path, None)?;
+    read_value(open(path)?, format).with_context(|| format!("Cannot read {}", path))
+}
+
+// files are signed and verified without going through a reader
+fn signed_data(input: &str) -> SignedData<'_> {
+    if input == "-" {
+        SignedData::Reader(Box::new(io::stdin()))
+    } else {
+        SignedData::File(Path::new(input))
+    }
+}
+
+fn read_key(input: &str) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    let mut content = Zeroizing::new(Vec::new());
+    open(input)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+// "-" reads the token from stdin
+fn read_token(token: &str) -> anyhow::Result<String> {
+    if token != "-" {
+        return Ok(token.to_string());
+    }
+    let mut buf = String::new();
+    io::stdin().read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+// private keys are only readable by their owner, like ssh-keygen does
+fn write_key_file(path: &Path, key: &[u8], private: bool) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Cannot write {}", path.display()))?;
+    // the mode only applies to new files, an existing one is tightened too
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
     }
+    #[cfg(not(unix))]
+    let _ = private;
+    file.write_all(key)?;
     Ok(())
 }
@@ -1,33 +1,30 @@
-use crate::cli::Base64Format;
-use crate::utils::input_reader;
-use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
-use base64::prelude::*;
+use super::codec::{process_codec_decode, process_codec_encode};
+use crate::cli::{Base64Format, Codec};
+use crate::error::Result;
+use std::io::{Read, Write};
 
-pub fn process_encode(input: String, format: Base64Format) -> anyhow::Result<()> {
-    let mut reader = input_reader(input)?;
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
-
-    let encoded = match format {
-        Base64Format::Standard => STANDARD.encode(&buf),
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.encode(&buf),
-    };
-    println!("{}", encoded);
-    Ok(())
+// `rcli base64` is `rcli encode/decode` with the two codecs it always had
+impl From<Base64Format> for Codec {
+    fn from(format: Base64Format) -> Self {
+        match format {
+            Base64Format::Standard => Codec::Base64,
+            Base64Format::UrlSafe => Codec::Base64UrlNoPad,
+        }
+    }
 }
 
-pub fn process_decode(input: String, format: Base64Format) -> anyhow::Result<()> {
-    let mut reader = input_reader(input)?;
-    let mut buf = String::new();
-    reader.read_to_string(&mut buf)?;
-    // avoid accident newline
-    let buf = buf.trim();
+pub fn process_encode(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+) -> Result<()> {
+    process_codec_encode(reader, writer, format.into(), None)
+}
 
-    let decoded = match format {
-        Base64Format::Standard => STANDARD.decode(buf)?,
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.decode(buf)?,
-    };
-    let decoded = String::from_utf8(decoded)?;
-    println!("{}", decoded);
-    Ok(())
+pub fn process_decode(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+) -> Result<()> {
+    process_codec_decode(reader, writer, format.into())
 }
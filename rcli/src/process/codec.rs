@@ -0,0 +1,451 @@
+use crate::cli::Codec;
+use crate::error::{Result, bail, err};
+use data_encoding::{
+    BASE32, BASE64, BASE64_NOPAD, BASE64URL, BASE64URL_NOPAD, Encoding, HEXLOWER,
+    HEXLOWER_PERMISSIVE, Specification,
+};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_encode};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::LazyLock;
+
+const BUF_SIZE: usize = 64 * 1024;
+
+const ASCII85: &[u8; 85] =
+    b"!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstu";
+const Z85: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+const ASCII85_DIGITS: [u8; 256] = digits(ASCII85);
+const Z85_DIGITS: [u8; 256] = digits(Z85);
+
+// RFC 3986 unreserved characters are left alone
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// Crockford's alphabet, decoding is case insensitive and reads I, L as 1 and O as 0
+static CROCKFORD: LazyLock<Encoding> = LazyLock::new(|| {
+    let mut spec = Specification::new();
+    spec.symbols.push_str("0123456789ABCDEFGHJKMNPQRSTVWXYZ");
+    spec.translate.from.push_str("abcdefghjkmnpqrstvwxyzIiLlOo");
+    spec.translate.to.push_str("ABCDEFGHJKMNPQRSTVWXYZ111100");
+    spec.encoding().expect("a valid base32 specification")
+});
+
+// the encoded text always ends with a newline
+pub fn process_codec_encode(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    codec: Codec,
+    wrap: Option<usize>,
+) -> Result<()> {
+    let wrap = wrap.filter(|width| *width > 0);
+    let mut column = 0;
+    transform(
+        reader,
+        |_| false,
+        |input, last, output| encode_chunk(codec, input, last, output),
+        |encoded| {
+            let Some(width) = wrap else {
+                column += encoded.len();
+                return Ok(writer.write_all(encoded)?);
+            };
+            let mut rest = encoded;
+            while !rest.is_empty() {
+                if column == width {
+                    writer.write_all(b"\n")?;
+                    column = 0;
+                }
+                let (line, tail) = rest.split_at(rest.len().min(width - column));
+                writer.write_all(line)?;
+                column += line.len();
+                rest = tail;
+            }
+            Ok(())
+        },
+    )?;
+    if column > 0 {
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// whitespace, and the dashes Crockford allows for readability, are skipped
+pub fn process_codec_decode(reader: impl Read, writer: &mut dyn Write, codec: Codec) -> Result<()> {
+    transform(
+        reader,
+        |b| b.is_ascii_whitespace() || (codec == Codec::Base32Crockford && b == b'-'),
+        |input, last, output| decode_chunk(codec, input, last, output),
+        |decoded| Ok(writer.write_all(decoded)?),
+    )
+}
+
+// feeds the reader through `step`, which converts what it can and returns
+// how much of its input it used. The rest is handed to it again with the
+// next read, and `last` tells it that no more is coming
+fn transform(
+    mut reader: impl Read,
+    skip: impl Fn(u8) -> bool,
+    mut step: impl FnMut(&[u8], bool, &mut Vec<u8>) -> Result<usize>,
+    mut emit: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut buf = vec![0; BUF_SIZE];
+    let mut pending = Vec::new();
+    let mut output = Vec::new();
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let last = n == 0;
+        pending.extend(buf[..n].iter().copied().filter(|b| !skip(*b)));
+        let used = step(&pending, last, &mut output)?;
+        pending.drain(..used);
+        emit(&output)?;
+        output.clear();
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn encode_chunk(codec: Codec, input: &[u8], last: bool, output: &mut Vec<u8>) -> Result<usize> {
+    let block = match codec {
+        Codec::Base58 if !last => return Ok(0),
+        Codec::Hex | Codec::Percent | Codec::Base58 => 1,
+        Codec::Base32 | Codec::Base32Crockford => 5,
+        Codec::Base64 | Codec::Base64NoPad | Codec::Base64Url | Codec::Base64UrlNoPad => 3,
+        Codec::Ascii85 | Codec::Z85 => 4,
+    };
+    let used = if last {
+        input.len()
+    } else {
+        input.len() / block * block
+    };
+    let input = &input[..used];
+    match codec {
+        Codec::Base58 => output.extend_from_slice(bs58::encode(input).into_string().as_bytes()),
+        Codec::Percent => output.extend(percent_encode(input, UNRESERVED).flat_map(str::bytes)),
+        Codec::Ascii85 => {
+            let mut groups = input.chunks_exact(4);
+            for group in groups.by_ref() {
+                if group == [0; 4] {
+                    output.push(b'z');
+                } else {
+                    output.extend(encode_group(group, ASCII85));
+                }
+            }
+            // a short final group is padded with zeros, and loses as many characters
+            let rest = groups.remainder();
+            if !rest.is_empty() {
+                output.extend(&encode_group(rest, ASCII85)[..rest.len() + 1]);
+            }
+        }
+        Codec::Z85 => {
+            if !input.len().is_multiple_of(4) {
+                bail!(
+                    InvalidInput,
+                    "Z85 only encodes whole 4-byte groups, {} bytes are left over",
+                    input.len() % 4
+                );
+            }
+            for group in input.chunks_exact(4) {
+                output.extend(encode_group(group, Z85));
+            }
+        }
+        _ => output.extend_from_slice(encoding(codec).encode(input).as_bytes()),
+    }
+    Ok(used)
+}
+
+fn decode_chunk(codec: Codec, input: &[u8], last: bool, output: &mut Vec<u8>) -> Result<usize> {
+    let block = match codec {
+        Codec::Base58 if !last => return Ok(0),
+        Codec::Base58 => {
+            output.extend(
+                bs58::decode(input)
+                    .into_vec()
+                    .map_err(|e| err!(InvalidInput, source = e, "Invalid base58"))?,
+            );
+            return Ok(input.len());
+        }
+        Codec::Ascii85 => return decode_ascii85(input, last, output),
+        Codec::Percent => return decode_percent(input, last, output),
+        Codec::Hex => 2,
+        Codec::Base32 | Codec::Base32Crockford => 8,
+        Codec::Base64 | Codec::Base64NoPad | Codec::Base64Url | Codec::Base64UrlNoPad => 4,
+        Codec::Z85 => 5,
+    };
+    let used = if last {
+        input.len()
+    } else {
+        input.len() / block * block
+    };
+    let input = &input[..used];
+    if codec == Codec::Z85 {
+        if !input.len().is_multiple_of(5) {
+            bail!(
+                InvalidInput,
+                "Invalid z85: the length must be a multiple of 5"
+            );
+        }
+        for group in input.chunks_exact(5) {
+            output.extend(decode_group(group, &Z85_DIGITS, codec)?);
+        }
+        return Ok(used);
+    }
+    let encoding = match codec {
+        Codec::Hex => HEXLOWER_PERMISSIVE,
+        codec => encoding(codec),
+    };
+    output.extend(
+        encoding
+            .decode(input)
+            .map_err(|e| err!(InvalidInput, source = e, "Invalid {}", codec))?,
+    );
+    Ok(used)
+}
+
+// the constants are borrowed, so copies are cheap
+fn encoding(codec: Codec) -> Encoding {
+    match codec {
+        Codec::Hex => HEXLOWER,
+        Codec::Base32 => BASE32,
+        Codec::Base32Crockford => CROCKFORD.clone(),
+        Codec::Base64 => BASE64,
+        Codec::Base64NoPad => BASE64_NOPAD,
+        Codec::Base64Url => BASE64URL,
+        Codec::Base64UrlNoPad => BASE64URL_NOPAD,
+        _ => unreachable!("{} is not a data-encoding codec", codec),
+    }
+}
+
+// "z" stands for four zero bytes between groups
+fn decode_ascii85(input: &[u8], last: bool, output: &mut Vec<u8>) -> Result<usize> {
+    let mut used = 0;
+    while used < input.len() {
+        let rest = &input[used..];
+        if rest[0] == b'z' {
+            output.extend([0; 4]);
+            used += 1;
+        } else if rest.len() >= 5 {
+            output.extend(decode_group(&rest[..5], &ASCII85_DIGITS, Codec::Ascii85)?);
+            used += 5;
+        } else if !last {
+            break;
+        } else if rest.len() == 1 {
+            bail!(
+                InvalidInput,
+                "Invalid ascii85: a single character can't end the input"
+            );
+        } else {
+            let mut group = [b'u'; 5];
+            group[..rest.len()].copy_from_slice(rest);
+            let decoded = decode_group(&group, &ASCII85_DIGITS, Codec::Ascii85)?;
+            output.extend(&decoded[..rest.len() - 1]);
+            used = input.len();
+        }
+    }
+    Ok(used)
+}
+
+fn decode_percent(input: &[u8], last: bool, output: &mut Vec<u8>) -> Result<usize> {
+    let mut used = 0;
+    while used < input.len() {
+        if input[used] != b'%' {
+            output.push(input[used]);
+            used += 1;
+            continue;
+        }
+        let Some(escape) = input.get(used + 1..used + 3) else {
+            if !last {
+                break;
+            }
+            bail!(InvalidInput, "Invalid percent encoding: a % at the end");
+        };
+        let byte = std::str::from_utf8(escape)
+            .ok()
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| {
+                err!(
+                    InvalidInput,
+                    "Invalid percent encoding: %{}",
+                    String::from_utf8_lossy(escape)
+                )
+            })?;
+        output.push(byte);
+        used += 3;
+    }
+    Ok(used)
+}
+
+// a group of up to 4 bytes, big endian, as 5 base 85 digits
+fn encode_group(group: &[u8], alphabet: &[u8; 85]) -> [u8; 5] {
+    let mut bytes = [0; 4];
+    bytes[..group.len()].copy_from_slice(group);
+    let mut n = u32::from_be_bytes(bytes);
+    let mut chars = [0; 5];
+    for c in chars.iter_mut().rev() {
+        *c = alphabet[(n % 85) as usize];
+        n /= 85;
+    }
+    chars
+}
+
+fn decode_group(chars: &[u8], digits: &[u8; 256], codec: Codec) -> Result<[u8; 4]> {
+    let mut n = 0u64;
+    for &c in chars {
+        let digit = digits[c as usize];
+        if digit == u8::MAX {
+            bail!(
+                InvalidInput,
+                "Invalid {}: unexpected character {:?}",
+                codec,
+                c as char
+            );
+        }
+        n = n * 85 + digit as u64;
+    }
+    let n = u32::try_from(n).map_err(|_| {
+        err!(
+            InvalidInput,
+            "Invalid {}: {} is out of range",
+            codec,
+            String::from_utf8_lossy(chars)
+        )
+    })?;
+    Ok(n.to_be_bytes())
+}
+
+const fn digits(alphabet: &[u8; 85]) -> [u8; 256] {
+    let mut digits = [u8::MAX; 256];
+    let mut i = 0;
+    while i < alphabet.len() {
+        digits[alphabet[i] as usize] = i as u8;
+        i += 1;
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Codec; 11] = [
+        Codec::Hex,
+        Codec::Base32,
+        Codec::Base32Crockford,
+        Codec::Base58,
+        Codec::Base64,
+        Codec::Base64NoPad,
+        Codec::Base64Url,
+        Codec::Base64UrlNoPad,
+        Codec::Ascii85,
+        Codec::Z85,
+        Codec::Percent,
+    ];
+
+    // hands out one byte per read to cross every chunk boundary
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn encoded(codec: Codec, data: &[u8], wrap: Option<usize>) -> Result<String> {
+        let mut output = Vec::new();
+        process_codec_encode(Trickle(data), &mut output, codec, wrap)?;
+        Ok(String::from_utf8(output).expect("utf-8 output"))
+    }
+
+    fn decoded(codec: Codec, text: &str) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        process_codec_decode(Trickle(text.as_bytes()), &mut output, codec)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_known_encodings() -> Result<()> {
+        let cases: [(Codec, &[u8], &str); 11] = [
+            (Codec::Hex, b"hello", "68656c6c6f"),
+            (Codec::Base32, b"foobar", "MZXW6YTBOI======"),
+            (Codec::Base32Crockford, b"foobar", "CSQPYRK1E8"),
+            (Codec::Base58, b"hello world", "StV1DL6CwTryKyV"),
+            (Codec::Base64, b"hello", "aGVsbG8="),
+            (Codec::Base64NoPad, b"hello", "aGVsbG8"),
+            (Codec::Base64Url, &[0xfb, 0xff], "-_8="),
+            (Codec::Base64UrlNoPad, &[0xfb, 0xff], "-_8"),
+            (
+                Codec::Ascii85,
+                b"Man is distinguished",
+                "9jqo^BlbD-BleB1DJ+*+F(f,q",
+            ),
+            (
+                Codec::Z85,
+                &[0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b],
+                "HelloWorld",
+            ),
+            (Codec::Percent, "a b/ü~".as_bytes(), "a%20b%2F%C3%BC~"),
+        ];
+        for (codec, data, text) in cases {
+            assert_eq!(
+                encoded(codec, data, None)?,
+                format!("{}\n", text),
+                "{}",
+                codec
+            );
+            assert_eq!(decoded(codec, text)?, data, "{}", codec);
+        }
+        assert_eq!(encoded(Codec::Ascii85, b"\0\0\0\0ab", None)?, "z@:B\n");
+        assert_eq!(decoded(Codec::Hex, "68656C6C6F\n")?, b"hello");
+        assert_eq!(decoded(Codec::Base32Crockford, "csqp-yrkl-e8")?, b"foobar");
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_binary() -> Result<()> {
+        let mut data = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+        data.extend([0; 9]);
+        for codec in ALL {
+            let data = if codec == Codec::Z85 {
+                &data[..1008]
+            } else {
+                &data[..]
+            };
+            let text = encoded(codec, data, Some(76))?;
+            assert!(text.lines().all(|line| line.len() <= 76), "{}", codec);
+            assert_eq!(decoded(codec, &text)?, data, "{}", codec);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrap() -> Result<()> {
+        assert_eq!(encoded(Codec::Hex, b"hello", Some(4))?, "6865\n6c6c\n6f\n");
+        assert_eq!(encoded(Codec::Hex, b"he", Some(4))?, "6865\n");
+        assert_eq!(encoded(Codec::Hex, b"", Some(4))?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(decoded(Codec::Hex, "6g").is_err());
+        assert!(decoded(Codec::Base64, "aGVsbG8").is_err());
+        assert!(decoded(Codec::Base58, "0OIl").is_err());
+        assert!(decoded(Codec::Ascii85, "9jqo^B").is_err());
+        assert!(decoded(Codec::Ascii85, "s8W-\"").is_err());
+        assert!(decoded(Codec::Z85, "Hello").is_ok());
+        assert!(decoded(Codec::Z85, "Hell").is_err());
+        assert!(decoded(Codec::Percent, "a%2").is_err());
+        assert!(decoded(Codec::Percent, "a%+1").is_err());
+        assert!(encoded(Codec::Z85, b"abc", None).is_err());
+    }
+}
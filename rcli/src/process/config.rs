@@ -0,0 +1,474 @@
+// defaults for subcommand options, each layer overriding the one before:
+//
+//   ~/.config/rcli/config.toml
+//   .rcli.toml in the current directory or the nearest parent
+//   RCLI_<SUBCOMMAND>_<OPTION> environment variables, e.g. RCLI_JWT_SIGN_ALG,
+//   except for options that clap already reads from a variable of their own
+//
+// and flags on the command line override them all. Sections are
+// subcommands, keys are their long options:
+//
+//   [csv]
+//   delimiter = ";"
+//
+//   [jwt.sign]
+//   key = "fixtures/jwt.pem"
+use crate::error::{RcliError, err};
+use clap::{Arg, ArgAction, Command};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PROJECT_FILE: &str = ".rcli.toml";
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    pub values: Vec<String>,
+    // a file or an environment variable
+    pub source: String,
+}
+
+// settings by subcommand path and option id, e.g. ["jwt", "sign", "alg"]
+#[derive(Debug, Default)]
+pub struct Config {
+    settings: BTreeMap<Vec<String>, Setting>,
+    // kept rather than returned, so a broken file doesn't stop --help or
+    // `rcli config show` from running
+    errors: Vec<RcliError>,
+}
+
+// where a file's content came from, for error messages
+struct Source<'a> {
+    name: &'a str,
+    content: &'a str,
+}
+
+impl Config {
+    pub fn load(cmd: &Command) -> Self {
+        let mut config = Config::default();
+        config.load_file(cmd, &user_config());
+        match std::env::current_dir() {
+            Ok(dir) => {
+                if let Some(project) = project_config(&dir) {
+                    config.load_file(cmd, &project);
+                }
+            }
+            Err(e) => config.errors.push(e.into()),
+        }
+        config.load_env(cmd, std::env::vars());
+        config
+    }
+
+    pub fn errors(&self) -> &[RcliError] {
+        &self.errors
+    }
+
+    // the first error, for subcommands that shouldn't run on a broken config
+    pub fn check(&mut self) -> Result<(), RcliError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.remove(0))
+        }
+    }
+
+    pub fn load_file(&mut self, cmd: &Command, path: &Path) {
+        if !path.is_file() {
+            return;
+        }
+        match fs::read_to_string(path) {
+            Ok(content) => self.load_str(cmd, &content, &path.display().to_string()),
+            Err(e) => self
+                .errors
+                .push(err!(InvalidConfig, source = e, "{}", path.display())),
+        }
+    }
+
+    // a file that doesn't parse is left out, a bad key only loses that key
+    pub fn load_str(&mut self, cmd: &Command, content: &str, name: &str) {
+        let table: toml::Table = match toml::from_str(content) {
+            Ok(table) => table,
+            Err(e) => {
+                self.errors
+                    .push(err!(InvalidConfig, source = e, "{}", name));
+                return;
+            }
+        };
+        let source = Source { name, content };
+        self.load_table(cmd, &table, &mut Vec::new(), &source);
+    }
+
+    fn load_table(
+        &mut self,
+        cmd: &Command,
+        table: &toml::Table,
+        path: &mut Vec<String>,
+        source: &Source,
+    ) {
+        for (key, value) in table {
+            path.push(key.clone());
+            let at = source.at(path);
+            if let toml::Value::Table(table) = value {
+                match cmd.find_subcommand(key) {
+                    Some(cmd) => self.load_table(cmd, table, path, source),
+                    None => self.errors.push(err!(
+                        InvalidConfig,
+                        "{}: [{}] is not a subcommand",
+                        at,
+                        path.join(".")
+                    )),
+                }
+                path.pop();
+                continue;
+            }
+            path.pop();
+            let Some(arg) = find_arg(cmd, key) else {
+                self.errors.push(err!(
+                    InvalidConfig,
+                    "{}: rcli {} has no option {}",
+                    at,
+                    path.join(" "),
+                    key
+                ));
+                continue;
+            };
+            let Some(values) = strings(value) else {
+                self.errors.push(err!(
+                    InvalidConfig,
+                    "{}: {} must be a value or a list",
+                    at,
+                    key
+                ));
+                continue;
+            };
+            let mut key = path.clone();
+            key.push(arg.get_id().to_string());
+            let source = source.name.to_string();
+            self.settings.insert(key, Setting { values, source });
+        }
+    }
+
+    // lists are comma separated
+    pub fn load_env(&mut self, cmd: &Command, vars: impl Iterator<Item = (String, String)>) {
+        let mut names = HashMap::new();
+        walk(cmd, &mut Vec::new(), &mut |key, arg| {
+            // e.g. --passphrase is RCLI_PASSPHRASE, not RCLI_TEXT_ENCRYPT_PASSPHRASE
+            if arg.get_env().is_none() {
+                names.insert(env_name(key), (key.to_vec(), takes_many(arg)));
+            }
+        });
+        for (name, value) in vars {
+            if let Some((key, many)) = names.get(&name) {
+                let values = if *many {
+                    value.split(',').map(|v| v.trim().to_string()).collect()
+                } else {
+                    vec![value]
+                };
+                let setting = Setting {
+                    values,
+                    source: name,
+                };
+                self.settings.insert(key.clone(), setting);
+            }
+        }
+    }
+
+    // the settings become the defaults of their options
+    pub fn apply(&self, cmd: Command) -> Command {
+        self.settings.iter().fold(cmd, |cmd, (key, setting)| {
+            set_default(cmd, key, &setting.values)
+        })
+    }
+}
+
+pub fn process_config_show(config: &Config, cmd: &Command, all: bool) -> String {
+    let mut lines: BTreeMap<Vec<String>, Setting> = BTreeMap::new();
+    let mut secrets = HashSet::new();
+    walk(cmd, &mut Vec::new(), &mut |key, arg| {
+        if is_secret(arg) {
+            secrets.insert(key.to_vec());
+        }
+    });
+    if all {
+        walk(cmd, &mut Vec::new(), &mut |key, arg| {
+            let values: Vec<String> = arg
+                .get_default_values()
+                .iter()
+                .map(|v| v.to_string_lossy().into_owned())
+                .collect();
+            if !values.is_empty() && !matches!(arg.get_action(), ArgAction::SetTrue) {
+                let source = "default".to_string();
+                lines.insert(key.to_vec(), Setting { values, source });
+            }
+        });
+    }
+    lines.extend(config.settings.clone());
+    let lines: Vec<(String, String)> = lines
+        .into_iter()
+        .map(|(key, setting)| {
+            if secrets.contains(&key) {
+                let line = format!("{} = \"{}\"", key.join("."), REDACTED);
+                return (line, setting.source);
+            }
+            let values: Vec<String> = setting
+                .values
+                .into_iter()
+                .map(|v| {
+                    // numbers and booleans print as such
+                    if v.parse::<i64>().is_ok() || v.parse::<bool>().is_ok() {
+                        v
+                    } else {
+                        toml::Value::String(v).to_string()
+                    }
+                })
+                .collect();
+            let value = match values.as_slice() {
+                [value] => value.clone(),
+                values => format!("[{}]", values.join(", ")),
+            };
+            (format!("{} = {}", key.join("."), value), setting.source)
+        })
+        .collect();
+    let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+    lines
+        .into_iter()
+        .map(|(line, source)| format!("{:<width$}  # {}\n", line, source))
+        .collect()
+}
+
+impl Source<'_> {
+    // "file:line" for the key at `path`, or just the file when it can't be found
+    fn at(&self, path: &[String]) -> String {
+        match line_of(self.content, path) {
+            Some(line) => format!("{}:{}", self.name, line),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+// toml::Table drops positions, toml_edit keeps them
+fn line_of(content: &str, path: &[String]) -> Option<usize> {
+    let doc = toml_edit::ImDocument::parse(content).ok()?;
+    let mut table: &dyn toml_edit::TableLike = doc.as_table();
+    let mut span = None;
+    for name in path {
+        let (key, item) = table.get_key_value(name)?;
+        span = key.span().or_else(|| item.span());
+        if let Some(inner) = item.as_table_like() {
+            table = inner;
+        }
+    }
+    let offset = span?.start;
+    Some(content[..offset].matches('\n').count() + 1)
+}
+
+fn user_config() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    Path::new(&home)
+        .join(".config")
+        .join("rcli")
+        .join("config.toml")
+}
+
+fn project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+// by id, or by long name with dashes
+fn find_arg<'a>(cmd: &'a Command, key: &str) -> Option<&'a Arg> {
+    cmd.get_arguments()
+        .find(|arg| arg.get_id() == key.replace('-', "_").as_str() || arg.get_long() == Some(key))
+}
+
+fn strings(value: &toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(s) => Some(vec![s.clone()]),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                toml::Value::Array(_) | toml::Value::Table(_) => None,
+                value => strings(value).map(|mut v| v.remove(0)),
+            })
+            .collect(),
+        toml::Value::Table(_) => None,
+        value => Some(vec![value.to_string()]),
+    }
+}
+
+// every option of every subcommand, with its key
+fn walk(cmd: &Command, path: &mut Vec<String>, f: &mut impl FnMut(&[String], &Arg)) {
+    for arg in cmd.get_arguments() {
+        if matches!(arg.get_id().as_str(), "help" | "version") {
+            continue;
+        }
+        path.push(arg.get_id().to_string());
+        f(path, arg);
+        path.pop();
+    }
+    for sub in cmd.get_subcommands() {
+        path.push(sub.get_name().to_string());
+        walk(sub, path, f);
+        path.pop();
+    }
+}
+
+fn env_name(key: &[String]) -> String {
+    format!("RCLI_{}", key.join("_").replace('-', "_").to_uppercase())
+}
+
+fn takes_many(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append)
+}
+
+// passphrases and credentials are never printed, neither by `config show`
+// nor as the [default: ..] of --help
+fn is_secret(arg: &Arg) -> bool {
+    arg.is_hide_env_values_set() || matches!(arg.get_id().as_str(), "passphrase" | "auth")
+}
+
+fn set_default(cmd: Command, key: &[String], values: &[String]) -> Command {
+    match key {
+        [id] => cmd.mut_arg(id, |arg| {
+            let secret = is_secret(&arg);
+            arg.default_values(values)
+                .required(false)
+                .hide_default_value(secret)
+        }),
+        [sub, rest @ ..] => cmd.mut_subcommand(sub, |sub| set_default(sub, rest, values)),
+        [] => cmd,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Opts, SubCommand};
+    use crate::error::{Result, error_chain};
+    use clap::{CommandFactory, FromArgMatches};
+
+    fn parse(config: &Config, args: &[&str]) -> Result<SubCommand> {
+        let matches = config
+            .apply(Opts::command())
+            .try_get_matches_from(args)
+            .and_then(|matches| Opts::from_arg_matches(&matches))
+            .map_err(|e| err!(InvalidArgument, source = e, "Invalid arguments"))?;
+        Ok(matches.cmd)
+    }
+
+    #[test]
+    fn test_layers() -> Result<()> {
+        let cmd = Opts::command();
+        let mut config = Config::default();
+        config.load_str(&cmd, "[diff]\nkey = \"id\"\nno-color = true\n", "user");
+        config.load_str(
+            &cmd,
+            "[diff]\nkey = \"name\"\n[time]\ntz = [\"UTC\", \"+08:00\"]\n",
+            "project",
+        );
+        assert!(config.errors().is_empty());
+        config.load_env(
+            &cmd,
+            [("RCLI_TIME_TZ".to_string(), "Asia/Tokyo, local".to_string())].into_iter(),
+        );
+
+        let SubCommand::Diff(opts) = parse(&config, &["rcli", "diff", "Cargo.toml", "Cargo.toml"])?
+        else {
+            panic!("not diff");
+        };
+        assert_eq!(opts.key.as_deref(), Some("name"));
+        assert!(opts.no_color);
+        let SubCommand::Diff(opts) = parse(
+            &config,
+            &["rcli", "diff", "Cargo.toml", "Cargo.toml", "-k", "sku"],
+        )?
+        else {
+            panic!("not diff");
+        };
+        assert_eq!(opts.key.as_deref(), Some("sku"));
+        let SubCommand::Time(opts) = parse(&config, &["rcli", "time"])? else {
+            panic!("not time");
+        };
+        assert_eq!(opts.tz, ["Asia/Tokyo".parse()?, "local".parse()?]);
+
+        let shown = process_config_show(&config, &cmd, false);
+        assert!(shown.contains("diff.key = \"name\""));
+        assert!(shown.contains("# project\n"));
+        assert!(shown.contains("# RCLI_TIME_TZ\n"));
+        assert!(process_config_show(&config, &cmd, true).contains("# default\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let cmd = Opts::command();
+        let mut config = Config::default();
+        config.load_str(&cmd, "[nope]\na = 1\n", "x");
+        config.load_str(
+            &cmd,
+            "[csv]\ndelimiter = \";\"\n\nnope = 1\n[csv.query]\ndelimiter = \";\"\n",
+            "y",
+        );
+        config.load_str(&cmd, "[csv\n", "z");
+        let errors = config
+            .errors()
+            .iter()
+            .map(|e| error_chain(e))
+            .collect::<Vec<_>>();
+        assert_eq!(errors[0], "x:1: [nope] is not a subcommand");
+        assert_eq!(errors[1], "y:4: rcli csv has no option nope");
+        assert!(errors[2].starts_with("z: TOML parse error at line 1"));
+        assert_eq!(errors.len(), 3);
+
+        // the good keys of a file with a bad one still load
+        let shown = process_config_show(&config, &cmd, false);
+        assert!(shown.contains("csv.delimiter = \";\""));
+        assert!(shown.contains("csv.query.delimiter = \";\""));
+        assert!(config.check().is_err());
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() -> Result<()> {
+        let cmd = Opts::command();
+        let mut config = Config::default();
+        config.load_str(
+            &cmd,
+            "[text.decrypt]\npassphrase = \"hunter2\"\n[http.serve]\nauth = \"me:secret\"\n",
+            "user",
+        );
+        // clap reads these from RCLI_PASSPHRASE and RCLI_HTTP_AUTH itself
+        config.load_env(
+            &cmd,
+            [
+                (
+                    "RCLI_TEXT_ENCRYPT_PASSPHRASE".to_string(),
+                    "hunter3".to_string(),
+                ),
+                ("RCLI_HTTP_SERVE_AUTH".to_string(), "me:other".to_string()),
+            ]
+            .into_iter(),
+        );
+
+        let shown = process_config_show(&config, &cmd, false);
+        assert!(
+            !shown.contains("hunter2") && !shown.contains("secret"),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("text.decrypt.passphrase = \"<redacted>\""));
+        assert!(shown.contains("http.serve.auth = \"<redacted>\""));
+        assert!(!shown.contains("RCLI_"));
+
+        let mut cmd = config.apply(Opts::command());
+        let help = cmd
+            .find_subcommand_mut("text")
+            .and_then(|text| text.find_subcommand_mut("decrypt"))
+            .expect("text decrypt")
+            .render_long_help()
+            .to_string();
+        assert!(!help.contains("hunter2"), "{}", help);
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use super::csv_convert::{CsvOptions, TypedCsvReader};
+use crate::cli::DataFormat;
+use crate::error::{Result, bail, err};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+// what was read: a lone object converts to a lone object, everything else is
+// handled as a list of records
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Document {
+    One(Value),
+    Many(Vec<Value>),
+}
+
+pub fn process_convert(
+    reader: impl Read,
+    writer: impl Write,
+    from: DataFormat,
+    to: DataFormat,
+) -> Result<()> {
+    let document = read(reader, from)?;
+    let mut writer = BufWriter::new(writer);
+    write(&mut writer, to, document)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(crate) fn read<R: Read>(mut reader: R, format: DataFormat) -> Result<Document> {
+    let document = match format {
+        DataFormat::Csv => {
+            let mut records = Vec::new();
+            TypedCsvReader::new(reader, &CsvOptions::default())?.for_each_row(false, |row| {
+                records.push(serde_json::to_value(row)?);
+                Ok(())
+            })?;
+            Document::Many(records)
+        }
+        DataFormat::Json => serde_json::from_reader::<_, Value>(reader)?.into(),
+        DataFormat::Ndjson => {
+            let mut records = Vec::new();
+            for (idx, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record = serde_json::from_str(&line)
+                    .map_err(|e| err!(InvalidInput, source = e, "line {}", idx + 1))?;
+                records.push(record);
+            }
+            Document::Many(records)
+        }
+        DataFormat::Yaml => serde_yaml::from_reader::<_, Value>(reader)?.into(),
+        DataFormat::Toml => {
+            let mut content = String::new();
+            reader.read_to_string(&mut content)?;
+            let table: Value = toml::from_str(&content)?;
+            from_toml(table)
+        }
+    };
+    Ok(document)
+}
+
+impl From<Value> for Document {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Array(records) => Document::Many(records),
+            value => Document::One(value),
+        }
+    }
+}
+
+// toml has no top-level arrays, a table holding nothing but one array of
+// tables (`[[rows]]`, as written by `rcli csv`) is read as those records
+fn from_toml(table: Value) -> Document {
+    match table {
+        Value::Object(map) if map.len() == 1 => {
+            let (key, value) = map.into_iter().next().expect("one entry");
+            match value {
+                Value::Array(records) if records.iter().all(Value::is_object) => {
+                    Document::Many(records)
+                }
+                value => Document::One(Value::Object(Map::from_iter([(key, value)]))),
+            }
+        }
+        table => Document::One(table),
+    }
+}
+
+pub(crate) fn write<W: Write>(mut writer: W, format: DataFormat, document: Document) -> Result<()> {
+    match format {
+        DataFormat::Csv => write_csv(writer, document.records()),
+        DataFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &document.into_value())?;
+            writeln!(writer)?;
+            Ok(())
+        }
+        DataFormat::Ndjson => {
+            for record in document.records() {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+            Ok(())
+        }
+        DataFormat::Yaml => Ok(serde_yaml::to_writer(writer, &document.into_value())?),
+        DataFormat::Toml => {
+            let table = match document {
+                Document::One(value @ Value::Object(_)) => value,
+                Document::One(value) => {
+                    bail!(InvalidInput, "TOML needs a table at the top, got {}", value)
+                }
+                Document::Many(records) if !records.iter().all(Value::is_object) => {
+                    bail!(InvalidInput, "TOML rows must be tables")
+                }
+                Document::Many(records) => Value::Object(Map::from_iter([(
+                    "rows".to_string(),
+                    Value::Array(records),
+                )])),
+            };
+            writer.write_all(toml::to_string_pretty(&strip_nulls(table))?.as_bytes())?;
+            Ok(())
+        }
+    }
+}
+
+impl Document {
+    fn records(self) -> Vec<Value> {
+        match self {
+            Document::One(value) => vec![value],
+            Document::Many(records) => records,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Document::One(value) => value,
+            Document::Many(records) => Value::Array(records),
+        }
+    }
+}
+
+// headers are the union of every record's flattened keys, in the order
+// they are first seen
+fn write_csv<W: Write>(writer: W, records: Vec<Value>) -> Result<()> {
+    let mut headers = Vec::new();
+    let mut seen = HashSet::new();
+    let mut rows = Vec::with_capacity(records.len());
+    for (idx, record) in records.iter().enumerate() {
+        let mut fields = Vec::new();
+        match record {
+            Value::Object(_) => flatten("", record, &mut fields),
+            value => flatten("value", value, &mut fields),
+        }
+        let mut row = HashMap::with_capacity(fields.len());
+        for (key, value) in fields {
+            // a literal "a.b" key next to a nested {"a": {"b": ..}}
+            if row.contains_key(&key) {
+                bail!(
+                    InvalidInput,
+                    "record {}: column {} appears twice",
+                    idx + 1,
+                    key
+                );
+            }
+            if seen.insert(key.clone()) {
+                headers.push(key.clone());
+            }
+            row.insert(key, value);
+        }
+        rows.push(row);
+    }
+
+    // a null or empty `club` next to `club.name` only says there is no club,
+    // its column goes; a value in both is ambiguous
+    let nested = |key: &str| {
+        headers.iter().any(|other| {
+            other.len() > key.len() && other.starts_with(key) && other[key.len()..].starts_with('.')
+        })
+    };
+    let mut dropped = HashSet::new();
+    for key in headers.iter().filter(|key| nested(key)) {
+        if rows
+            .iter()
+            .any(|row| row.get(key).is_some_and(|v| !v.is_empty()))
+        {
+            bail!(
+                InvalidInput,
+                "column {} holds both values and nested fields",
+                key
+            );
+        }
+        dropped.insert(key.clone());
+    }
+    headers.retain(|key| !dropped.contains(key));
+
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&headers)?;
+    for row in rows {
+        writer.write_record(
+            headers
+                .iter()
+                .map(|key| row.get(key).map(String::as_str).unwrap_or_default()),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// nested objects become dotted names, arrays are kept as json text and an
+// empty object is an empty cell
+fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) if map.is_empty() && !prefix.is_empty() => {
+            fields.push((prefix.to_string(), String::new()))
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&name, value, fields);
+            }
+        }
+        Value::Null => fields.push((prefix.to_string(), String::new())),
+        Value::String(s) => fields.push((prefix.to_string(), s.clone())),
+        value => fields.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+// toml has no null, drop those keys and array items
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k, strip_nulls(v)))
+            .collect(),
+        Value::Array(items) => items
+            .into_iter()
+            .filter(|v| !v.is_null())
+            .map(strip_nulls)
+            .collect(),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn convert(input: &str, from: DataFormat, to: DataFormat) -> Result<String> {
+        let mut out = Vec::new();
+        write(&mut out, to, read(input.as_bytes(), from)?)?;
+        Ok(String::from_utf8(out).expect("utf-8 output"))
+    }
+
+    #[test]
+    fn test_json_to_csv_flattens_and_unions() -> Result<()> {
+        let input = r#"[
+            {"name": "Buffon", "club": {"name": "Juventus", "since": 2001}},
+            {"name": "Perin", "number": 37, "tags": ["gk", "it"], "club": null}
+        ]"#;
+        let output = convert(input, DataFormat::Json, DataFormat::Csv)?;
+        assert_eq!(
+            output,
+            "name,club.name,club.since,number,tags\n\
+             Buffon,Juventus,2001,,\n\
+             Perin,,,37,\"[\"\"gk\"\",\"\"it\"\"]\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_columns_that_collide() -> Result<()> {
+        // an empty object stays as an empty cell
+        let output = convert(
+            r#"[{"a": 1, "meta": {}}, {"a": 2, "meta": {}}]"#,
+            DataFormat::Json,
+            DataFormat::Csv,
+        )?;
+        assert_eq!(output, "a,meta\n1,\n2,\n");
+
+        // a null parent in one record and parent.child in another
+        let output = convert(
+            r#"[{"parent": null}, {"parent": {"child": 1}}, {"parent": {}}]"#,
+            DataFormat::Json,
+            DataFormat::Csv,
+        )?;
+        assert_eq!(output, "parent.child\n\"\"\n1\n\"\"\n");
+
+        // a value and nested fields under the same name
+        let err = convert(
+            r#"[{"parent": 1}, {"parent": {"child": 1}}]"#,
+            DataFormat::Json,
+            DataFormat::Csv,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "column parent holds both values and nested fields"
+        );
+
+        // a literal dotted key next to the nested one it spells
+        let err = convert(
+            r#"[{"a.b": 1, "a": {"b": 2}}]"#,
+            DataFormat::Json,
+            DataFormat::Csv,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "record 1: column a.b appears twice");
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_to_ndjson_is_typed() -> Result<()> {
+        let output = convert(
+            "name,age\nBuffon,41\nPerin,\n",
+            DataFormat::Csv,
+            DataFormat::Ndjson,
+        )?;
+        assert_eq!(
+            output,
+            "{\"name\":\"Buffon\",\"age\":41}\n{\"name\":\"Perin\",\"age\":null}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_round_trip() -> Result<()> {
+        let input = "{\"a\":1,\"b\":null}\n\n{\"a\":2,\"b\":\"x\"}\n";
+        let toml = convert(input, DataFormat::Ndjson, DataFormat::Toml)?;
+        assert!(toml.contains("[[rows]]"));
+        let ndjson = convert(&toml, DataFormat::Toml, DataFormat::Ndjson)?;
+        assert_eq!(ndjson, "{\"a\":1}\n{\"a\":2,\"b\":\"x\"}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_object_keeps_shape() -> Result<()> {
+        let yaml = "server:\n  port: 8080\n  hosts: [a, b]\n";
+        let json = convert(yaml, DataFormat::Yaml, DataFormat::Json)?;
+        let value: Value = serde_json::from_str(&json)?;
+        assert_eq!(
+            value,
+            json!({"server": {"port": 8080, "hosts": ["a", "b"]}})
+        );
+
+        let toml = convert(&json, DataFormat::Json, DataFormat::Toml)?;
+        assert_eq!(
+            toml,
+            "[server]\nport = 8080\nhosts = [\n    \"a\",\n    \"b\",\n]\n"
+        );
+        assert!(convert("[1, 2]", DataFormat::Json, DataFormat::Toml).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(DataFormat::from_path("a.JSONL"), Some(DataFormat::Ndjson));
+        assert_eq!(DataFormat::from_path("dir/a.yml"), Some(DataFormat::Yaml));
+        assert_eq!(DataFormat::from_path("a.csv"), Some(DataFormat::Csv));
+        assert_eq!(DataFormat::from_path("-"), None);
+    }
+}
@@ -1,27 +1,519 @@
-use crate::cli::csv::OutputFormat;
-use csv::{Reader, StringRecord};
+use crate::cli::csv::{ColumnType, OutputFormat, Schema};
+use crate::error::{Result, bail, err};
+use csv::{ReaderBuilder, StringRecord, StringRecordsIntoIter};
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use serde_json::Value;
-use std::fs;
+use std::collections::VecDeque;
+use std::io::{BufWriter, Read, Write};
+
+// records used to infer column types before anything is written
+const SAMPLE_SIZE: usize = 1000;
 
 pub fn process_csv(
-    input_path: &str,
-    output_path: String,
+    reader: impl Read,
+    writer: impl Write,
     format: OutputFormat,
-) -> anyhow::Result<()> {
-    let mut reader = Reader::from_path(input_path)?;
-    let mut ret = Vec::with_capacity(256);
-    let headers = reader.headers()?.clone();
-    for result in reader.records() {
-        let record: StringRecord = result?;
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value);
-    }
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
-        OutputFormat::Toml => toml::to_string_pretty(&ret)?,
+    delimiter: char,
+    header: bool,
+    schema: Option<Schema>,
+) -> Result<()> {
+    if !delimiter.is_ascii() {
+        bail!(
+            InvalidArgument,
+            "Delimiter must be a single ASCII character: {:?}",
+            delimiter
+        );
+    }
+    let options = CsvOptions {
+        delimiter: delimiter as u8,
+        header,
+        schema: schema.unwrap_or_default(),
     };
+    convert(reader, writer, format, &options)
+}
 
-    fs::write(output_path, content)?;
-    Ok(())
+pub(crate) struct CsvOptions {
+    pub(crate) delimiter: u8,
+    pub(crate) header: bool,
+    pub(crate) schema: Schema,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            header: true,
+            schema: Schema::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Cell<'a> {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Date(&'a str),
+    String(&'a str),
+}
+
+struct Column {
+    name: String,
+    column_type: ColumnType,
+    // values that don't match an explicit type are an error, a wrong guess
+    // from the sample just falls back to a string
+    strict: bool,
+}
+
+// a record serialized as a map in column order
+pub(crate) struct Row<'a> {
+    columns: &'a [Column],
+    cells: Vec<Cell<'a>>,
+    // toml has no null
+    skip_null: bool,
+}
+
+// csv records typed by a schema inferred from the first SAMPLE_SIZE records,
+// read lazily after the sample
+pub(crate) struct TypedCsvReader<R: Read> {
+    columns: Vec<Column>,
+    sample: VecDeque<StringRecord>,
+    records: StringRecordsIntoIter<R>,
+}
+
+impl<R: Read> TypedCsvReader<R> {
+    pub(crate) fn new(reader: R, options: &CsvOptions) -> Result<Self> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .has_headers(options.header)
+            .from_reader(reader);
+
+        let headers = if options.header {
+            Some(reader.headers()?.iter().map(|h| h.to_string()).collect())
+        } else {
+            None
+        };
+        let mut records = reader.into_records();
+        let mut sample = VecDeque::with_capacity(SAMPLE_SIZE);
+        for record in records.by_ref().take(SAMPLE_SIZE) {
+            sample.push_back(record?);
+        }
+        let names = headers.unwrap_or_else(|| {
+            let len = sample.front().map(|r| r.len()).unwrap_or_default();
+            (1..=len).map(|i| format!("column{}", i)).collect()
+        });
+        let columns = infer_columns(names, sample.iter(), &options.schema)?;
+        Ok(Self {
+            columns,
+            sample,
+            records,
+        })
+    }
+
+    pub(crate) fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    pub(crate) fn for_each_row(
+        self,
+        skip_null: bool,
+        mut f: impl FnMut(&Row) -> Result<()>,
+    ) -> Result<()> {
+        self.for_each_row_while(skip_null, |row| f(row).map(|_| true))
+    }
+
+    // stops at the first row `f` returns false for
+    pub(crate) fn for_each_row_while(
+        self,
+        skip_null: bool,
+        mut f: impl FnMut(&Row) -> Result<bool>,
+    ) -> Result<()> {
+        let records = self.sample.into_iter().map(Ok).chain(self.records);
+        for (idx, record) in records.enumerate() {
+            let record = record?;
+            let cells = self
+                .columns
+                .iter()
+                .zip(record.iter())
+                .map(|(column, value)| column.parse(value))
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| err!(InvalidInput, source = e, "record {}", idx + 1))?;
+            let row = Row {
+                columns: &self.columns,
+                cells,
+                skip_null,
+            };
+            if !f(&row)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn convert<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    format: OutputFormat,
+    options: &CsvOptions,
+) -> Result<()> {
+    let reader = TypedCsvReader::new(reader, options)?;
+    let mut out = Output::new(BufWriter::new(writer), format);
+    reader.for_each_row(matches!(format, OutputFormat::Toml), |row| out.write(row))?;
+    out.finish()
+}
+
+fn infer_columns<'a>(
+    names: Vec<String>,
+    sample: impl Iterator<Item = &'a StringRecord> + Clone,
+    schema: &Schema,
+) -> Result<Vec<Column>> {
+    for (name, _) in schema.0.iter() {
+        if !names.contains(name) {
+            bail!(
+                InvalidArgument,
+                "Schema column {} is not in the input",
+                name
+            );
+        }
+    }
+    let columns = names
+        .into_iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            let explicit = schema.0.iter().find(|(n, _)| *n == name).map(|(_, t)| *t);
+            let column_type = explicit.unwrap_or_else(|| {
+                sample
+                    .clone()
+                    .filter_map(|record| record.get(idx))
+                    .map(detect)
+                    .fold(ColumnType::Null, widen)
+            });
+            Column {
+                name,
+                column_type,
+                strict: explicit.is_some(),
+            }
+        })
+        .collect();
+    Ok(columns)
+}
+
+// the narrowest type that can hold `value`
+fn detect(value: &str) -> ColumnType {
+    if value.is_empty() {
+        ColumnType::Null
+    } else if parse_bool(value).is_some() {
+        ColumnType::Bool
+    } else if parse_integer(value).is_some() {
+        ColumnType::Integer
+    } else if parse_float(value).is_some() {
+        ColumnType::Float
+    } else if is_date(value) {
+        ColumnType::Date
+    } else {
+        ColumnType::String
+    }
+}
+
+// the narrowest type that can hold values of both types
+fn widen(a: ColumnType, b: ColumnType) -> ColumnType {
+    use ColumnType::*;
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Null, t) | (t, Null) => t,
+        (Integer, Float) | (Float, Integer) => Float,
+        _ => String,
+    }
+}
+
+impl Column {
+    fn parse<'a>(&self, value: &'a str) -> Result<Cell<'a>> {
+        // empty fields are null unless the schema asks for strings
+        if value.is_empty() && !(self.strict && self.column_type == ColumnType::String) {
+            return Ok(Cell::Null);
+        }
+        let cell = match self.column_type {
+            ColumnType::Null => None,
+            ColumnType::Bool => parse_bool(value).map(Cell::Bool),
+            ColumnType::Integer => parse_integer(value).map(Cell::Integer),
+            ColumnType::Float => parse_float(value).map(Cell::Float),
+            ColumnType::Date => is_date(value).then_some(Cell::Date(value)),
+            ColumnType::String => Some(Cell::String(value)),
+        };
+        match cell {
+            Some(cell) => Ok(cell),
+            None if self.strict => bail!(
+                InvalidInput,
+                "column {}: cannot parse {:?} as {}",
+                self.name,
+                value,
+                self.column_type
+            ),
+            None => Ok(Cell::String(value)),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_integer(value: &str) -> Option<i64> {
+    if has_leading_zero(value) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+// rejects the `inf` and `nan` spellings rust accepts
+fn parse_float(value: &str) -> Option<f64> {
+    if has_leading_zero(value) || !value.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+// leading zeros usually mean an identifier (zip codes, phone numbers)
+fn has_leading_zero(value: &str) -> bool {
+    let digits = value.strip_prefix(['-', '+']).unwrap_or(value).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+// YYYY-MM-DD
+pub(crate) fn is_date(value: &str) -> bool {
+    let mut parts = value.splitn(3, '-');
+    let (Some(y), Some(m), Some(d)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    if y.len() != 4 || m.len() != 2 || d.len() != 2 {
+        return false;
+    }
+    let (Ok(y), Ok(m), Ok(d)) = (y.parse::<u32>(), m.parse::<u32>(), d.parse::<u32>()) else {
+        return false;
+    };
+    let leap = y.is_multiple_of(4) && (!y.is_multiple_of(100) || y.is_multiple_of(400));
+    let days = match m {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&d)
+}
+
+impl Row<'_> {
+    pub(crate) fn values(&self) -> Vec<Value> {
+        self.cells.iter().map(Value::from).collect()
+    }
+}
+
+impl From<&Cell<'_>> for Value {
+    fn from(cell: &Cell<'_>) -> Self {
+        match cell {
+            Cell::Null => Value::Null,
+            Cell::Bool(v) => Value::Bool(*v),
+            Cell::Integer(v) => Value::from(*v),
+            Cell::Float(v) => Value::from(*v),
+            Cell::Date(v) | Cell::String(v) => Value::from(*v),
+        }
+    }
+}
+
+impl Serialize for Cell<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Cell::Null => serializer.serialize_none(),
+            Cell::Bool(v) => serializer.serialize_bool(*v),
+            Cell::Integer(v) => serializer.serialize_i64(*v),
+            Cell::Float(v) => serializer.serialize_f64(*v),
+            Cell::Date(v) | Cell::String(v) => serializer.serialize_str(v),
+        }
+    }
+}
+
+impl Serialize for Row<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (column, cell) in self.columns.iter().zip(self.cells.iter()) {
+            if self.skip_null && *cell == Cell::Null {
+                continue;
+            }
+            map.serialize_entry(&column.name, cell)?;
+        }
+        map.end()
+    }
+}
+
+// writes rows as they come: a json array, a yaml sequence, or a toml array
+// of `[[rows]]` tables
+struct Output<W: Write> {
+    writer: W,
+    format: OutputFormat,
+    rows: usize,
+}
+
+impl<W: Write> Output<W> {
+    fn new(writer: W, format: OutputFormat) -> Self {
+        Self {
+            writer,
+            format,
+            rows: 0,
+        }
+    }
+
+    fn write(&mut self, row: &Row) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                self.writer
+                    .write_all(if self.rows == 0 { b"[\n  " } else { b",\n  " })?;
+                serde_json::to_writer(&mut self.writer, row)?;
+            }
+            OutputFormat::Yaml => {
+                let item = serde_yaml::to_string(&[row])?;
+                self.writer.write_all(item.as_bytes())?;
+            }
+            OutputFormat::Toml => {
+                let table = toml::to_string(row)?;
+                write!(self.writer, "[[rows]]\n{}\n", table)?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        match self.format {
+            OutputFormat::Json if self.rows == 0 => self.writer.write_all(b"[]\n")?,
+            OutputFormat::Json => self.writer.write_all(b"\n]\n")?,
+            OutputFormat::Yaml if self.rows == 0 => self.writer.write_all(b"[]\n")?,
+            _ => {}
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PLAYERS: &str = "\
+name,number,height,active,born,zip
+Buffon,77,1.92,false,1978-01-28,00100
+Perin,37,1.88,true,1992-11-10,
+Szczesny,1,,TRUE,1990-04-18,16100
+";
+
+    fn run(input: &str, format: OutputFormat, options: CsvOptions) -> Result<String> {
+        let mut out = Vec::new();
+        convert(input.as_bytes(), &mut out, format, &options)?;
+        Ok(String::from_utf8(out).expect("utf-8 output"))
+    }
+
+    fn options(delimiter: u8, header: bool, schema: &str) -> Result<CsvOptions> {
+        Ok(CsvOptions {
+            delimiter,
+            header,
+            schema: schema.parse()?,
+        })
+    }
+
+    #[test]
+    fn test_infer_types() -> Result<()> {
+        let output = run(PLAYERS, OutputFormat::Json, options(b',', true, "")?)?;
+        let value: Value = serde_json::from_str(&output)?;
+        assert_eq!(
+            value[0],
+            json!({"name": "Buffon", "number": 77, "height": 1.92, "active": false,
+                   "born": "1978-01-28", "zip": "00100"})
+        );
+        assert_eq!(value[1]["zip"], Value::Null);
+        assert_eq!(value[2]["height"], Value::Null);
+        assert_eq!(value[2]["active"], true);
+        // keys keep the column order
+        assert!(output.starts_with("[\n  {\"name\":\"Buffon\",\"number\":77,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_override() -> Result<()> {
+        let output = run(
+            PLAYERS,
+            OutputFormat::Json,
+            options(b',', true, "number:float,born:string")?,
+        )?;
+        let value: Value = serde_json::from_str(&output)?;
+        assert_eq!(value[0]["number"], 77.0);
+        assert_eq!(value[0]["born"], "1978-01-28");
+
+        let err = run(
+            PLAYERS,
+            OutputFormat::Json,
+            options(b',', true, "name:integer")?,
+        );
+        assert!(err.unwrap_err().to_string().contains("record 1"));
+        assert!(
+            run(
+                PLAYERS,
+                OutputFormat::Json,
+                options(b',', true, "age:integer")?
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimiter_and_no_header() -> Result<()> {
+        let output = run("a;1\nb;2\n", OutputFormat::Json, options(b';', false, "")?)?;
+        let value: Value = serde_json::from_str(&output)?;
+        assert_eq!(
+            value,
+            json!([{"column1": "a", "column2": 1}, {"column1": "b", "column2": 2}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_and_toml_output() -> Result<()> {
+        let yaml = run(PLAYERS, OutputFormat::Yaml, options(b',', true, "")?)?;
+        let value: Value = serde_yaml::from_str(&yaml)?;
+        assert_eq!(value.as_array().map(|rows| rows.len()), Some(3));
+        assert_eq!(value[1]["number"], 37);
+
+        let toml = run(PLAYERS, OutputFormat::Toml, options(b',', true, "")?)?;
+        let value: toml::Table = toml::from_str(&toml)?;
+        let rows = value["rows"].as_array().expect("rows");
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["number"].as_integer(), Some(77));
+        assert!(rows[1].get("zip").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("0"), ColumnType::Integer);
+        assert_eq!(detect("-12"), ColumnType::Integer);
+        assert_eq!(detect("007"), ColumnType::String);
+        assert_eq!(detect("1e3"), ColumnType::Float);
+        assert_eq!(detect("nan"), ColumnType::String);
+        assert_eq!(detect("2024-02-29"), ColumnType::Date);
+        assert_eq!(detect("2023-02-29"), ColumnType::String);
+        assert_eq!(
+            widen(ColumnType::Integer, ColumnType::Float),
+            ColumnType::Float
+        );
+        assert_eq!(
+            widen(ColumnType::Bool, ColumnType::Integer),
+            ColumnType::String
+        );
+    }
 }
@@ -0,0 +1,689 @@
+use super::convert::{Document, write};
+use super::csv_convert::{CsvOptions, TypedCsvReader};
+use super::expr::{Expr, compare, parse};
+use crate::cli::DataFormat;
+use crate::cli::csv::CsvQueryOpts;
+use crate::error::{Result, bail, err};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+enum Item {
+    Expr(Expr),
+    // `None` is count(*)
+    Agg(AggFunc, Option<Expr>),
+}
+
+struct Query {
+    headers: Vec<String>,
+    items: Vec<Item>,
+    filter: Option<Expr>,
+    group_by: Option<Vec<Expr>>,
+    // (key, descending)
+    sort: Vec<(SortKey, bool)>,
+    limit: Option<usize>,
+}
+
+enum SortKey {
+    Output(usize),
+    Input(Expr),
+}
+
+enum Acc {
+    Count(u64),
+    Sum {
+        int: i64,
+        float: f64,
+        all_int: bool,
+        seen: bool,
+    },
+    Avg {
+        sum: f64,
+        n: u64,
+    },
+    Min(Value),
+    Max(Value),
+}
+
+struct Group {
+    // non-aggregated items, taken from the first record of the group
+    values: Vec<Value>,
+    accs: Vec<Option<Acc>>,
+}
+
+// reads and writes what `opts` describes, its input and output are up to
+// the caller
+pub fn process_csv_query(reader: impl Read, writer: impl Write, opts: &CsvQueryOpts) -> Result<()> {
+    if !opts.delimiter.is_ascii() {
+        bail!(
+            InvalidArgument,
+            "Delimiter must be a single ASCII character: {:?}",
+            opts.delimiter
+        );
+    }
+    let options = CsvOptions {
+        delimiter: opts.delimiter as u8,
+        header: opts.header,
+        ..Default::default()
+    };
+    run(reader, BufWriter::new(writer), opts, &options)
+}
+
+fn run<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    opts: &CsvQueryOpts,
+    options: &CsvOptions,
+) -> Result<()> {
+    let reader = TypedCsvReader::new(reader, options)?;
+    let query = Query::new(&reader.column_names(), opts)?;
+    let mut out = RowWriter::new(writer, opts.format, query.headers.clone())?;
+
+    // without grouping or sorting every record is written as soon as it is read
+    if query.group_by.is_none() && query.sort.is_empty() {
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut written = 0;
+        reader.for_each_row_while(false, |row| {
+            let row = row.values();
+            if written < limit && query.keep(&row)? {
+                out.write(query.project(&row)?)?;
+                written += 1;
+            }
+            Ok(written < limit)
+        })?;
+        return out.finish();
+    }
+
+    // (sort keys, output row)
+    let mut rows = Vec::new();
+    let mut groups = Vec::<Group>::new();
+    let mut index = HashMap::<String, usize>::new();
+    reader.for_each_row(false, |row| {
+        let row = row.values();
+        if !query.keep(&row)? {
+            return Ok(());
+        }
+        let Some(group_by) = &query.group_by else {
+            let out = query.project(&row)?;
+            rows.push((query.sort_keys(&row, &out)?, out));
+            return Ok(());
+        };
+        let key = group_by
+            .iter()
+            .map(|expr| expr.eval(&row))
+            .collect::<Result<Vec<_>>>()?;
+        let idx = *index
+            .entry(serde_json::to_string(&key)?)
+            .or_insert_with(|| groups.len());
+        if idx == groups.len() {
+            groups.push(query.new_group(&row)?);
+        }
+        query.accumulate(&mut groups[idx], &row)
+    })?;
+    // aggregates over the whole input still give one row when nothing
+    // matched, like sql without GROUP BY
+    if query.group_by.as_ref().is_some_and(Vec::is_empty) && groups.is_empty() {
+        groups.push(query.new_group(&[])?);
+    }
+    if query.group_by.is_some() {
+        rows = groups
+            .into_iter()
+            .map(|group| {
+                let out = group.finish();
+                Ok((query.sort_keys(&[], &out)?, out))
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    if !query.sort.is_empty() {
+        rows.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b)
+                .zip(&query.sort)
+                .map(|((a, b), (_, desc))| {
+                    let ord = order(a, b);
+                    if *desc { ord.reverse() } else { ord }
+                })
+                .find(|ord| *ord != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+    let rows = rows.into_iter().map(|(_, row)| row);
+    for row in rows.take(query.limit.unwrap_or(usize::MAX)) {
+        out.write(row)?;
+    }
+    out.finish()
+}
+
+impl Query {
+    fn new(columns: &[String], opts: &CsvQueryOpts) -> Result<Self> {
+        let group_by = opts
+            .group_by
+            .as_deref()
+            .map(|group_by| {
+                split_list(group_by)
+                    .into_iter()
+                    .map(|item| Ok((item.to_string(), parse(item, columns)?)))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+
+        let select = match (&opts.select, &group_by) {
+            (Some(select), _) => select.clone(),
+            (None, None) => "*".to_string(),
+            (None, Some(group_by)) => {
+                let mut items = group_by.iter().map(|(s, _)| s.clone()).collect::<Vec<_>>();
+                items.push("count(*) as count".to_string());
+                items.join(", ")
+            }
+        };
+
+        let mut headers = Vec::new();
+        let mut items = Vec::new();
+        for item in split_list(&select) {
+            if item == "*" {
+                headers.extend(columns.iter().cloned());
+                items.extend((0..columns.len()).map(Expr::Column).map(Item::Expr));
+                continue;
+            }
+            let (source, alias) = split_alias(item);
+            let parsed = match aggregate(source) {
+                Some((func, "*")) if func == AggFunc::Count => Item::Agg(func, None),
+                Some((func, arg)) => Item::Agg(func, Some(parse(arg, columns)?)),
+                None => Item::Expr(parse(source, columns)?),
+            };
+            if let (Item::Expr(_), Some(group_by)) = (&parsed, &group_by)
+                && !group_by.iter().any(|(s, _)| s == source)
+            {
+                bail!(
+                    InvalidQuery,
+                    "{} must be in --group-by or inside an aggregate",
+                    source
+                );
+            }
+            headers.push(alias.unwrap_or(source).to_string());
+            items.push(parsed);
+        }
+
+        // aggregates without --group-by summarize all records as one group
+        let group_by = match group_by {
+            Some(group_by) => Some(group_by.into_iter().map(|(_, e)| e).collect()),
+            None if items.iter().any(|item| matches!(item, Item::Agg(..))) => {
+                if items.iter().any(|item| matches!(item, Item::Expr(_))) {
+                    bail!(
+                        InvalidQuery,
+                        "Mixing columns and aggregates needs --group-by"
+                    );
+                }
+                Some(Vec::new())
+            }
+            None => None,
+        };
+
+        let sort = opts
+            .sort
+            .as_deref()
+            .map(split_list)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| {
+                let (name, desc) = match item.rsplit_once(char::is_whitespace) {
+                    Some((name, dir)) if dir.eq_ignore_ascii_case("desc") => (name.trim(), true),
+                    Some((name, dir)) if dir.eq_ignore_ascii_case("asc") => (name.trim(), false),
+                    _ => (item, false),
+                };
+                // output columns first, then anything computed from the input
+                // record, which only exists when not grouping
+                let key = match headers.iter().position(|h| h == name) {
+                    Some(idx) => SortKey::Output(idx),
+                    None if group_by.is_none() => SortKey::Input(parse(name, columns)?),
+                    None => bail!(
+                        InvalidQuery,
+                        "Cannot sort by {}, it is not an output column",
+                        name
+                    ),
+                };
+                Ok((key, desc))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let filter = opts
+            .filter
+            .as_deref()
+            .map(|filter| parse(filter, columns))
+            .transpose()?;
+
+        Ok(Self {
+            headers,
+            items,
+            filter,
+            group_by,
+            sort,
+            limit: opts.limit,
+        })
+    }
+
+    fn keep(&self, row: &[Value]) -> Result<bool> {
+        match &self.filter {
+            Some(filter) => filter.matches(row),
+            None => Ok(true),
+        }
+    }
+
+    fn project(&self, row: &[Value]) -> Result<Vec<Value>> {
+        self.items
+            .iter()
+            .map(|item| match item {
+                Item::Expr(expr) => expr.eval(row),
+                Item::Agg(..) => unreachable!("aggregates always group"),
+            })
+            .collect()
+    }
+
+    fn sort_keys(&self, row: &[Value], out: &[Value]) -> Result<Vec<Value>> {
+        self.sort
+            .iter()
+            .map(|(key, _)| match key {
+                SortKey::Output(idx) => Ok(out[*idx].clone()),
+                SortKey::Input(expr) => expr.eval(row),
+            })
+            .collect()
+    }
+
+    fn new_group(&self, row: &[Value]) -> Result<Group> {
+        let mut values = Vec::new();
+        let mut accs = Vec::new();
+        for item in self.items.iter() {
+            match item {
+                Item::Expr(expr) => {
+                    values.push(expr.eval(row)?);
+                    accs.push(None);
+                }
+                Item::Agg(func, _) => {
+                    values.push(Value::Null);
+                    accs.push(Some(Acc::new(*func)));
+                }
+            }
+        }
+        Ok(Group { values, accs })
+    }
+
+    fn accumulate(&self, group: &mut Group, row: &[Value]) -> Result<()> {
+        for (item, acc) in self.items.iter().zip(group.accs.iter_mut()) {
+            if let (Item::Agg(_, arg), Some(acc)) = (item, acc) {
+                let value = match arg {
+                    Some(arg) => arg.eval(row)?,
+                    None => Value::Bool(true),
+                };
+                acc.add(value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Group {
+    fn finish(self) -> Vec<Value> {
+        self.values
+            .into_iter()
+            .zip(self.accs)
+            .map(|(value, acc)| acc.map_or(value, Acc::finish))
+            .collect()
+    }
+}
+
+impl Acc {
+    fn new(func: AggFunc) -> Self {
+        match func {
+            AggFunc::Count => Acc::Count(0),
+            AggFunc::Sum => Acc::Sum {
+                int: 0,
+                float: 0.0,
+                all_int: true,
+                seen: false,
+            },
+            AggFunc::Avg => Acc::Avg { sum: 0.0, n: 0 },
+            AggFunc::Min => Acc::Min(Value::Null),
+            AggFunc::Max => Acc::Max(Value::Null),
+        }
+    }
+
+    // nulls are skipped, like sql
+    fn add(&mut self, value: Value) -> Result<()> {
+        if value.is_null() {
+            return Ok(());
+        }
+        match self {
+            Acc::Count(n) => *n += 1,
+            Acc::Sum {
+                int,
+                float,
+                all_int,
+                seen,
+            } => {
+                let n = number(&value)?;
+                *seen = true;
+                *float += n;
+                match value.as_i64().and_then(|v| int.checked_add(v)) {
+                    Some(sum) if *all_int => *int = sum,
+                    _ => *all_int = false,
+                }
+            }
+            Acc::Avg { sum, n } => {
+                *sum += number(&value)?;
+                *n += 1;
+            }
+            Acc::Min(min) => {
+                if min.is_null() || order(&value, min) == Ordering::Less {
+                    *min = value;
+                }
+            }
+            Acc::Max(max) => {
+                if max.is_null() || order(&value, max) == Ordering::Greater {
+                    *max = value;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Acc::Count(n) => Value::from(n),
+            Acc::Sum { seen: false, .. } => Value::Null,
+            Acc::Sum { int, all_int, .. } if all_int => Value::from(int),
+            Acc::Sum { float, .. } => Value::from(float),
+            Acc::Avg { n: 0, .. } => Value::Null,
+            Acc::Avg { sum, n } => Value::from(sum / n as f64),
+            Acc::Min(v) | Acc::Max(v) => v,
+        }
+    }
+}
+
+fn number(value: &Value) -> Result<f64> {
+    value
+        .as_f64()
+        .ok_or_else(|| err!(InvalidQuery, "Cannot sum or average {}", value))
+}
+
+// a total order for sorting: null < bool < number < string
+fn order(a: &Value, b: &Value) -> Ordering {
+    let rank = |v: &Value| match v {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        _ => 4,
+    };
+    compare(a, b).unwrap_or_else(|| rank(a).cmp(&rank(b)))
+}
+
+// `sum(price)` -> (Sum, "price")
+fn aggregate(item: &str) -> Option<(AggFunc, &str)> {
+    let (name, rest) = item.split_once('(')?;
+    let func = match name.trim().to_ascii_lowercase().as_str() {
+        "count" => AggFunc::Count,
+        "sum" => AggFunc::Sum,
+        "avg" => AggFunc::Avg,
+        "min" => AggFunc::Min,
+        "max" => AggFunc::Max,
+        _ => return None,
+    };
+    // the parenthesis has to close at the very end, `sum(a) + sum(b)` is not one call
+    let arg = rest.strip_suffix(')')?;
+    split_top_level(arg, ',')
+        .is_some()
+        .then_some((func, arg.trim()))
+}
+
+// `expr as name`
+fn split_alias(item: &str) -> (&str, Option<&str>) {
+    let lower = item.to_ascii_lowercase();
+    match lower.rfind(" as ") {
+        Some(pos) if !item[pos + 4..].trim().contains(char::is_whitespace) => {
+            (item[..pos].trim(), Some(item[pos + 4..].trim()))
+        }
+        _ => (item, None),
+    }
+}
+
+fn split_list(list: &str) -> Vec<&str> {
+    split_top_level(list, ',')
+        .unwrap_or_else(|| vec![list])
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// split on `sep` outside quotes, parentheses and brackets, None when they
+// don't balance
+pub(crate) fn split_top_level(s: &str, sep: char) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth = depth.checked_sub(1)?,
+            (None, c) if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 || quote.is_some() {
+        return None;
+    }
+    parts.push(&s[start..]);
+    Some(parts)
+}
+
+// csv and ndjson rows go straight out, the other formats need the whole list
+enum RowWriter<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Ndjson {
+        writer: W,
+        headers: Vec<String>,
+    },
+    Buffered {
+        writer: W,
+        format: DataFormat,
+        headers: Vec<String>,
+        rows: Vec<Value>,
+    },
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(writer: W, format: DataFormat, headers: Vec<String>) -> Result<Self> {
+        let out = match format {
+            DataFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(writer);
+                csv.write_record(&headers)?;
+                RowWriter::Csv(Box::new(csv))
+            }
+            DataFormat::Ndjson => RowWriter::Ndjson { writer, headers },
+            format => RowWriter::Buffered {
+                writer,
+                format,
+                headers,
+                rows: Vec::new(),
+            },
+        };
+        Ok(out)
+    }
+
+    fn write(&mut self, row: Vec<Value>) -> Result<()> {
+        match self {
+            RowWriter::Csv(csv) => csv.write_record(row.iter().map(|v| match v {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            }))?,
+            RowWriter::Ndjson { writer, headers } => {
+                serde_json::to_writer(&mut *writer, &record(headers, row))?;
+                writeln!(writer)?;
+            }
+            RowWriter::Buffered { headers, rows, .. } => {
+                rows.push(Value::Object(record(headers, row)));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            RowWriter::Csv(mut csv) => csv.flush()?,
+            RowWriter::Ndjson { mut writer, .. } => writer.flush()?,
+            RowWriter::Buffered {
+                mut writer,
+                format,
+                rows,
+                ..
+            } => {
+                write(&mut writer, format, Document::Many(rows))?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn record(headers: &[String], row: Vec<Value>) -> Map<String, Value> {
+    headers.iter().cloned().zip(row).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const PEOPLE: &str = "\
+name,age,country,salary
+Li,35,CN,100
+Wang,28,CN,80
+Sato,41,JP,120
+Kim,,KR,90
+Zhao,52,CN,
+";
+
+    fn query(args: &[&str]) -> Result<String> {
+        let mut argv = vec!["query"];
+        argv.extend_from_slice(args);
+        let opts = CsvQueryOpts::try_parse_from(argv).expect("valid arguments");
+        let mut out = Vec::new();
+        run(PEOPLE.as_bytes(), &mut out, &opts, &CsvOptions::default())?;
+        Ok(String::from_utf8(out).expect("utf-8 output"))
+    }
+
+    #[test]
+    fn test_filter_select_limit() -> Result<()> {
+        let output = query(&[
+            "--select",
+            "name, age + 1 as next",
+            "--where",
+            "age > 30 && country == 'CN'",
+        ])?;
+        assert_eq!(output, "name,next\nLi,36\nZhao,53\n");
+        let output = query(&["--select", "name", "--limit", "2"])?;
+        assert_eq!(output, "name\nLi\nWang\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort() -> Result<()> {
+        let output = query(&["--select", "name,age", "--sort", "age desc", "--limit", "3"])?;
+        assert_eq!(output, "name,age\nZhao,52\nSato,41\nLi,35\n");
+        // nulls sort first
+        let output = query(&["--select", "name", "--sort", "salary,name"])?;
+        assert_eq!(output, "name\nZhao\nWang\nKim\nLi\nSato\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by() -> Result<()> {
+        let output = query(&[
+            "--select",
+            "country, count(*) as n, count(salary), sum(salary), avg(age), min(name), max(age)",
+            "--group-by",
+            "country",
+            "--sort",
+            "n desc, country",
+        ])?;
+        assert_eq!(
+            output,
+            "country,n,count(salary),sum(salary),avg(age),min(name),max(age)\n\
+             CN,3,2,180,38.333333333333336,Li,52\n\
+             JP,1,1,120,41.0,Sato,41\n\
+             KR,1,1,90,,Kim,\n"
+        );
+        let output = query(&[
+            "--group-by",
+            "country",
+            "--format",
+            "ndjson",
+            "--limit",
+            "1",
+        ])?;
+        assert_eq!(output, "{\"country\":\"CN\",\"count\":3}\n");
+        let output = query(&["--select", "sum(salary) / count(*)"]);
+        assert!(output.is_err());
+        let output = query(&["--select", "sum(salary), count(*)"])?;
+        assert_eq!(output, "sum(salary),count(*)\n390,5\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_over_no_rows() -> Result<()> {
+        let output = query(&[
+            "--select",
+            "count(*), sum(salary), avg(age), min(name)",
+            "--where",
+            "age > 100",
+        ])?;
+        assert_eq!(output, "count(*),sum(salary),avg(age),min(name)\n0,,,\n");
+        // grouped, no group means no row
+        let output = query(&["--group-by", "country", "--where", "age > 100"])?;
+        assert_eq!(output, "country,count\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        assert!(query(&["--select", "name, count(*)"]).is_err());
+        assert!(query(&["--select", "name", "--group-by", "country"]).is_err());
+        assert!(query(&["--sort", "height"]).is_err());
+        assert!(query(&["--group-by", "country", "--sort", "age"]).is_err());
+        assert!(query(&["--where", "height > 1"]).is_err());
+        assert!(query(&["--select", "sum(name)"]).is_err());
+    }
+
+    #[test]
+    fn test_split_top_level() {
+        assert_eq!(
+            split_list("a, contains(b, ','), 'x,y'"),
+            vec!["a", "contains(b, ',')", "'x,y'"]
+        );
+        assert_eq!(split_alias("avg(age) AS mean"), ("avg(age)", Some("mean")));
+        assert_eq!(aggregate("sum(a) + sum(b)"), None);
+        assert_eq!(aggregate("count(*)"), Some((AggFunc::Count, "*")));
+    }
+}
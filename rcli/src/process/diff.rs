@@ -0,0 +1,524 @@
+use super::expr::compare;
+use super::query::{read_value, write_value};
+use crate::cli::{DataFormat, DiffFormat};
+use crate::error::{Result, bail, err};
+use serde_json::{Map, Value, json};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+    // an array item matched by `field`, added items have no index and go
+    // to the end
+    Item {
+        field: String,
+        value: Value,
+        index: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(Vec<PathSegment>, Value),
+    Removed(Vec<PathSegment>, Value),
+    Changed(Vec<PathSegment>, Value, Value),
+}
+
+// the rendered difference, and whether there is any
+pub fn process_diff(
+    old: &Value,
+    new: &Value,
+    key: Option<&str>,
+    format: DiffFormat,
+    color: bool,
+) -> Result<(String, bool)> {
+    let changes = diff(old, new, key);
+    let rendered = match format {
+        DiffFormat::Text => render_text(&changes, color),
+        DiffFormat::JsonPatch => serde_json::to_string_pretty(&json_patch(&changes))? + "\n",
+        DiffFormat::MergePatch => serde_json::to_string_pretty(&merge_patch(old, new))? + "\n",
+    };
+    Ok((rendered, !changes.is_empty()))
+}
+
+// applies a JSON Patch (an array) or a JSON Merge Patch (anything else),
+// and writes the document in its own format
+pub fn process_patch(
+    reader: impl Read,
+    format: DataFormat,
+    patch: &Value,
+    mut writer: impl Write,
+) -> Result<()> {
+    let mut document = read_value(reader, format)?;
+    match patch {
+        Value::Array(operations) => apply_json_patch(&mut document, operations)?,
+        patch => apply_merge_patch(&mut document, patch),
+    }
+    writer.write_all(write_value(&document, format)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn diff(old: &Value, new: &Value, key: Option<&str>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_values(old, new, key, &mut Vec::new(), &mut changes);
+    changes
+}
+
+// changes come out in an order a JSON Patch can apply them: changes inside
+// array items first, then removals from the back, then additions
+fn diff_values(
+    old: &Value,
+    new: &Value,
+    key: Option<&str>,
+    path: &mut Vec<PathSegment>,
+    changes: &mut Vec<Change>,
+) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (k, v) in a {
+                path.push(PathSegment::Key(k.clone()));
+                match b.get(k) {
+                    Some(w) => diff_values(v, w, key, path, changes),
+                    None => changes.push(Change::Removed(path.clone(), v.clone())),
+                }
+                path.pop();
+            }
+            for (k, w) in b.iter().filter(|(k, _)| !a.contains_key(*k)) {
+                path.push(PathSegment::Key(k.clone()));
+                changes.push(Change::Added(path.clone(), w.clone()));
+                path.pop();
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            match key.filter(|field| keyed(a, field) && keyed(b, field)) {
+                Some(field) => diff_keyed(a, b, field, key, path, changes),
+                None => {
+                    for (i, (v, w)) in a.iter().zip(b).enumerate() {
+                        path.push(PathSegment::Index(i));
+                        diff_values(v, w, key, path, changes);
+                        path.pop();
+                    }
+                    for i in (b.len()..a.len()).rev() {
+                        path.push(PathSegment::Index(i));
+                        changes.push(Change::Removed(path.clone(), a[i].clone()));
+                        path.pop();
+                    }
+                    for (i, w) in b.iter().enumerate().skip(a.len()) {
+                        path.push(PathSegment::Index(i));
+                        changes.push(Change::Added(path.clone(), w.clone()));
+                        path.pop();
+                    }
+                }
+            }
+        }
+        (a, b) if !equal(a, b) => changes.push(Change::Changed(path.clone(), a.clone(), b.clone())),
+        _ => {}
+    }
+}
+
+// the order of items matched by key doesn't matter
+fn diff_keyed(
+    a: &[Value],
+    b: &[Value],
+    field: &str,
+    key: Option<&str>,
+    path: &mut Vec<PathSegment>,
+    changes: &mut Vec<Change>,
+) {
+    let find = |items: &[Value], id: &Value| items.iter().position(|item| equal(&item[field], id));
+    let item = |id: &Value, index| PathSegment::Item {
+        field: field.to_string(),
+        value: id.clone(),
+        index,
+    };
+    for (i, v) in a.iter().enumerate() {
+        if let Some(j) = find(b, &v[field]) {
+            path.push(item(&v[field], Some(i)));
+            diff_values(v, &b[j], key, path, changes);
+            path.pop();
+        }
+    }
+    for (i, v) in a.iter().enumerate().rev() {
+        if find(b, &v[field]).is_none() {
+            path.push(item(&v[field], Some(i)));
+            changes.push(Change::Removed(path.clone(), v.clone()));
+            path.pop();
+        }
+    }
+    for w in b {
+        if find(a, &w[field]).is_none() {
+            path.push(item(&w[field], None));
+            changes.push(Change::Added(path.clone(), w.clone()));
+            path.pop();
+        }
+    }
+}
+
+// every item is an object with a unique scalar `field`
+fn keyed(items: &[Value], field: &str) -> bool {
+    let mut seen = HashSet::new();
+    items.iter().all(|item| match item.get(field) {
+        Some(id @ (Value::String(_) | Value::Number(_) | Value::Bool(_))) => {
+            seen.insert(id.to_string())
+        }
+        _ => false,
+    })
+}
+
+// deep equality where 1 and 1.0 are the same, as YAML and TOML may differ
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_)) => compare(a, b) == Some(Ordering::Equal),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(v, w)| equal(v, w))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).is_some_and(|w| equal(v, w)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+// the path in `rcli query` syntax
+pub fn display_path(path: &[PathSegment]) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+    path.iter()
+        .map(|segment| match segment {
+            PathSegment::Key(k)
+                if !k.is_empty() && k.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                format!(".{}", k)
+            }
+            PathSegment::Key(k) => format!(".{}", Value::from(k.as_str())),
+            PathSegment::Index(i) => format!("[{}]", i),
+            PathSegment::Item { field, value, .. } => format!("[?{} == {}]", field, value),
+        })
+        .collect()
+}
+
+// RFC 6901, with "-" for the end of an array
+fn pointer(path: &[PathSegment]) -> String {
+    path.iter()
+        .map(|segment| {
+            let token = match segment {
+                PathSegment::Key(k) => k.replace('~', "~0").replace('/', "~1"),
+                PathSegment::Index(i) | PathSegment::Item { index: Some(i), .. } => i.to_string(),
+                PathSegment::Item { index: None, .. } => "-".to_string(),
+            };
+            format!("/{}", token)
+        })
+        .collect()
+}
+
+fn render_text(changes: &[Change], color: bool) -> String {
+    let paint = |code: &str, line: String| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m\n", code, line)
+        } else {
+            format!("{}\n", line)
+        }
+    };
+    changes
+        .iter()
+        .map(|change| match change {
+            Change::Added(path, value) => {
+                paint("32", format!("+ {}: {}", display_path(path), value))
+            }
+            Change::Removed(path, value) => {
+                paint("31", format!("- {}: {}", display_path(path), value))
+            }
+            Change::Changed(path, old, new) => paint(
+                "33",
+                format!("~ {}: {} -> {}", display_path(path), old, new),
+            ),
+        })
+        .collect()
+}
+
+pub fn json_patch(changes: &[Change]) -> Value {
+    changes
+        .iter()
+        .map(|change| match change {
+            Change::Added(path, value) => {
+                json!({"op": "add", "path": pointer(path), "value": value})
+            }
+            Change::Removed(path, _) => json!({"op": "remove", "path": pointer(path)}),
+            Change::Changed(path, _, value) => {
+                json!({"op": "replace", "path": pointer(path), "value": value})
+            }
+        })
+        .collect()
+}
+
+// RFC 7396: null removes a key and arrays are replaced as a whole
+pub fn merge_patch(old: &Value, new: &Value) -> Value {
+    let (Value::Object(a), Value::Object(b)) = (old, new) else {
+        return new.clone();
+    };
+    let mut patch = Map::new();
+    for k in a.keys().filter(|k| !b.contains_key(*k)) {
+        patch.insert(k.clone(), Value::Null);
+    }
+    for (k, w) in b {
+        match a.get(k) {
+            Some(v) if equal(v, w) => {}
+            Some(v) => {
+                patch.insert(k.clone(), merge_patch(v, w));
+            }
+            None => {
+                patch.insert(k.clone(), w.clone());
+            }
+        }
+    }
+    Value::Object(patch)
+}
+
+// all or nothing: the document is left alone when an operation fails
+pub fn apply_json_patch(document: &mut Value, operations: &[Value]) -> Result<()> {
+    let mut patched = document.clone();
+    for (idx, operation) in operations.iter().enumerate() {
+        apply_operation(&mut patched, operation)
+            .map_err(|e| err!(InvalidInput, source = e, "JSON Patch operation {}", idx))?;
+    }
+    *document = patched;
+    Ok(())
+}
+
+fn apply_operation(document: &mut Value, operation: &Value) -> Result<()> {
+    let field = |name: &str| {
+        operation
+            .get(name)
+            .ok_or_else(|| err!(InvalidInput, "missing \"{}\"", name))
+    };
+    let text = |name: &str| -> Result<&str> {
+        field(name)?
+            .as_str()
+            .ok_or_else(|| err!(InvalidInput, "\"{}\" must be a string", name))
+    };
+    let path = text("path")?;
+    match text("op")? {
+        "add" => add(document, path, field("value")?.clone()),
+        "remove" => remove(document, path).map(|_| ()),
+        "replace" => {
+            *document
+                .pointer_mut(path)
+                .ok_or_else(|| err!(InvalidInput, "{} doesn't exist", path))? =
+                field("value")?.clone();
+            Ok(())
+        }
+        "move" => {
+            let from = text("from")?;
+            if path.starts_with(&format!("{}/", from)) {
+                bail!(InvalidInput, "cannot move {} into itself", from);
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        "copy" => {
+            let value = document
+                .pointer(text("from")?)
+                .ok_or_else(|| {
+                    err!(
+                        InvalidInput,
+                        "{} doesn't exist",
+                        text("from").unwrap_or_default()
+                    )
+                })?
+                .clone();
+            add(document, path, value)
+        }
+        "test" => match document.pointer(path) {
+            Some(value) if equal(value, field("value")?) => Ok(()),
+            _ => bail!(InvalidInput, "test failed at {}", path),
+        },
+        op => bail!(InvalidInput, "unknown op {}", op),
+    }
+}
+
+// the parent of the last token, and that token
+fn split_pointer(path: &str) -> Result<(&str, String)> {
+    let (parent, last) = path
+        .rsplit_once('/')
+        .ok_or_else(|| err!(InvalidInput, "{} is not a JSON pointer", path))?;
+    Ok((parent, last.replace("~1", "/").replace("~0", "~")))
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> Result<()> {
+    if path.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+    let (parent, last) = split_pointer(path)?;
+    match document.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.insert(last, value);
+        }
+        Some(Value::Array(items)) => {
+            let idx = if last == "-" {
+                items.len()
+            } else {
+                last.parse()
+                    .map_err(|_| err!(InvalidInput, "{} is not an array index", last))?
+            };
+            if idx > items.len() {
+                bail!(InvalidInput, "{} is out of range", path);
+            }
+            items.insert(idx, value);
+        }
+        Some(_) => bail!(InvalidInput, "{} is not an object or array", parent),
+        None => bail!(InvalidInput, "{} doesn't exist", parent),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, path: &str) -> Result<Value> {
+    let (parent, last) = split_pointer(path)?;
+    let removed = match document.pointer_mut(parent) {
+        Some(Value::Object(map)) => map.shift_remove(&last),
+        Some(Value::Array(items)) => last
+            .parse::<usize>()
+            .ok()
+            .filter(|idx| *idx < items.len())
+            .map(|idx| items.remove(idx)),
+        _ => None,
+    };
+    removed.ok_or_else(|| err!(InvalidInput, "{} doesn't exist", path))
+}
+
+pub fn apply_merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = target {
+        for (k, v) in patch {
+            if v.is_null() {
+                map.shift_remove(k);
+            } else {
+                apply_merge_patch(map.entry(k.as_str()).or_insert(Value::Null), v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn old() -> Value {
+        json!({
+            "server": {"host": "localhost", "port": 8080, "a/b": 1},
+            "debug": true,
+            "users": [
+                {"id": 1, "name": "Buffon"},
+                {"id": 2, "name": "Perin"},
+                {"id": 3, "name": "Pinsoglio"}
+            ],
+            "tags": ["a", "b", "c"]
+        })
+    }
+
+    fn new() -> Value {
+        json!({
+            "server": {"host": "0.0.0.0", "port": 8080.0, "a/b": 2},
+            "users": [
+                {"id": 3, "name": "Pinsoglio"},
+                {"id": 1, "name": "Gigi"},
+                {"id": 4, "name": "Di Gregorio"}
+            ],
+            "tags": ["a"],
+            "replicas": 3
+        })
+    }
+
+    #[test]
+    fn test_diff_by_index_and_by_key() {
+        let text = render_text(&diff(&old(), &new(), None), false);
+        assert_eq!(
+            text,
+            "~ .server.host: \"localhost\" -> \"0.0.0.0\"\n\
+             ~ .server.\"a/b\": 1 -> 2\n\
+             - .debug: true\n\
+             ~ .users[0].id: 1 -> 3\n\
+             ~ .users[0].name: \"Buffon\" -> \"Pinsoglio\"\n\
+             ~ .users[1].id: 2 -> 1\n\
+             ~ .users[1].name: \"Perin\" -> \"Gigi\"\n\
+             ~ .users[2].id: 3 -> 4\n\
+             ~ .users[2].name: \"Pinsoglio\" -> \"Di Gregorio\"\n\
+             - .tags[2]: \"c\"\n\
+             - .tags[1]: \"b\"\n\
+             + .replicas: 3\n"
+        );
+        let text = render_text(&diff(&old(), &new(), Some("id")), false);
+        assert!(text.contains("~ .users[?id == 1].name: \"Buffon\" -> \"Gigi\"\n"));
+        assert!(text.contains("- .users[?id == 2]: {\"id\":2,\"name\":\"Perin\"}\n"));
+        assert!(text.contains("+ .users[?id == 4]: {\"id\":4,\"name\":\"Di Gregorio\"}\n"));
+        assert!(!text.contains("Pinsoglio"));
+        assert!(diff(&old(), &old(), Some("id")).is_empty());
+    }
+
+    #[test]
+    fn test_json_patch_round_trip() -> Result<()> {
+        for key in [None, Some("id")] {
+            let patch = json_patch(&diff(&old(), &new(), key));
+            let mut patched = old();
+            apply_json_patch(&mut patched, patch.as_array().unwrap())?;
+            // items matched by key keep their old order
+            let changes = diff(&patched, &new(), Some("id"));
+            assert!(changes.is_empty(), "{:?}: {:?}", key, changes);
+        }
+        let patch = json_patch(&diff(&old(), &new(), None));
+        assert!(
+            patch
+                .as_array()
+                .unwrap()
+                .contains(&json!({"op": "replace", "path": "/server/a~1b", "value": 2}))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_patch_operations() -> Result<()> {
+        let mut doc = json!({"a": {"b": [1, 2]}, "c": "x"});
+        let ops = json!([
+            {"op": "add", "path": "/a/b/1", "value": 9},
+            {"op": "move", "from": "/c", "path": "/a/c"},
+            {"op": "copy", "from": "/a/b", "path": "/d"},
+            {"op": "test", "path": "/d/1", "value": 9.0}
+        ]);
+        apply_json_patch(&mut doc, ops.as_array().unwrap())?;
+        assert_eq!(
+            doc,
+            json!({"a": {"b": [1, 9, 2], "c": "x"}, "d": [1, 9, 2]})
+        );
+
+        // a failing test leaves the document alone
+        let ops = json!([
+            {"op": "remove", "path": "/d"},
+            {"op": "test", "path": "/a/c", "value": "y"}
+        ]);
+        assert!(apply_json_patch(&mut doc, ops.as_array().unwrap()).is_err());
+        assert!(doc.get("d").is_some());
+        let ops = json!([{"op": "remove", "path": "/a/b/5"}]);
+        assert!(apply_json_patch(&mut doc, ops.as_array().unwrap()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_patch_round_trip() {
+        let patch = merge_patch(&old(), &new());
+        assert_eq!(patch["debug"], Value::Null);
+        assert_eq!(patch["server"], json!({"host": "0.0.0.0", "a/b": 2}));
+        let mut patched = old();
+        apply_merge_patch(&mut patched, &patch);
+        assert!(diff(&patched, &new(), None).is_empty());
+    }
+}
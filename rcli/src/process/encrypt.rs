@@ -0,0 +1,593 @@
+// file encryption with ChaCha20-Poly1305 in the STREAM construction
+// (https://eprint.iacr.org/2015/189), so files of any size are processed
+// chunk by chunk. The layout is
+//
+//   magic "RCLI-ENC" | version | kdf | [argon2id m, t, p, salt] | nonce prefix | chunk size
+//   chunk 0 | chunk 1 | ... | last chunk
+//
+// every chunk is ciphertext plus a 16 byte tag, and the header is the
+// associated data of every chunk so it can't be changed either. The whole
+// output may be wrapped in base64, decryption detects that on its own.
+use crate::error::{RcliError, Result, bail, err};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::read::DecoderReader;
+use base64::write::EncoderWriter;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"RCLI-ENC";
+const VERSION: u8 = 1;
+const KDF_KEY_FILE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+pub(crate) const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 16;
+// 12 byte nonce minus STREAM's 4 byte counter and 1 byte last-chunk flag
+pub(crate) const NONCE_PREFIX_SIZE: usize = 7;
+pub(crate) const TAG_SIZE: usize = 16;
+pub(crate) const CHUNK_SIZE: u32 = 64 * 1024;
+
+pub enum Secret {
+    Key(Zeroizing<[u8; KEY_SIZE]>),
+    Passphrase(Zeroizing<String>),
+}
+
+// argon2id cost parameters, the defaults follow the OWASP recommendation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kdf {
+    KeyFile,
+    Argon2id {
+        params: KdfParams,
+        salt: [u8; SALT_SIZE],
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    kdf: Kdf,
+    nonce: [u8; NONCE_PREFIX_SIZE],
+    chunk_size: u32,
+}
+
+// upper bounds for parameters read from a header, so a crafted file can't make
+// decryption allocate gigabytes or run for hours before the tag is checked
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 10;
+const MAX_P_COST: u32 = 16;
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
+impl Secret {
+    pub fn load_key(path: &str) -> Result<Self> {
+        let content = Zeroizing::new(fs::read(path)?);
+        let key = parse_key(&content)
+            .ok_or_else(|| err!(InvalidKey, "Key file {} must hold 32 bytes", path))?;
+        Ok(Secret::Key(key))
+    }
+
+    // a key file wins, then a given passphrase, otherwise ask for one
+    pub fn resolve(key: Option<String>, passphrase: Option<String>, confirm: bool) -> Result<Self> {
+        match (key, passphrase) {
+            (Some(path), _) => Self::load_key(&path),
+            (None, Some(passphrase)) if !passphrase.is_empty() => {
+                Ok(Secret::Passphrase(Zeroizing::new(passphrase)))
+            }
+            _ => Self::prompt(confirm),
+        }
+    }
+
+    // ask on the terminal, twice when encrypting
+    pub fn prompt(confirm: bool) -> Result<Self> {
+        let passphrase = Zeroizing::new(rpassword::prompt_password("Passphrase: ")?);
+        if confirm {
+            let again = Zeroizing::new(rpassword::prompt_password("Confirm passphrase: ")?);
+            if passphrase != again {
+                bail!(InvalidArgument, "Passphrases don't match");
+            }
+        }
+        if passphrase.is_empty() {
+            bail!(InvalidArgument, "Passphrase must not be empty");
+        }
+        Ok(Secret::Passphrase(passphrase))
+    }
+}
+
+// a key is 32 raw bytes, or their base64 encoding
+pub(crate) fn parse_key(content: &[u8]) -> Option<Zeroizing<[u8; KEY_SIZE]>> {
+    if let Ok(key) = content.try_into() {
+        return Some(Zeroizing::new(key));
+    }
+    let text = std::str::from_utf8(content).ok()?.trim();
+    let decoded = Zeroizing::new(
+        STANDARD
+            .decode(text)
+            .or_else(|_| URL_SAFE_NO_PAD.decode(text))
+            .ok()?,
+    );
+    Some(Zeroizing::new(decoded.as_slice().try_into().ok()?))
+}
+
+pub fn process_text_encrypt(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    secret: &Secret,
+    base64: bool,
+) -> Result<()> {
+    armored(writer, base64, |writer| {
+        encrypt(reader, writer, secret, KdfParams::default())
+    })
+}
+
+// a writer may have taken some plaintext by the time tampering is detected
+pub fn process_text_decrypt(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    secret: &Secret,
+) -> Result<()> {
+    decrypt(reader, writer, secret)
+}
+
+// optionally wrap everything `f` writes in base64
+pub(crate) fn armored(
+    writer: &mut dyn Write,
+    base64: bool,
+    f: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    if !base64 {
+        return f(writer);
+    }
+    let mut encoder = EncoderWriter::new(writer, &STANDARD);
+    f(&mut encoder)?;
+    encoder.finish()?.write_all(b"\n")?;
+    Ok(())
+}
+
+// raw files start with the magic, base64 ones with its encoding
+pub(crate) fn unarmored<'a>(reader: impl Read + 'a, magic: &[u8]) -> Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let mut start = Vec::with_capacity(magic.len());
+    (&mut reader)
+        .take(magic.len() as u64)
+        .read_to_end(&mut start)?;
+    let raw = start == magic;
+    let reader = io::Cursor::new(start).chain(reader);
+    if raw {
+        Ok(Box::new(reader))
+    } else {
+        Ok(Box::new(DecoderReader::new(
+            SkipWhitespace(reader),
+            &STANDARD,
+        )))
+    }
+}
+
+fn encrypt(
+    reader: impl Read,
+    mut writer: impl Write,
+    secret: &Secret,
+    params: KdfParams,
+) -> Result<()> {
+    let mut nonce = [0u8; NONCE_PREFIX_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let kdf = match secret {
+        Secret::Key(_) => Kdf::KeyFile,
+        Secret::Passphrase(_) => {
+            let mut salt = [0u8; SALT_SIZE];
+            OsRng.fill_bytes(&mut salt);
+            Kdf::Argon2id { params, salt }
+        }
+    };
+    let header = Header {
+        kdf,
+        nonce,
+        chunk_size: CHUNK_SIZE,
+    };
+    let aad = header.to_bytes();
+    writer.write_all(&aad)?;
+
+    let key = header.derive_key(secret)?;
+    encrypt_chunks(reader, writer, &key, &nonce, &aad)
+}
+
+fn decrypt(reader: impl Read, writer: impl Write, secret: &Secret) -> Result<()> {
+    let mut reader = unarmored(reader, MAGIC)?;
+    let (header, aad) = Header::read(&mut reader)?;
+    let key = header.derive_key(secret)?;
+    decrypt_chunks(
+        reader,
+        writer,
+        &key,
+        &header.nonce,
+        header.chunk_size,
+        &aad,
+        tampered,
+    )
+}
+
+// STREAM over CHUNK_SIZE pieces of the reader, `aad` is bound to every chunk
+pub(crate) fn encrypt_chunks(
+    reader: impl Read,
+    mut writer: impl Write,
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_PREFIX_SIZE],
+    aad: &[u8],
+) -> Result<()> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let mut encryptor = EncryptorBE32::from_aead(cipher, nonce.into());
+
+    let mut reader = ChunkReader::new(reader, CHUNK_SIZE as usize);
+    loop {
+        let (chunk, last) = reader.next_chunk()?;
+        let payload = Payload { msg: chunk, aad };
+        if last {
+            let ciphertext = encryptor
+                .encrypt_last(payload)
+                .map_err(|e| err!(InvalidInput, source = e, "Encryption failed"))?;
+            writer.write_all(&ciphertext)?;
+            return Ok(());
+        }
+        let ciphertext = encryptor
+            .encrypt_next(payload)
+            .map_err(|e| err!(InvalidInput, source = e, "Encryption failed"))?;
+        writer.write_all(&ciphertext)?;
+    }
+}
+
+pub(crate) fn decrypt_chunks(
+    reader: impl Read,
+    mut writer: impl Write,
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_PREFIX_SIZE],
+    chunk_size: u32,
+    aad: &[u8],
+    failed: fn() -> RcliError,
+) -> Result<()> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let mut decryptor = DecryptorBE32::from_aead(cipher, nonce.into());
+
+    let mut reader = ChunkReader::new(reader, chunk_size as usize + TAG_SIZE);
+    loop {
+        let (chunk, last) = reader.next_chunk()?;
+        let payload = Payload { msg: chunk, aad };
+        if last {
+            let plaintext = decryptor.decrypt_last(payload).map_err(|_| failed())?;
+            writer.write_all(&plaintext)?;
+            return Ok(());
+        }
+        let plaintext = decryptor.decrypt_next(payload).map_err(|_| failed())?;
+        writer.write_all(&plaintext)?;
+    }
+}
+
+fn not_encrypted() -> RcliError {
+    err!(InvalidInput, "Input is not a file encrypted by rcli")
+}
+
+fn tampered() -> RcliError {
+    err!(
+        DecryptionFailed,
+        "Decryption failed: the key or passphrase is wrong, or the data was modified"
+    )
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        match &self.kdf {
+            Kdf::KeyFile => buf.push(KDF_KEY_FILE),
+            Kdf::Argon2id { params, salt } => {
+                buf.push(KDF_ARGON2ID);
+                buf.extend_from_slice(&params.m_cost.to_le_bytes());
+                buf.extend_from_slice(&params.t_cost.to_le_bytes());
+                buf.extend_from_slice(&params.p_cost.to_le_bytes());
+                buf.extend_from_slice(salt);
+            }
+        }
+        buf.extend_from_slice(&self.nonce);
+        buf.extend_from_slice(&self.chunk_size.to_le_bytes());
+        buf
+    }
+
+    // also returns the raw bytes, they are the associated data of every chunk
+    fn read(reader: &mut impl Read) -> Result<(Self, Vec<u8>)> {
+        let mut raw = Vec::new();
+        let mut take = |n: usize| -> Result<Vec<u8>> {
+            let mut buf = vec![0u8; n];
+            reader.read_exact(&mut buf).map_err(|_| not_encrypted())?;
+            raw.extend_from_slice(&buf);
+            Ok(buf)
+        };
+        let u32_le = |b: Vec<u8>| u32::from_le_bytes(b.try_into().expect("4 bytes"));
+
+        if take(MAGIC.len())? != MAGIC {
+            return Err(not_encrypted());
+        }
+        let version = take(1)?[0];
+        if version != VERSION {
+            bail!(
+                InvalidInput,
+                "Unsupported encrypted file version {}",
+                version
+            );
+        }
+        let kdf = match take(1)?[0] {
+            KDF_KEY_FILE => Kdf::KeyFile,
+            KDF_ARGON2ID => {
+                let params = KdfParams {
+                    m_cost: u32_le(take(4)?),
+                    t_cost: u32_le(take(4)?),
+                    p_cost: u32_le(take(4)?),
+                };
+                if params.m_cost > MAX_M_COST
+                    || params.t_cost > MAX_T_COST
+                    || params.p_cost > MAX_P_COST
+                {
+                    bail!(
+                        DecryptionFailed,
+                        "Decryption failed: argon2 parameters m={} t={} p={} exceed the limits",
+                        params.m_cost,
+                        params.t_cost,
+                        params.p_cost
+                    );
+                }
+                let salt = take(SALT_SIZE)?.try_into().expect("salt size");
+                Kdf::Argon2id { params, salt }
+            }
+            kdf => bail!(InvalidInput, "Unsupported key derivation {}", kdf),
+        };
+        let nonce = take(NONCE_PREFIX_SIZE)?.try_into().expect("nonce size");
+        let chunk_size = u32_le(take(4)?);
+        if chunk_size == 0 || chunk_size > 16 * 1024 * 1024 {
+            bail!(InvalidInput, "Invalid chunk size {}", chunk_size);
+        }
+        let header = Header {
+            kdf,
+            nonce,
+            chunk_size,
+        };
+        Ok((header, raw))
+    }
+
+    fn derive_key(&self, secret: &Secret) -> Result<Zeroizing<[u8; KEY_SIZE]>> {
+        match (&self.kdf, secret) {
+            (Kdf::KeyFile, Secret::Key(key)) => Ok(key.clone()),
+            (Kdf::Argon2id { params, salt }, Secret::Passphrase(passphrase)) => {
+                let params =
+                    Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_SIZE))
+                        .map_err(|e| err!(InvalidInput, source = e, "Invalid argon2 parameters"))?;
+                let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+                    .map_err(|e| err!(InvalidKey, source = e, "Key derivation failed"))?;
+                Ok(key)
+            }
+            (Kdf::KeyFile, Secret::Passphrase(_)) => {
+                bail!(
+                    InvalidKey,
+                    "This file was encrypted with a key file, use --key"
+                )
+            }
+            (Kdf::Argon2id { .. }, Secret::Key(_)) => {
+                bail!(InvalidKey, "This file was encrypted with a passphrase")
+            }
+        }
+    }
+}
+
+// hands out chunks of exactly `size` bytes, except the last one, and knows
+// which chunk is the last by reading one byte ahead
+struct ChunkReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    size: usize,
+    peeked: Option<u8>,
+}
+
+impl<R: Read> ChunkReader<R> {
+    fn new(reader: R, size: usize) -> Self {
+        Self {
+            reader,
+            buf: Vec::with_capacity(size),
+            size,
+            peeked: None,
+        }
+    }
+
+    fn next_chunk(&mut self) -> Result<(&[u8], bool)> {
+        self.buf.clear();
+        self.buf.extend(self.peeked.take());
+        fill(&mut self.reader, &mut self.buf, self.size)?;
+        let mut next = [0u8; 1];
+        let last = if self.buf.len() < self.size || fill_one(&mut self.reader, &mut next)? == 0 {
+            true
+        } else {
+            self.peeked = Some(next[0]);
+            false
+        };
+        Ok((&self.buf, last))
+    }
+}
+
+fn fill(reader: &mut impl Read, buf: &mut Vec<u8>, size: usize) -> io::Result<()> {
+    let missing = (size - buf.len()) as u64;
+    reader.take(missing).read_to_end(buf)?;
+    Ok(())
+}
+
+fn fill_one(reader: &mut impl Read, buf: &mut [u8; 1]) -> io::Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+// base64 output ends with a newline and may be wrapped by other tools
+struct SkipWhitespace<R>(R);
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.0.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..n {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap argon2 parameters so the tests stay fast
+    const TEST_PARAMS: KdfParams = KdfParams {
+        m_cost: 256,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn passphrase(s: &str) -> Secret {
+        Secret::Passphrase(Zeroizing::new(s.to_string()))
+    }
+
+    fn key(byte: u8) -> Secret {
+        Secret::Key(Zeroizing::new([byte; KEY_SIZE]))
+    }
+
+    fn round_trip(data: &[u8], secret: &Secret) -> Result<Vec<u8>> {
+        let mut encrypted = Vec::new();
+        encrypt(data, &mut encrypted, secret, TEST_PARAMS)?;
+        let mut decrypted = Vec::new();
+        decrypt(&encrypted[..], &mut decrypted, secret)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn test_round_trip_across_chunks() -> Result<()> {
+        let chunk = CHUNK_SIZE as usize;
+        for len in [0, 1, chunk - 1, chunk, chunk + 1, 3 * chunk] {
+            let data = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            assert_eq!(round_trip(&data, &key(7))?, data, "length {}", len);
+        }
+        assert_eq!(
+            round_trip(b"hello world!", &passphrase("correct horse"))?,
+            b"hello world!"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_is_detected() -> Result<()> {
+        let secret = passphrase("correct horse");
+        let mut encoder = EncoderWriter::new(Vec::new(), &STANDARD);
+        encrypt(&b"hello world!"[..], &mut encoder, &secret, TEST_PARAMS)?;
+        let mut encoded = encoder.finish()?;
+        encoded.push(b'\n');
+        assert!(encoded.starts_with(b"UkNMSS1FTkM"));
+
+        let mut decrypted = Vec::new();
+        decrypt(&encoded[..], &mut decrypted, &secret)?;
+        assert_eq!(decrypted, b"hello world!");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampering_fails() -> Result<()> {
+        let data = vec![42u8; CHUNK_SIZE as usize * 2 + 10];
+        let secret = key(1);
+        let mut encrypted = Vec::new();
+        encrypt(&data[..], &mut encrypted, &secret, TEST_PARAMS)?;
+        let header_len = MAGIC.len() + 2 + NONCE_PREFIX_SIZE + 4;
+
+        let mut flipped = encrypted.clone();
+        flipped[header_len + 100] ^= 1;
+        // header bytes are authenticated too
+        let mut nonce = encrypted.clone();
+        nonce[MAGIC.len() + 2] ^= 1;
+        // dropping the final chunk must not look like a shorter file
+        let truncated = encrypted[..header_len + 2 * (CHUNK_SIZE as usize + TAG_SIZE)].to_vec();
+
+        for bad in [flipped, nonce, truncated] {
+            let err = decrypt(&bad[..], &mut Vec::new(), &secret).unwrap_err();
+            assert!(err.to_string().starts_with("Decryption failed"), "{}", err);
+        }
+        assert!(decrypt(&encrypted[..], &mut Vec::new(), &key(2)).is_err());
+        assert!(decrypt(&encrypted[..], &mut Vec::new(), &passphrase("x")).is_err());
+        assert!(decrypt(&b"plain text"[..], &mut Vec::new(), &secret).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_round_trip() -> Result<()> {
+        let header = Header {
+            kdf: Kdf::Argon2id {
+                params: KdfParams::default(),
+                salt: [3; SALT_SIZE],
+            },
+            nonce: [9; NONCE_PREFIX_SIZE],
+            chunk_size: CHUNK_SIZE,
+        };
+        let bytes = header.to_bytes();
+        let (read, raw) = Header::read(&mut &bytes[..])?;
+        assert_eq!(read, header);
+        assert_eq!(raw, bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_kdf_params_are_rejected() {
+        let header = Header {
+            kdf: Kdf::Argon2id {
+                params: KdfParams {
+                    m_cost: u32::MAX,
+                    ..KdfParams::default()
+                },
+                salt: [3; SALT_SIZE],
+            },
+            nonce: [9; NONCE_PREFIX_SIZE],
+            chunk_size: CHUNK_SIZE,
+        };
+        let mut bytes = header.to_bytes();
+        bytes.extend_from_slice(&[0; 64]);
+        let start = std::time::Instant::now();
+        let err = decrypt(&bytes[..], &mut Vec::new(), &passphrase("x")).unwrap_err();
+        assert!(err.to_string().contains("exceed the limits"), "{}", err);
+        assert!(start.elapsed().as_secs() < 1);
+    }
+}
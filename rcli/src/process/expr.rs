@@ -0,0 +1,534 @@
+// a small expression language for `rcli csv query`:
+//
+//   age > 30 && (country == 'CN' || lower(country) == "jp")
+//
+// literals: numbers, 'single' or "double" quoted strings, true, false, null
+// columns:  bare names, or `back ticked` when they contain spaces
+// operators: || && ! == != < <= > >= + - * / % (also `and`, `or`, `not`)
+// functions: lower upper trim len contains starts_with ends_with
+use crate::error::{RcliError, Result, bail, err};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    Column(usize),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Func {
+    Lower,
+    Upper,
+    Trim,
+    Len,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(Value),
+    Op(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")", ",",
+];
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    columns: &'a [String],
+}
+
+// parse `src`, resolving column names against `columns`
+pub(crate) fn parse(src: &str, columns: &[String]) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        columns,
+    };
+    let expr = parser.or()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        bail!(
+            InvalidQuery,
+            "Unexpected {:?} in expression: {}",
+            token,
+            src
+        );
+    }
+    Ok(expr)
+}
+
+// the names `src` refers to, for callers without a fixed set of columns
+pub(crate) fn identifiers(src: &str) -> Result<Vec<String>> {
+    let tokens = tokenize(src)?;
+    let mut names: Vec<String> = Vec::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        let Token::Ident(name) = token else {
+            continue;
+        };
+        let call = matches!(tokens.get(idx + 1), Some(Token::Op("(")));
+        let keyword = matches!(
+            name.to_ascii_lowercase().as_str(),
+            "true" | "false" | "null" | "and" | "or" | "not"
+        );
+        if !call && !keyword && !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    Ok(names)
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' || c == '`' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => s.push(c),
+                        None => bail!(InvalidQuery, "Unterminated string in expression: {}", src),
+                    },
+                    Some((_, q)) if q == c => break,
+                    Some((_, c)) => s.push(c),
+                    None => bail!(InvalidQuery, "Unterminated string in expression: {}", src),
+                }
+            }
+            tokens.push(if c == '`' {
+                Token::Ident(s)
+            } else {
+                Token::Str(s)
+            });
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let text = &src[start..end];
+            let num = match text.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => Value::from(
+                    text.parse::<f64>()
+                        .map_err(|_| err!(InvalidQuery, "Invalid number {} in expression", text))?,
+                ),
+            };
+            tokens.push(Token::Num(num));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(src[start..end].to_string()));
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| src[start..].starts_with(*op))
+                .ok_or_else(|| err!(InvalidQuery, "Unexpected {:?} in expression: {}", c, src))?;
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+impl Parser<'_> {
+    fn peek_op(&self, ops: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(op),
+            Some(Token::Ident(word)) => {
+                let op = match word.to_ascii_lowercase().as_str() {
+                    "and" => "&&",
+                    "or" => "||",
+                    "not" => "!",
+                    _ => return None,
+                };
+                ops.contains(&op).then_some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(found)) if *found == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            other => bail!(
+                InvalidQuery,
+                "Expected {} in expression, found {:?}",
+                op,
+                other
+            ),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut lhs = self.and()?;
+        while self.peek_op(&["||"]).is_some() {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut lhs = self.not()?;
+        while self.peek_op(&["&&"]).is_some() {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.peek_op(&["!"]).is_some() {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let lhs = self.sum()?;
+        let op = match self.peek_op(&["==", "!=", "<", "<=", ">", ">="]) {
+            Some("==") => BinOp::Eq,
+            Some("!=") => BinOp::Ne,
+            Some("<") => BinOp::Lt,
+            Some("<=") => BinOp::Le,
+            Some(">") => BinOp::Gt,
+            Some(">=") => BinOp::Ge,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let mut lhs = self.product()?;
+        while let Some(op) = self.peek_op(&["+", "-"]) {
+            self.pos += 1;
+            let op = if op == "+" { BinOp::Add } else { BinOp::Sub };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_op(&["*", "/", "%"]) {
+            self.pos += 1;
+            let op = match op {
+                "*" => BinOp::Mul,
+                "/" => BinOp::Div,
+                _ => BinOp::Rem,
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.peek_op(&["-"]).is_some() {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| err!(InvalidQuery, "Unexpected end of expression"))?;
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Literal(n)),
+            Token::Str(s) => Ok(Expr::Literal(Value::String(s))),
+            Token::Op("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op(op) => bail!(InvalidQuery, "Unexpected {} in expression", op),
+            Token::Ident(name) if self.peek_op(&["("]).is_some() => {
+                let func = name.parse::<Func>()?;
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek_op(&[")"]).is_none() {
+                    args.push(self.or()?);
+                    while self.peek_op(&[","]).is_some() {
+                        self.pos += 1;
+                        args.push(self.or()?);
+                    }
+                }
+                self.expect(")")?;
+                if args.len() != func.arity() {
+                    bail!(
+                        InvalidQuery,
+                        "{:?} takes {} argument(s)",
+                        func,
+                        func.arity()
+                    );
+                }
+                Ok(Expr::Call(func, args))
+            }
+            Token::Ident(name) => {
+                if let Some(idx) = self.columns.iter().position(|c| *c == name) {
+                    return Ok(Expr::Column(idx));
+                }
+                match name.to_ascii_lowercase().as_str() {
+                    "true" => Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => Ok(Expr::Literal(Value::Bool(false))),
+                    "null" => Ok(Expr::Literal(Value::Null)),
+                    _ => bail!(InvalidQuery, "Unknown column {}", name),
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Func {
+    type Err = RcliError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "lower" => Ok(Func::Lower),
+            "upper" => Ok(Func::Upper),
+            "trim" => Ok(Func::Trim),
+            "len" | "length" => Ok(Func::Len),
+            "contains" => Ok(Func::Contains),
+            "starts_with" => Ok(Func::StartsWith),
+            "ends_with" => Ok(Func::EndsWith),
+            _ => bail!(InvalidQuery, "Unknown function {}", name),
+        }
+    }
+}
+
+impl Func {
+    fn arity(&self) -> usize {
+        match self {
+            Func::Lower | Func::Upper | Func::Trim | Func::Len => 1,
+            Func::Contains | Func::StartsWith | Func::EndsWith => 2,
+        }
+    }
+}
+
+impl Expr {
+    pub(crate) fn eval(&self, row: &[Value]) -> Result<Value> {
+        let value = match self {
+            Expr::Literal(v) => v.clone(),
+            Expr::Column(idx) => row.get(*idx).cloned().unwrap_or(Value::Null),
+            Expr::Not(e) => Value::Bool(!truthy(&e.eval(row)?)),
+            Expr::Neg(e) => match e.eval(row)? {
+                Value::Null => Value::Null,
+                v => arithmetic(BinOp::Sub, &Value::from(0), &v)?,
+            },
+            Expr::And(a, b) => Value::Bool(truthy(&a.eval(row)?) && truthy(&b.eval(row)?)),
+            Expr::Or(a, b) => Value::Bool(truthy(&a.eval(row)?) || truthy(&b.eval(row)?)),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(row)?, b.eval(row)?);
+                match op {
+                    BinOp::Eq => Value::Bool(compare(&a, &b) == Some(Ordering::Equal)),
+                    BinOp::Ne => Value::Bool(compare(&a, &b) != Some(Ordering::Equal)),
+                    BinOp::Lt => Value::Bool(compare(&a, &b) == Some(Ordering::Less)),
+                    BinOp::Le => Value::Bool(matches!(
+                        compare(&a, &b),
+                        Some(Ordering::Less | Ordering::Equal)
+                    )),
+                    BinOp::Gt => Value::Bool(compare(&a, &b) == Some(Ordering::Greater)),
+                    BinOp::Ge => Value::Bool(matches!(
+                        compare(&a, &b),
+                        Some(Ordering::Greater | Ordering::Equal)
+                    )),
+                    op => arithmetic(*op, &a, &b)?,
+                }
+            }
+            Expr::Call(func, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(row))
+                    .collect::<Result<Vec<_>>>()?;
+                call(*func, &args)
+            }
+        };
+        Ok(value)
+    }
+
+    pub(crate) fn matches(&self, row: &[Value]) -> Result<bool> {
+        Ok(truthy(&self.eval(row)?))
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    matches!(value, Value::Bool(true))
+}
+
+// values of different types (other than two numbers) never compare
+pub(crate) fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+// integers stay integers while they fit, null in gives null out
+fn arithmetic(op: BinOp, a: &Value, b: &Value) -> Result<Value> {
+    let (Value::Number(x), Value::Number(y)) = (a, b) else {
+        if a.is_null() || b.is_null() {
+            return Ok(Value::Null);
+        }
+        bail!(InvalidQuery, "Cannot apply {:?} to {} and {}", op, a, b);
+    };
+    if let (Some(x), Some(y)) = (x.as_i64(), y.as_i64()) {
+        let result = match op {
+            BinOp::Add => x.checked_add(y),
+            BinOp::Sub => x.checked_sub(y),
+            BinOp::Mul => x.checked_mul(y),
+            BinOp::Rem => x.checked_rem(y),
+            _ => None,
+        };
+        if let Some(result) = result {
+            return Ok(Value::from(result));
+        }
+    }
+    let (x, y) = (
+        x.as_f64().unwrap_or(f64::NAN),
+        y.as_f64().unwrap_or(f64::NAN),
+    );
+    let result = match op {
+        BinOp::Add => x + y,
+        BinOp::Sub => x - y,
+        BinOp::Mul => x * y,
+        BinOp::Div => x / y,
+        _ => x % y,
+    };
+    // json has no nan or infinity, e.g. division by zero
+    Ok(serde_json::Number::from_f64(result).map_or(Value::Null, Value::Number))
+}
+
+fn call(func: Func, args: &[Value]) -> Value {
+    let text = |idx: usize| match &args[idx] {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        v => Some(v.to_string()),
+    };
+    let Some(s) = text(0) else {
+        return Value::Null;
+    };
+    match func {
+        Func::Lower => Value::from(s.to_lowercase()),
+        Func::Upper => Value::from(s.to_uppercase()),
+        Func::Trim => Value::from(s.trim()),
+        Func::Len => Value::from(s.chars().count()),
+        Func::Contains => text(1).map_or(Value::Null, |p| Value::Bool(s.contains(&p))),
+        Func::StartsWith => text(1).map_or(Value::Null, |p| Value::Bool(s.starts_with(&p))),
+        Func::EndsWith => text(1).map_or(Value::Null, |p| Value::Bool(s.ends_with(&p))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(src: &str, row: Value) -> Result<Value> {
+        let columns = ["name", "age", "country", "home town"].map(String::from);
+        let row = row.as_array().cloned().unwrap_or_default();
+        parse(src, &columns)?.eval(&row)
+    }
+
+    #[test]
+    fn test_eval_conditions() -> Result<()> {
+        let row = json!(["Perin", 26, "IT", "Genova"]);
+        assert_eq!(
+            eval("age > 20 && country == 'IT'", row.clone())?,
+            json!(true)
+        );
+        assert_eq!(
+            eval("age > 30 || not (country != \"IT\")", row.clone())?,
+            json!(true)
+        );
+        assert_eq!(eval("!(age >= 26) or age < 10", row.clone())?, json!(false));
+        assert_eq!(
+            eval("starts_with(lower(name), 'per')", row.clone())?,
+            json!(true)
+        );
+        assert_eq!(eval("len(`home town`) == 6", row.clone())?, json!(true));
+        assert_eq!(eval("age * 2 + 1 - -1", row.clone())?, json!(54));
+        assert_eq!(eval("age / 4", row.clone())?, json!(6.5));
+        // mismatched types are never equal or ordered
+        assert_eq!(eval("age == '26'", row.clone())?, json!(false));
+        assert_eq!(eval("age > 'a'", row)?, json!(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_nulls() -> Result<()> {
+        let row = json!(["Perin", null, null, "Genova"]);
+        assert_eq!(eval("age == null", row.clone())?, json!(true));
+        assert_eq!(eval("age > 1", row.clone())?, json!(false));
+        assert_eq!(eval("age + 1", row.clone())?, json!(null));
+        assert_eq!(eval("upper(country)", row)?, json!(null));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let columns = ["age".to_string()];
+        assert!(parse("height > 1", &columns).is_err());
+        assert!(parse("age >", &columns).is_err());
+        assert!(parse("(age > 1", &columns).is_err());
+        assert!(parse("lower(age, age)", &columns).is_err());
+        assert!(parse("name == 'x", &columns).is_err());
+        assert!(parse("age > 1 2", &columns).is_err());
+    }
+}
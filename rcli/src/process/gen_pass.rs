@@ -1,44 +1,239 @@
+use crate::cli::genpass::GenPassOpts;
+use crate::error::{Result, bail, err};
+use rand::Rng;
 use rand::seq::SliceRandom;
+use std::sync::LazyLock;
+use zxcvbn::zxcvbn;
+
+// I, O, o and 0 are left out by default, --exclude-similar also drops 1 and l
+const UPPERCASE: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+const LOWERCASE: &str = "abcdefghijklmnpqrstuvwxyz";
+const NUMBER: &str = "123456789";
+const SYMBOL: &str = "!@#$%^&*_";
+const SIMILAR: &str = "0O1lIo";
+
+// the EFF large wordlist, "<dice rolls>\t<word>" per line
+static WORDLIST: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
+    include_str!("../../assets/eff_large_wordlist.txt")
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .collect()
+});
+
+pub struct GeneratedPassword {
+    pub password: String,
+    // bits of entropy from how it was generated, not from what it looks like
+    pub entropy: f64,
+    // zxcvbn's 0 (weakest) to 4 (strongest)
+    pub score: u8,
+    // zxcvbn's estimate against a slow hash at 10k guesses per second
+    pub crack_time: String,
+}
 
-const UPPERCASE: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWERCASE: &[u8] = b"abcdefghijklmnpqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
-const SYMBOL: &[u8] = b"!@#$%^&*_";
 pub fn process_genpass(
     length: u8,
     lc: bool,
     uc: bool,
     number: bool,
     symbol: bool,
-) -> anyhow::Result<String> {
+) -> Result<String> {
+    let classes = char_classes(uc, lc, number, symbol, false);
+    random_chars(&classes, length as usize)
+}
+
+pub fn process_genpass_opts(opts: &GenPassOpts) -> Result<Vec<GeneratedPassword>> {
+    (0..opts.count)
+        .map(|_| {
+            let (password, entropy) = generate(opts)?;
+            let estimate = zxcvbn(&password, &[])
+                .map_err(|e| err!(InvalidArgument, source = e, "Cannot rate the password"))?;
+            Ok(GeneratedPassword {
+                entropy,
+                score: estimate.score(),
+                crack_time: estimate
+                    .crack_times()
+                    .offline_slow_hashing_1e4_per_second()
+                    .to_string(),
+                password,
+            })
+        })
+        .collect()
+}
+
+fn generate(opts: &GenPassOpts) -> Result<(String, f64)> {
+    if let Some(words) = opts.words {
+        let passphrase = random_words(words as usize, &opts.separator, opts.capitalize)?;
+        let entropy = words as f64 * (WORDLIST.len() as f64).log2();
+        return Ok((passphrase, entropy));
+    }
+    let classes = match &opts.charset {
+        Some(charset) => {
+            let mut chars = charset.chars().collect::<Vec<_>>();
+            chars.sort_unstable();
+            chars.dedup();
+            if opts.exclude_similar {
+                chars.retain(|c| !SIMILAR.contains(*c));
+            }
+            vec![chars]
+        }
+        None => char_classes(
+            opts.uppercase,
+            opts.lowercase,
+            opts.number,
+            opts.symbol,
+            opts.exclude_similar,
+        ),
+    };
+    let password = random_chars(&classes, opts.length as usize)?;
+    let pool = classes.iter().map(Vec::len).sum::<usize>();
+    Ok((password, opts.length as f64 * (pool as f64).log2()))
+}
+
+fn char_classes(
+    uc: bool,
+    lc: bool,
+    number: bool,
+    symbol: bool,
+    exclude_similar: bool,
+) -> Vec<Vec<char>> {
+    [
+        (uc, UPPERCASE),
+        (lc, LOWERCASE),
+        (number, NUMBER),
+        (symbol, SYMBOL),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, chars)| {
+        chars
+            .chars()
+            .filter(|c| !exclude_similar || !SIMILAR.contains(*c))
+            .collect()
+    })
+    .collect()
+}
+
+// at least one character of every class, the rest from all of them
+fn random_chars(classes: &[Vec<char>], length: usize) -> Result<String> {
+    if classes.iter().all(Vec::is_empty) {
+        bail!(InvalidArgument, "No characters to generate a password from");
+    }
+    if length < classes.len() {
+        bail!(
+            InvalidArgument,
+            "Password length {} is too short to hold all {} character classes",
+            length,
+            classes.len()
+        );
+    }
     let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
+    let mut password = classes
+        .iter()
+        .map(|class| {
+            class
+                .choose(&mut rng)
+                .copied()
+                .ok_or_else(|| err!(InvalidArgument, "A character class is empty"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let chars = classes.concat();
+    while password.len() < length {
+        password.push(chars[rng.gen_range(0..chars.len())]);
+    }
+    password.shuffle(&mut rng);
+    Ok(password.into_iter().collect())
+}
 
-    if uc {
-        chars.extend_from_slice(UPPERCASE);
-        password.push(*UPPERCASE.choose(&mut rng).expect("Expect a uppercase"));
+fn random_words(count: usize, separator: &str, capitalize: bool) -> Result<String> {
+    if count == 0 {
+        bail!(InvalidArgument, "A passphrase needs at least one word");
     }
-    if lc {
-        chars.extend_from_slice(LOWERCASE);
-        password.push(*LOWERCASE.choose(&mut rng).expect("Expect a lowercase"));
+    let mut rng = rand::thread_rng();
+    let words = (0..count)
+        .map(|_| {
+            let word = WORDLIST[rng.gen_range(0..WORDLIST.len())];
+            if !capitalize {
+                return word.to_string();
+            }
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(words.join(separator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn opts(args: &[&str]) -> GenPassOpts {
+        GenPassOpts::parse_from(std::iter::once("genpass").chain(args.iter().copied()))
     }
 
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("Expect a number"));
+    #[test]
+    fn test_wordlist() {
+        assert_eq!(WORDLIST.len(), 7776);
+        assert_eq!(WORDLIST[0], "abacus");
+        assert_eq!(WORDLIST[7775], "zoom");
     }
 
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("Expect a symbol"));
+    #[test]
+    fn test_classes_can_be_turned_off() -> Result<()> {
+        let opts = opts(&["-l", "24", "--symbol", "false", "-n", "false"]);
+        let (password, entropy) = generate(&opts)?;
+        assert_eq!(password.chars().count(), 24);
+        assert!(password.chars().all(|c| c.is_ascii_alphabetic()));
+        assert!((entropy - 24.0 * 49f64.log2()).abs() < 1e-9);
+        Ok(())
     }
 
-    for _ in 0..(length - password.len() as u8) {
-        let c = chars.choose(&mut rng).expect("Chars must not be empty");
-        password.push(*c);
+    #[test]
+    fn test_too_short_is_an_error() {
+        assert!(process_genpass(3, true, true, true, true).is_err());
+        assert!(process_genpass(4, true, true, true, true).is_ok());
+        assert!(process_genpass(8, false, false, false, false).is_err());
     }
-    password.shuffle(&mut rng);
 
-    Ok(String::from_utf8(password).expect("Can't generate a random password"))
+    #[test]
+    fn test_charset_and_exclude_similar() -> Result<()> {
+        let (password, _) = generate(&opts(&["--charset", "01lx", "--exclude-similar"]))?;
+        assert_eq!(password, "x".repeat(16));
+        let (password, _) = generate(&opts(&["-l", "200", "--exclude-similar"]))?;
+        assert!(!password.chars().any(|c| SIMILAR.contains(c)));
+        // the default alphabets only leave out I, O, o and 0
+        let password = process_genpass(200, true, true, true, false)?;
+        assert!(!password.chars().any(|c| "IOo0".contains(c)));
+        let pool = char_classes(true, true, true, true, false).concat();
+        assert!(pool.contains(&'1') && pool.contains(&'l'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_passphrase() -> Result<()> {
+        let generated = process_genpass_opts(&opts(&[
+            "--words",
+            "6",
+            "--separator",
+            " ",
+            "--capitalize",
+            "--count",
+            "3",
+        ]))?;
+        assert_eq!(generated.len(), 3);
+        for generated in generated {
+            let words = generated.password.split(' ').collect::<Vec<_>>();
+            assert_eq!(words.len(), 6);
+            assert!(
+                words
+                    .iter()
+                    .all(|w| w.starts_with(|c: char| c.is_uppercase()))
+            );
+            assert!((generated.entropy - 6.0 * 7776f64.log2()).abs() < 1e-9);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,217 @@
+use crate::cli::HashAlgorithm;
+use crate::error::{Result, bail, err};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+// checksums of files and every file below a directory, in the order given
+// and sorted by path within a directory. `hash_reader` is for stdin
+pub fn process_hash(inputs: &[String], algorithm: HashAlgorithm) -> Result<Vec<(String, String)>> {
+    let mut checksums = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut files = Vec::new();
+            walk(path, &mut files)?;
+            files.sort();
+            let hashed = files
+                .par_iter()
+                .map(|file| {
+                    Ok((
+                        file.display().to_string(),
+                        hash_file(algorithm, file, false)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            checksums.extend(hashed);
+        } else {
+            checksums.push((input.clone(), hash_file(algorithm, path, true)?));
+        }
+    }
+    Ok(checksums)
+}
+
+// verify a `sha256sum` style list, "<hex>  <path>" per line. Every entry is
+// reported, a missing or unreadable file is an error for that entry only
+pub fn process_hash_check(
+    mut checklist: impl Read,
+    algorithm: HashAlgorithm,
+) -> Result<Vec<(String, Result<bool>)>> {
+    let mut content = String::new();
+    checklist.read_to_string(&mut content)?;
+    let entries = parse_checklist(&content, algorithm)?;
+    Ok(entries
+        .into_par_iter()
+        .map(|(expected, path)| {
+            let matched =
+                hash_file(algorithm, Path::new(&path), false).map(|actual| actual == expected);
+            (path, matched)
+        })
+        .collect())
+}
+
+fn parse_checklist(content: &str, algorithm: HashAlgorithm) -> Result<Vec<(String, String)>> {
+    let hex_len = match algorithm {
+        HashAlgorithm::Blake3 | HashAlgorithm::Sha256 => 64,
+        HashAlgorithm::Sha512 => 128,
+    };
+    let mut entries = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // a `*` in front of the path marks binary mode, which changes nothing here
+        let (hex, path) = line
+            .split_once("  ")
+            .or_else(|| line.split_once(" *"))
+            .ok_or_else(|| {
+                err!(
+                    InvalidInput,
+                    "line {}: expected \"<checksum>  <path>\"",
+                    idx + 1
+                )
+            })?;
+        if hex.len() != hex_len || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!(
+                InvalidInput,
+                "line {}: not a {} checksum: {}",
+                idx + 1,
+                algorithm,
+                hex
+            );
+        }
+        entries.push((hex.to_ascii_lowercase(), path.to_string()));
+    }
+    Ok(entries)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// blake3 memory maps the file, and uses all cores for a single large file
+fn hash_file(algorithm: HashAlgorithm, path: &Path, parallel: bool) -> Result<String> {
+    if algorithm == HashAlgorithm::Blake3 {
+        let mut hasher = blake3::Hasher::new();
+        if parallel {
+            hasher.update_mmap_rayon(path)?;
+        } else {
+            hasher.update_mmap(path)?;
+        }
+        return Ok(hasher.finalize().to_hex().to_string());
+    }
+    hash_reader(
+        algorithm,
+        BufReader::with_capacity(64 * 1024, File::open(path)?),
+    )
+}
+
+pub fn hash_reader(algorithm: HashAlgorithm, mut reader: impl Read) -> Result<String> {
+    let digest = match algorithm {
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update_reader(reader)?;
+            hasher.finalize().as_bytes().to_vec()
+        }
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(&mut reader, &mut hasher)?;
+            hasher.finalize().to_vec()
+        }
+        HashAlgorithm::Sha512 => {
+            let mut hasher = Sha512::new();
+            io::copy(&mut reader, &mut hasher)?;
+            hasher.finalize().to_vec()
+        }
+    };
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() -> Result<()> {
+        assert_eq!(
+            hash_reader(HashAlgorithm::Sha256, &b"abc"[..])?,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(hash_reader(HashAlgorithm::Sha512, &b"abc"[..])?.starts_with("ddaf35a193617aba"));
+        assert_eq!(
+            hash_reader(HashAlgorithm::Blake3, &b"abc"[..])?,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_matches_reader() -> Result<()> {
+        let path = Path::new("fixtures/urlsafe_base64.txt");
+        for algorithm in [
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+        ] {
+            let expected = hash_reader(algorithm, File::open(path)?)?;
+            assert_eq!(hash_file(algorithm, path, true)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_and_checklist() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("sub"))?;
+        for (name, content) in [("b.txt", "b"), ("a.txt", "a"), ("sub/c.txt", "c")] {
+            fs::write(dir.path().join(name), content)?;
+        }
+        let root = dir.path().display().to_string();
+        let checksums = process_hash(std::slice::from_ref(&root), HashAlgorithm::Sha256)?;
+        let paths = checksums
+            .iter()
+            .map(|(p, _)| Path::new(p).strip_prefix(&root).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                Path::new("a.txt"),
+                Path::new("b.txt"),
+                Path::new("sub/c.txt")
+            ]
+        );
+
+        let mut checklist = checksums
+            .iter()
+            .map(|(path, hex)| format!("{}  {}\n", hex, path))
+            .collect::<String>();
+        let missing = dir.path().join("missing.txt").display().to_string();
+        checklist.push_str(&format!("{}  {}\n", checksums[0].1, missing));
+        fs::write(dir.path().join("b.txt"), "tampered")?;
+        let results = process_hash_check(checklist.as_bytes(), HashAlgorithm::Sha256)?
+            .into_iter()
+            .map(|(path, matched)| (path, matched.ok()))
+            .collect::<Vec<_>>();
+        let expected = checksums
+            .iter()
+            .map(|(path, _)| path.clone())
+            .zip([Some(true), Some(false), Some(true)])
+            .chain([(missing, None)])
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+
+        assert!(parse_checklist("abc  file\n", HashAlgorithm::Sha256).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+use crate::error::Result;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+use std::fs::Metadata;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+use tower_http::compression::CompressionLayer;
+use tower_http::services::ServeDir;
+use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tracing::{Level, info, warn};
+
+// everything but unreserved characters is escaped in a path segment
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+#[derive(Debug)]
+struct HttpServeState {
+    dir: PathBuf,
+    // blake3 of "user:password", compared in constant time
+    credentials: Option<blake3::Hash>,
+}
+
+pub async fn process_http_serve(
+    dir: PathBuf,
+    addr: SocketAddr,
+    auth: Option<(String, String)>,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving {:?} on http://{}", dir, listener.local_addr()?);
+    if auth.is_none() && !addr.ip().is_loopback() {
+        warn!("Anyone on the network can read these files, use --auth to require a password");
+    }
+    axum::serve(listener, router(dir, auth))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+fn router(dir: PathBuf, auth: Option<(String, String)>) -> Router {
+    let state = Arc::new(HttpServeState {
+        dir,
+        credentials: auth
+            .map(|(user, password)| blake3::hash(format!("{user}:{password}").as_bytes())),
+    });
+    let mut router = Router::new()
+        .fallback(file_handler)
+        .with_state(state.clone());
+    if state.credentials.is_some() {
+        router = router.layer(middleware::from_fn_with_state(state, basic_auth));
+    }
+    router
+        .layer(CompressionLayer::new().gzip(true).br(true))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+}
+
+async fn basic_auth(
+    State(state): State<Arc<HttpServeState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+        .and_then(|(_, credentials)| STANDARD.decode(credentials.trim()).ok());
+    match (provided, state.credentials) {
+        (Some(provided), Some(expected)) if blake3::hash(&provided) == expected => {
+            next.run(req).await
+        }
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [(
+                header::WWW_AUTHENTICATE,
+                r#"Basic realm="rcli", charset="UTF-8""#,
+            )],
+        )
+            .into_response(),
+    }
+}
+
+async fn file_handler(State(state): State<Arc<HttpServeState>>, req: Request) -> Response {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let Some(relative) = resolve(req.uri().path()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let path = state.dir.join(&relative);
+    let Ok(metadata) = tokio::fs::metadata(&path).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if metadata.is_dir() {
+        let uri_path = req.uri().path();
+        if !uri_path.ends_with('/') {
+            return Redirect::temporary(&format!("{}/", uri_path)).into_response();
+        }
+        if !path.join("index.html").is_file() {
+            return match list_dir(&path, uri_path, relative.as_os_str().is_empty()).await {
+                Ok(html) => Html(html).into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            };
+        }
+        return serve_file(&state.dir, req, None).await;
+    }
+
+    let etag = etag(&metadata);
+    if let Some(etag) = &etag
+        && matches_etag(req.headers(), etag)
+    {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag.clone())]).into_response();
+    }
+    serve_file(&state.dir, req, etag).await
+}
+
+// ServeDir does ranges, Last-Modified and content types, we add the ETag
+async fn serve_file(dir: &Path, req: Request, etag: Option<HeaderValue>) -> Response {
+    let mut res = match ServeDir::new(dir).oneshot(req).await {
+        Ok(res) => res.map(Body::new),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Some(etag) = etag
+        && (res.status() == StatusCode::OK || res.status() == StatusCode::PARTIAL_CONTENT)
+    {
+        res.headers_mut().insert(header::ETAG, etag);
+    }
+    res
+}
+
+// the request path relative to the served directory, None for anything
+// that could escape it
+fn resolve(uri_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(uri_path).decode_utf8().ok()?;
+    let mut path = PathBuf::new();
+    for segment in decoded.split('/') {
+        if segment.contains('\\') || segment.contains('\0') {
+            return None;
+        }
+        match Path::new(segment).components().next() {
+            None | Some(Component::CurDir) => {}
+            Some(Component::Normal(name)) => path.push(name),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+// weak, as the body may be compressed on the way out
+fn etag(metadata: &Metadata) -> Option<HeaderValue> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let etag = format!(
+        "W/\"{:x}-{:x}.{:x}\"",
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    );
+    HeaderValue::from_str(&etag).ok()
+}
+
+fn matches_etag(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(candidates) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag.to_str().unwrap_or_default());
+    candidates
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+async fn list_dir(dir: &Path, uri_path: &str, root: bool) -> Result<String> {
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        // follows symlinks, a broken one is listed without size or date
+        let metadata = tokio::fs::metadata(entry.path()).await.ok();
+        let is_dir = metadata.as_ref().is_some_and(Metadata::is_dir);
+        entries.push((
+            !is_dir,
+            entry.file_name().to_string_lossy().into_owned(),
+            metadata,
+        ));
+    }
+    // directories first, then by name
+    entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    let title = escape(&percent_decode_str(uri_path).decode_utf8_lossy());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Index of {title}</title>\n\
+         <style>body {{ font-family: sans-serif; }} td {{ padding: 0 1em; }} .size {{ text-align: right; }}</style>\n\
+         </head>\n<body>\n<h1>Index of {title}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n"
+    );
+    if !root {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for (is_file, name, metadata) in entries {
+        let slash = if is_file { "" } else { "/" };
+        let size = match &metadata {
+            Some(metadata) if is_file => human_size(metadata.len()),
+            _ => String::new(),
+        };
+        let modified = metadata
+            .and_then(|metadata| metadata.modified().ok())
+            .map(httpdate::fmt_http_date)
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{slash}\">{}{slash}</a></td><td class=\"size\">{}</td><td>{}</td></tr>\n",
+            utf8_percent_encode(&name, SEGMENT),
+            escape(&name),
+            size,
+            modified
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    async fn get(router: &Router, uri: &str, headers: &[(&str, &str)]) -> Result<Response> {
+        let mut req = Request::get(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(Body::empty()).expect("valid request");
+        // a router never fails, its errors are responses
+        let Ok(res) = router.clone().oneshot(req).await;
+        Ok(res)
+    }
+
+    async fn text(res: Response) -> Result<String> {
+        let body = to_bytes(res.into_body(), usize::MAX)
+            .await
+            .expect("readable body");
+        Ok(String::from_utf8(body.to_vec()).expect("utf-8 body"))
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("/"), Some(PathBuf::new()));
+        assert_eq!(
+            resolve("/keys/id%5Fed25519"),
+            Some(PathBuf::from("keys/id_ed25519"))
+        );
+        assert_eq!(resolve("/a/./b//c"), Some(PathBuf::from("a/b/c")));
+        assert_eq!(resolve("/../Cargo.toml"), None);
+        assert_eq!(resolve("/keys/%2E%2E/%2E%2E/Cargo.toml"), None);
+        assert_eq!(resolve("/..%5CCargo.toml"), None);
+    }
+
+    #[tokio::test]
+    async fn test_listing_and_redirect() -> Result<()> {
+        let router = router(PathBuf::from("fixtures"), None);
+        let res = get(&router, "/keys", &[]).await?;
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "/keys/");
+
+        let html = text(get(&router, "/", &[]).await?).await?;
+        assert!(html.contains("<a href=\"keys/\">keys/</a>"));
+        assert!(html.contains("<a href=\"blake3.txt\">blake3.txt</a>"));
+        assert!(!html.contains("../"));
+        // directories come first
+        assert!(html.find("keys/").unwrap() < html.find("blake3.txt").unwrap());
+
+        let html = text(get(&router, "/keys/", &[]).await?).await?;
+        assert!(html.contains("<a href=\"../\">../</a>"));
+        assert!(html.contains("id_ed25519.pub"));
+
+        let res = get(&router, "/../Cargo.toml", &[]).await?;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_and_etag() -> Result<()> {
+        let router = router(PathBuf::from("fixtures"), None);
+        let content = std::fs::read_to_string("fixtures/blake3.txt")?;
+
+        let res = get(&router, "/blake3.txt", &[]).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().contains_key(header::LAST_MODIFIED));
+        let etag = res.headers()[header::ETAG]
+            .to_str()
+            .expect("ascii etag")
+            .to_string();
+        assert_eq!(text(res).await?, content);
+
+        let res = get(&router, "/blake3.txt", &[("range", "bytes=0-3")]).await?;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(text(res).await?, content[..4]);
+
+        let res = get(&router, "/blake3.txt", &[("if-none-match", &etag)]).await?;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let res = get(&router, "/blake3.txt", &[("if-none-match", "W/\"other\"")]).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = get(&router, "/urlsafe_base64.txt", &[("accept-encoding", "br")]).await?;
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "br");
+        // partial content is never compressed
+        let res = get(
+            &router,
+            "/urlsafe_base64.txt",
+            &[("accept-encoding", "gzip"), ("range", "bytes=0-99")],
+        )
+        .await?;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_basic_auth() -> Result<()> {
+        let router = router(
+            PathBuf::from("fixtures"),
+            Some(("alice".to_string(), "s3cret".to_string())),
+        );
+        let res = get(&router, "/blake3.txt", &[]).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        let wrong = format!("Basic {}", STANDARD.encode("alice:wrong"));
+        let res = get(&router, "/blake3.txt", &[("authorization", &wrong)]).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let right = format!("Basic {}", STANDARD.encode("alice:s3cret"));
+        let res = get(&router, "/blake3.txt", &[("authorization", &right)]).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+}
@@ -0,0 +1,259 @@
+use crate::cli::IdKind;
+use crate::error::{Result, bail, err};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ulid::Ulid;
+use uuid::{Uuid, Variant};
+
+// 2010-11-04T01:42:54.657Z, twitter's snowflake epoch
+const SNOWFLAKE_EPOCH: u64 = 1_288_834_974_657;
+const NANOID_ALPHABET: [char; 64] = nanoid::alphabet::SAFE;
+
+pub fn process_id_gen(
+    kind: IdKind,
+    count: u32,
+    length: usize,
+    machine: u16,
+) -> Result<Vec<String>> {
+    if kind == IdKind::Nanoid && length == 0 {
+        bail!(InvalidArgument, "A nanoid needs at least one character");
+    }
+    let mut snowflake = Snowflake::new(machine);
+    (0..count)
+        .map(|_| {
+            let id = match kind {
+                IdKind::Uuid4 => Uuid::new_v4().to_string(),
+                IdKind::Uuid7 => Uuid::now_v7().to_string(),
+                IdKind::Ulid => Ulid::new().to_string(),
+                IdKind::Nanoid => nanoid::nanoid!(length, &NANOID_ALPHABET),
+                IdKind::Snowflake => snowflake.next()?.to_string(),
+            };
+            Ok(id)
+        })
+        .collect()
+}
+
+// what an ID tells about itself, as label and value
+pub fn process_id_inspect(id: &str, kind: Option<IdKind>) -> Result<Vec<(&'static str, String)>> {
+    let id = id.trim();
+    match kind {
+        Some(IdKind::Uuid4 | IdKind::Uuid7) => inspect_uuid(id),
+        Some(IdKind::Ulid) => inspect_ulid(id),
+        Some(IdKind::Nanoid) => inspect_nanoid(id),
+        Some(IdKind::Snowflake) => inspect_snowflake(id),
+        None if Uuid::try_parse(id).is_ok() => inspect_uuid(id),
+        None if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) => inspect_snowflake(id),
+        None if id.len() == 26 && Ulid::from_string(id).is_ok() => inspect_ulid(id),
+        None if !id.is_empty() && id.chars().all(|c| NANOID_ALPHABET.contains(&c)) => {
+            inspect_nanoid(id)
+        }
+        None => bail!(
+            InvalidInput,
+            "{} is not a UUID, ULID, nanoid or snowflake",
+            id
+        ),
+    }
+}
+
+fn inspect_uuid(id: &str) -> Result<Vec<(&'static str, String)>> {
+    let uuid = Uuid::try_parse(id).map_err(|e| err!(InvalidInput, source = e, "Invalid UUID"))?;
+    let version = match uuid.get_version_num() {
+        _ if uuid.is_nil() => "nil".to_string(),
+        _ if uuid.is_max() => "max".to_string(),
+        1 => "1 (time and node)".to_string(),
+        2 => "2 (DCE security)".to_string(),
+        3 => "3 (MD5 of a name)".to_string(),
+        4 => "4 (random)".to_string(),
+        5 => "5 (SHA-1 of a name)".to_string(),
+        6 => "6 (reordered time)".to_string(),
+        7 => "7 (Unix time)".to_string(),
+        8 => "8 (custom)".to_string(),
+        n => n.to_string(),
+    };
+    let variant = match uuid.get_variant() {
+        Variant::NCS => "NCS",
+        Variant::RFC4122 => "RFC 9562",
+        Variant::Microsoft => "Microsoft",
+        _ => "reserved",
+    };
+    let mut fields = vec![
+        ("kind", "uuid".to_string()),
+        ("uuid", uuid.hyphenated().to_string()),
+        ("version", version),
+        ("variant", variant.to_string()),
+    ];
+    if let Some(timestamp) = uuid.get_timestamp() {
+        let (secs, nanos) = timestamp.to_unix();
+        fields.push(("timestamp", datetime(secs as i64, nanos)?));
+    }
+    Ok(fields)
+}
+
+fn inspect_ulid(id: &str) -> Result<Vec<(&'static str, String)>> {
+    let ulid = Ulid::from_string(id).map_err(|e| err!(InvalidInput, source = e, "Invalid ULID"))?;
+    let ms = ulid.timestamp_ms();
+    Ok(vec![
+        ("kind", "ulid".to_string()),
+        (
+            "timestamp",
+            datetime((ms / 1000) as i64, (ms % 1000) as u32 * 1_000_000)?,
+        ),
+        ("random", format!("{:020x}", ulid.random())),
+        ("uuid", Uuid::from_u128(ulid.0).to_string()),
+    ])
+}
+
+fn inspect_nanoid(id: &str) -> Result<Vec<(&'static str, String)>> {
+    if let Some(c) = id.chars().find(|c| !NANOID_ALPHABET.contains(c)) {
+        bail!(InvalidInput, "{:?} is not in the nanoid alphabet", c);
+    }
+    // 64 symbols, 6 bits each
+    Ok(vec![
+        ("kind", "nanoid".to_string()),
+        ("length", id.chars().count().to_string()),
+        ("entropy", format!("{} bits", id.chars().count() * 6)),
+        ("timestamp", "none, nanoids are random".to_string()),
+    ])
+}
+
+fn inspect_snowflake(id: &str) -> Result<Vec<(&'static str, String)>> {
+    let id = id
+        .parse::<u64>()
+        .map_err(|e| err!(InvalidInput, source = e, "Invalid snowflake"))?;
+    let ms = (id >> 22) + SNOWFLAKE_EPOCH;
+    Ok(vec![
+        ("kind", "snowflake".to_string()),
+        (
+            "timestamp",
+            datetime((ms / 1000) as i64, (ms % 1000) as u32 * 1_000_000)?,
+        ),
+        ("machine", ((id >> 12) & 0x3ff).to_string()),
+        ("sequence", (id & 0xfff).to_string()),
+    ])
+}
+
+fn datetime(secs: i64, nanos: u32) -> Result<String> {
+    let time = DateTime::<Utc>::from_timestamp(secs, nanos)
+        .ok_or_else(|| err!(InvalidInput, "Timestamp out of range"))?;
+    Ok(time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+struct Snowflake {
+    machine: u64,
+    last: u64,
+    sequence: u64,
+}
+
+impl Snowflake {
+    fn new(machine: u16) -> Self {
+        Self {
+            machine: machine as u64 & 0x3ff,
+            last: 0,
+            sequence: 0,
+        }
+    }
+
+    fn next(&mut self) -> Result<u64> {
+        let mut now = now_ms()?;
+        if now < self.last {
+            bail!(InvalidInput, "The clock went backwards");
+        }
+        if now == self.last {
+            self.sequence = (self.sequence + 1) & 0xfff;
+            // 4096 IDs in this millisecond already, wait for the next one
+            while self.sequence == 0 && now == self.last {
+                now = now_ms()?;
+            }
+        } else {
+            self.sequence = 0;
+        }
+        self.last = now;
+        self.id(now)
+    }
+
+    fn id(&self, now: u64) -> Result<u64> {
+        let since = now.checked_sub(SNOWFLAKE_EPOCH).ok_or_else(|| {
+            err!(
+                InvalidInput,
+                "The clock is before the snowflake epoch (2010)"
+            )
+        })?;
+        Ok((since << 22) | (self.machine << 12) | self.sequence)
+    }
+}
+
+fn now_ms() -> Result<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| err!(InvalidInput, source = e, "The clock is before 1970"))?;
+    Ok(now.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn field(fields: &[(&str, String)], label: &str) -> String {
+        fields
+            .iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_inspect_guesses_the_kind() -> Result<()> {
+        let fields = process_id_inspect("01890a5d-ac96-774b-bcce-b302099a8057", None)?;
+        assert_eq!(field(&fields, "version"), "7 (Unix time)");
+        assert_eq!(field(&fields, "variant"), "RFC 9562");
+        assert_eq!(field(&fields, "timestamp"), "2023-06-30T03:34:18.518Z");
+
+        let fields = process_id_inspect("01ARZ3NDEKTSV4RRFFQ69G5FAV", None)?;
+        assert_eq!(field(&fields, "kind"), "ulid");
+        assert_eq!(field(&fields, "timestamp"), "2016-07-30T23:54:10.259Z");
+
+        let fields = process_id_inspect("1541815603606036480", None)?;
+        assert_eq!(field(&fields, "kind"), "snowflake");
+        assert_eq!(field(&fields, "timestamp"), "2022-06-28T16:07:40.105Z");
+
+        let fields = process_id_inspect("V1StGXR8_Z5jdHi6B-myT", None)?;
+        assert_eq!(field(&fields, "kind"), "nanoid");
+        assert!(process_id_inspect("not an id!", None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snowflake_before_its_epoch() {
+        let snowflake = Snowflake::new(7);
+        assert!(snowflake.id(SNOWFLAKE_EPOCH - 1).is_err());
+        assert_eq!(snowflake.id(SNOWFLAKE_EPOCH).ok(), Some(7 << 12));
+    }
+
+    #[test]
+    fn test_gen_round_trips_through_inspect() -> Result<()> {
+        for kind in [
+            IdKind::Uuid4,
+            IdKind::Uuid7,
+            IdKind::Ulid,
+            IdKind::Nanoid,
+            IdKind::Snowflake,
+        ] {
+            let ids = process_id_gen(kind, 100, 21, 7)?;
+            assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 100);
+            let fields = process_id_inspect(&ids[0], Some(kind))?;
+            if kind != IdKind::Nanoid && kind != IdKind::Uuid4 {
+                let timestamp = DateTime::parse_from_rfc3339(&field(&fields, "timestamp"))
+                    .expect("rfc 3339 timestamp");
+                assert!((Utc::now() - timestamp.to_utc()).num_seconds() < 60);
+            }
+        }
+        let ids = process_id_gen(IdKind::Snowflake, 10_000, 0, 7)?;
+        let ids: Vec<u64> = ids
+            .iter()
+            .map(|id| id.parse().expect("numeric id"))
+            .collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        Ok(())
+    }
+}
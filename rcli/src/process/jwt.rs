@@ -0,0 +1,480 @@
+// compact JWS tokens (RFC 7519) signed with EdDSA, HS256 or ES256. Keys load
+// through `KeyLoader` like `rcli text`, so EdDSA takes the same key files
+// (raw, PEM, OpenSSH or JWK) and rchat's PEM keys work as they are
+use super::key::invalid_key;
+use super::text::{Ed25519Singer, Ed25519Verifier, KeyLoader};
+use crate::cli::{JwtAlgorithm, JwtSignOpts, JwtVerifyOpts};
+use crate::error::{Result, bail, err};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::{Signer, Verifier};
+use hmac::{Hmac, Mac};
+use p256::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use serde_json::{Map, Value, json};
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+struct HmacKey(Zeroizing<Vec<u8>>);
+
+struct Es256Signer(p256::ecdsa::SigningKey);
+
+struct Es256Verifier(p256::ecdsa::VerifyingKey);
+
+enum TokenSigner {
+    EdDsa(Ed25519Singer),
+    Hs256(HmacKey),
+    Es256(Es256Signer),
+}
+
+enum TokenVerifier {
+    EdDsa(Ed25519Verifier),
+    Hs256(HmacKey),
+    Es256(Es256Verifier),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedToken {
+    pub header: Value,
+    pub claims: Value,
+}
+
+// `key` is the content of the file `opts.key` names
+pub fn process_jwt_sign(key: &[u8], opts: &JwtSignOpts) -> Result<String> {
+    let mut claims = match &opts.claims {
+        Some(claims) => match serde_json::from_str(claims)
+            .map_err(|e| err!(InvalidArgument, source = e, "Invalid --claims"))?
+        {
+            Value::Object(claims) => claims,
+            _ => bail!(InvalidArgument, "--claims must be a JSON object"),
+        },
+        None => Map::new(),
+    };
+    let now = now();
+    for (name, value) in [("sub", &opts.sub), ("aud", &opts.aud), ("iss", &opts.iss)] {
+        if let Some(value) = value {
+            claims.insert(name.into(), value.as_str().into());
+        }
+    }
+    claims.insert("iat".into(), now.into());
+    if let Some(exp) = opts.exp {
+        claims.insert("exp".into(), now.saturating_add(exp.as_secs()).into());
+    }
+    let signer = TokenSigner::try_new(opts.alg, key)?;
+    signer.sign(&Value::Object(claims))
+}
+
+pub fn process_jwt_verify(token: &str, key: &[u8], opts: &JwtVerifyOpts) -> Result<DecodedToken> {
+    let verifier = TokenVerifier::try_new(opts.alg, key)?;
+    let expected = Expected {
+        alg: opts.alg,
+        iss: opts.iss.as_deref(),
+        aud: opts.aud.as_deref(),
+        leeway: opts.leeway,
+    };
+    verify(token.trim(), &verifier, &expected, now())
+}
+
+pub fn process_jwt_decode(token: &str) -> Result<DecodedToken> {
+    let (decoded, _, _) = split(token.trim())?;
+    Ok(decoded)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is after 1970")
+        .as_secs()
+}
+
+impl TokenSigner {
+    fn try_new(alg: JwtAlgorithm, key: &[u8]) -> Result<Self> {
+        Ok(match alg {
+            JwtAlgorithm::EdDsa => TokenSigner::EdDsa(Ed25519Singer::try_new(key)?),
+            JwtAlgorithm::Hs256 => TokenSigner::Hs256(HmacKey::try_new(key)?),
+            JwtAlgorithm::Es256 => TokenSigner::Es256(Es256Signer::try_new(key)?),
+        })
+    }
+
+    fn alg(&self) -> JwtAlgorithm {
+        match self {
+            TokenSigner::EdDsa(_) => JwtAlgorithm::EdDsa,
+            TokenSigner::Hs256(_) => JwtAlgorithm::Hs256,
+            TokenSigner::Es256(_) => JwtAlgorithm::Es256,
+        }
+    }
+
+    fn sign(&self, claims: &Value) -> Result<String> {
+        let header = json!({"alg": self.alg().to_string(), "typ": "JWT"});
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let signature = match self {
+            TokenSigner::EdDsa(key) => key
+                .signing_key()
+                .sign(signing_input.as_bytes())
+                .to_bytes()
+                .to_vec(),
+            TokenSigner::Hs256(key) => key
+                .mac(signing_input.as_bytes())?
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            TokenSigner::Es256(Es256Signer(key)) => {
+                let signature: p256::ecdsa::Signature = key.sign(signing_input.as_bytes());
+                signature.to_bytes().to_vec()
+            }
+        };
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+}
+
+impl TokenVerifier {
+    fn try_new(alg: JwtAlgorithm, key: &[u8]) -> Result<Self> {
+        Ok(match alg {
+            JwtAlgorithm::EdDsa => TokenVerifier::EdDsa(Ed25519Verifier::try_new(key)?),
+            JwtAlgorithm::Hs256 => TokenVerifier::Hs256(HmacKey::try_new(key)?),
+            JwtAlgorithm::Es256 => TokenVerifier::Es256(Es256Verifier::try_new(key)?),
+        })
+    }
+
+    fn verify(&self, signing_input: &str, signature: &[u8]) -> Result<bool> {
+        let msg = signing_input.as_bytes();
+        Ok(match self {
+            TokenVerifier::EdDsa(key) => match ed25519_dalek::Signature::from_slice(signature) {
+                Ok(signature) => key.verifying_key().verify(msg, &signature).is_ok(),
+                Err(_) => false,
+            },
+            TokenVerifier::Hs256(key) => key.mac(msg)?.verify_slice(signature).is_ok(),
+            TokenVerifier::Es256(Es256Verifier(key)) => {
+                match p256::ecdsa::Signature::from_slice(signature) {
+                    Ok(signature) => key.verify(msg, &signature).is_ok(),
+                    Err(_) => false,
+                }
+            }
+        })
+    }
+}
+
+struct Expected<'a> {
+    alg: JwtAlgorithm,
+    iss: Option<&'a str>,
+    aud: Option<&'a str>,
+    leeway: Duration,
+}
+
+// the header's alg has to be the one asked for, a token must not pick how
+// it is verified. Claims are checked only once the signature is good, and
+// every failing claim is reported
+fn verify(
+    token: &str,
+    verifier: &TokenVerifier,
+    expected: &Expected,
+    now: u64,
+) -> Result<DecodedToken> {
+    let (decoded, signing_input, signature) = split(token)?;
+    let alg = decoded
+        .header
+        .get("alg")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    if alg != expected.alg.to_string() {
+        bail!(
+            VerificationFailed,
+            "alg: token is signed with {:?}, expected {}",
+            alg,
+            expected.alg
+        );
+    }
+    if !verifier.verify(signing_input, &signature)? {
+        bail!(VerificationFailed, "signature: doesn't match the key");
+    }
+
+    let claims = &decoded.claims;
+    let leeway = expected.leeway.as_secs();
+    let mut failures = Vec::new();
+    match claims.get("exp").map(Value::as_u64) {
+        Some(Some(exp)) if now > exp.saturating_add(leeway) => {
+            failures.push(format!("exp: expired {} ago", human(now - exp)));
+        }
+        Some(None) => failures.push("exp: not a number".to_string()),
+        _ => {}
+    }
+    match claims.get("nbf").map(Value::as_u64) {
+        Some(Some(nbf)) if now.saturating_add(leeway) < nbf => {
+            failures.push(format!("nbf: not valid for another {}", human(nbf - now)));
+        }
+        Some(None) => failures.push("nbf: not a number".to_string()),
+        _ => {}
+    }
+    if let Some(iss) = expected.iss {
+        match claims.get("iss") {
+            Some(Value::String(actual)) if actual == iss => {}
+            Some(actual) => failures.push(format!("iss: is {}, expected {:?}", actual, iss)),
+            None => failures.push(format!("iss: missing, expected {:?}", iss)),
+        }
+    }
+    if let Some(aud) = expected.aud {
+        let matched = match claims.get("aud") {
+            Some(Value::String(actual)) => actual == aud,
+            Some(Value::Array(actual)) => actual.iter().any(|a| a.as_str() == Some(aud)),
+            _ => false,
+        };
+        if !matched {
+            let actual = claims
+                .get("aud")
+                .map(Value::to_string)
+                .unwrap_or("missing".into());
+            failures.push(format!("aud: is {}, expected {:?}", actual, aud));
+        }
+    }
+    if !failures.is_empty() {
+        bail!(VerificationFailed, "{}", failures.join("\n"));
+    }
+    Ok(decoded)
+}
+
+fn split(token: &str) -> Result<(DecodedToken, &str, Vec<u8>)> {
+    let (signing_input, signature) = token
+        .rsplit_once('.')
+        .ok_or_else(|| err!(InvalidInput, "Not a JWT, expected header.claims.signature"))?;
+    let (header, claims) = signing_input
+        .split_once('.')
+        .ok_or_else(|| err!(InvalidInput, "Not a JWT, expected header.claims.signature"))?;
+    let part = |name: &str, part: &str| -> Result<Value> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|e| err!(InvalidInput, source = e, "Invalid {}", name))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| err!(InvalidInput, source = e, "Invalid {}", name))
+    };
+    let decoded = DecodedToken {
+        header: part("header", header)?,
+        claims: part("claims", claims)?,
+    };
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|e| err!(InvalidInput, source = e, "Invalid signature"))?;
+    Ok((decoded, signing_input, signature))
+}
+
+// "3d 4h", "5m 20s", the two largest units
+pub fn human(secs: u64) -> String {
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let parts = units
+        .iter()
+        .scan(secs, |rest, (name, size)| {
+            let n = *rest / size;
+            *rest %= size;
+            Some((n, name))
+        })
+        .skip_while(|(n, _)| *n == 0)
+        .take(2)
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, name)| format!("{}{}", n, name))
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+impl DecodedToken {
+    // "expires in 13d 23h" and the like for the registered time claims
+    pub fn times(&self, now: u64) -> Vec<String> {
+        let claim = |name: &str| self.claims.get(name).and_then(Value::as_u64);
+        let mut lines = Vec::new();
+        if let Some(iat) = claim("iat") {
+            lines.push(format!("issued {} ago", human(now.saturating_sub(iat))));
+        }
+        if let Some(nbf) = claim("nbf")
+            && nbf > now
+        {
+            lines.push(format!("not valid for another {}", human(nbf - now)));
+        }
+        if let Some(exp) = claim("exp") {
+            if exp > now {
+                lines.push(format!("expires in {}", human(exp - now)));
+            } else {
+                lines.push(format!("expired {} ago", human(now - exp)));
+            }
+        }
+        lines
+    }
+}
+
+impl HmacKey {
+    fn mac(&self, msg: &[u8]) -> Result<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).map_err(invalid_key)?;
+        mac.update(msg);
+        Ok(mac)
+    }
+}
+
+// RFC 7518 wants HS256 keys of at least 256 bits
+impl KeyLoader for HmacKey {
+    fn try_new(key: &[u8]) -> Result<Self> {
+        if key.len() < 32 {
+            bail!(InvalidKey, "HS256 keys must be at least 32 bytes");
+        }
+        Ok(HmacKey(Zeroizing::new(key.to_vec())))
+    }
+}
+
+impl KeyLoader for Es256Signer {
+    fn try_new(key: &[u8]) -> Result<Self> {
+        let key = p256::ecdsa::SigningKey::from_pkcs8_pem(&pem(key)?).map_err(|e| {
+            err!(
+                InvalidKey,
+                source = e,
+                "ES256 keys must be P-256 PKCS#8 PEM"
+            )
+        })?;
+        Ok(Es256Signer(key))
+    }
+}
+
+// also accepts private keys, their public half is used
+impl KeyLoader for Es256Verifier {
+    fn try_new(key: &[u8]) -> Result<Self> {
+        let pem = pem(key)?;
+        if let Ok(key) = p256::ecdsa::VerifyingKey::from_public_key_pem(&pem) {
+            return Ok(Es256Verifier(key));
+        }
+        let key = p256::ecdsa::SigningKey::from_pkcs8_pem(&pem).map_err(|e| {
+            err!(
+                InvalidKey,
+                source = e,
+                "ES256 keys must be P-256 SPKI or PKCS#8 PEM"
+            )
+        })?;
+        Ok(Es256Verifier(*key.verifying_key()))
+    }
+}
+
+fn pem(key: &[u8]) -> Result<Zeroizing<String>> {
+    let pem = std::str::from_utf8(key)
+        .map_err(|e| err!(InvalidKey, source = e, "ES256 keys must be PEM"))?;
+    Ok(Zeroizing::new(pem.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    const NOW: u64 = 1_714_000_000;
+
+    fn expected(alg: JwtAlgorithm) -> Expected<'static> {
+        Expected {
+            alg,
+            iss: Some("rchat_server"),
+            aud: Some("rchat_web"),
+            leeway: Duration::from_secs(60),
+        }
+    }
+
+    fn claims(exp: u64) -> Value {
+        json!({"sub": "1", "iss": "rchat_server", "aud": "rchat_web", "iat": NOW, "exp": exp})
+    }
+
+    fn key_pairs() -> Result<Vec<(TokenSigner, TokenVerifier)>> {
+        let es256 = p256::ecdsa::SigningKey::random(&mut OsRng);
+        Ok(vec![
+            (
+                TokenSigner::EdDsa(Ed25519Singer::load("fixtures/ed25519.sk")?),
+                TokenVerifier::EdDsa(Ed25519Verifier::load("fixtures/ed25519.pk")?),
+            ),
+            (
+                TokenSigner::Hs256(HmacKey::load("fixtures/blake3.txt")?),
+                TokenVerifier::Hs256(HmacKey::load("fixtures/blake3.txt")?),
+            ),
+            (
+                TokenSigner::Es256(Es256Signer(es256.clone())),
+                TokenVerifier::Es256(Es256Verifier(*es256.verifying_key())),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_sign_verify_round_trip() -> Result<()> {
+        for (signer, verifier) in key_pairs()? {
+            let token = signer.sign(&claims(NOW + 3600))?;
+            let decoded = verify(&token, &verifier, &expected(signer.alg()), NOW)?;
+            assert_eq!(decoded.claims["sub"], "1");
+            assert_eq!(decoded.header["alg"], signer.alg().to_string());
+
+            let mut tampered = token.clone();
+            tampered.insert(tampered.find('.').unwrap() + 2, 'x');
+            assert!(verify(&tampered, &verifier, &expected(signer.alg()), NOW).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_failures_are_listed() -> Result<()> {
+        let (signer, verifier) = key_pairs()?.remove(0);
+        let token =
+            signer.sign(&json!({"iss": "someone", "aud": ["a", "b"], "exp": NOW - 7200}))?;
+        let err = verify(&token, &verifier, &expected(JwtAlgorithm::EdDsa), NOW).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "exp: expired 2h ago\n\
+             iss: is \"someone\", expected \"rchat_server\"\n\
+             aud: is [\"a\",\"b\"], expected \"rchat_web\""
+        );
+
+        // within the leeway is fine
+        let token = signer.sign(&claims(NOW - 30))?;
+        assert!(verify(&token, &verifier, &expected(JwtAlgorithm::EdDsa), NOW).is_ok());
+
+        // the token can't choose its algorithm
+        let err = verify(&token, &verifier, &expected(JwtAlgorithm::Hs256), NOW).unwrap_err();
+        assert!(err.to_string().starts_with("alg:"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_huge_claims_dont_overflow() -> Result<()> {
+        let (signer, verifier) = key_pairs()?.remove(0);
+        let token = signer.sign(&claims(u64::MAX))?;
+        assert!(verify(&token, &verifier, &expected(JwtAlgorithm::EdDsa), NOW).is_ok());
+
+        let token =
+            signer.sign(&json!({"iss": "rchat_server", "aud": "rchat_web", "nbf": u64::MAX}))?;
+        let err = verify(&token, &verifier, &expected(JwtAlgorithm::EdDsa), NOW).unwrap_err();
+        assert!(err.to_string().starts_with("nbf: not valid for another"));
+
+        // the leeway can't wrap an expired token back into validity
+        let mut lenient = expected(JwtAlgorithm::EdDsa);
+        lenient.leeway = Duration::from_secs(u64::MAX);
+        let token = signer.sign(&claims(NOW - 1))?;
+        assert!(verify(&token, &verifier, &lenient, NOW).is_ok());
+        let token = signer.sign(&claims(1))?;
+        assert!(verify(&token, &verifier, &expected(JwtAlgorithm::EdDsa), u64::MAX).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_and_human() -> Result<()> {
+        let (signer, _) = key_pairs()?.remove(1);
+        let token = signer.sign(&claims(NOW + 14 * 86400))?;
+        let decoded = process_jwt_decode(&token)?;
+        assert_eq!(decoded.header, json!({"alg": "HS256", "typ": "JWT"}));
+        assert_eq!(
+            decoded.times(NOW + 3600),
+            ["issued 1h ago", "expires in 13d 23h"]
+        );
+        assert_eq!(human(0), "0s");
+        assert_eq!(human(86400 + 5), "1d");
+        assert!(process_jwt_decode("not a token").is_err());
+        Ok(())
+    }
+}
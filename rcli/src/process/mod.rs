@@ -1,9 +1,43 @@
 mod base64;
+mod codec;
+mod config;
+mod convert;
 mod csv_convert;
+mod csv_query;
+mod diff;
+mod encrypt;
+mod expr;
 mod gen_pass;
+mod hash;
+mod http_serve;
+mod id;
+mod jwt;
+mod key;
+mod query;
+mod schema;
+mod seal;
 mod text;
+mod time;
 
 pub use base64::{process_decode, process_encode};
+pub use codec::{process_codec_decode, process_codec_encode};
+pub use config::{Config, Setting, process_config_show};
+pub use convert::process_convert;
 pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
-pub use text::{process_text_generate, process_text_sign, process_text_verify};
+pub use csv_query::process_csv_query;
+pub use diff::{Change, PathSegment, process_diff, process_patch};
+pub use encrypt::{Secret, process_text_decrypt, process_text_encrypt};
+pub use gen_pass::{GeneratedPassword, process_genpass, process_genpass_opts};
+pub use hash::{hash_reader, process_hash, process_hash_check};
+pub use http_serve::process_http_serve;
+pub use id::{process_id_gen, process_id_inspect};
+pub use jwt::{DecodedToken, process_jwt_decode, process_jwt_sign, process_jwt_verify};
+pub use key::{Fingerprints, process_key_convert, process_key_fingerprint};
+pub use query::{input_format, process_query, process_set, read_value};
+pub use schema::{Violation, process_schema_infer, process_schema_validate};
+pub use seal::{Identity, Recipient, process_text_open, process_text_seal};
+pub use text::{
+    SignatureEnvelope, SignedData, default_keyring, process_text_generate, process_text_sign,
+    process_text_verify,
+};
+pub use time::{format_time, parse_time, process_time};
@@ -0,0 +1,591 @@
+// a small subset of jq for `rcli query` and `rcli set`:
+//
+//   .users[].email
+//   .items[?price > 10 && tags != null] | length
+//   .servers | map(select(port >= 8000) | .name)
+//
+// paths:   .name ."quoted name" [0] [-1] ["name"] [] (every item) [?predicate]
+// stages:  paths, select(predicate), map(stages), length and keys, joined by |
+//
+// predicates are `rcli csv query` expressions over the fields of the current
+// value, nested ones written as `address.city`
+use super::convert::{Document, read};
+use super::csv_query::split_top_level;
+use super::expr::{Expr, identifiers, parse};
+use crate::cli::DataFormat;
+use crate::error::{Result, bail, err};
+use serde_json::{Map, Value};
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Stage {
+    Path(Vec<Segment>),
+    Select(Predicate),
+    Map(Vec<Stage>),
+    Length,
+    Keys,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment {
+    Field(String),
+    Index(i64),
+    Iterate,
+    Filter(Predicate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Predicate {
+    fields: Vec<String>,
+    expr: Expr,
+}
+
+pub fn process_query(filter: &str, reader: impl Read, format: DataFormat) -> Result<Vec<Value>> {
+    let stages = parse_stages(filter)?;
+    let inputs = match format {
+        // a stream of records, each one goes through the filter
+        DataFormat::Csv | DataFormat::Ndjson => match read(reader, format)? {
+            Document::One(value) => vec![value],
+            Document::Many(records) => records,
+        },
+        format => vec![read_value(reader, format)?],
+    };
+    let mut results = Vec::new();
+    for value in inputs {
+        results.extend(run(&stages, value)?);
+    }
+    Ok(results)
+}
+
+// sets `path` and writes the document out again in its own format. Comments
+// and formatting of YAML and TOML are not kept
+pub fn process_set(
+    path: &str,
+    value: Value,
+    reader: impl Read,
+    mut writer: impl Write,
+    format: DataFormat,
+) -> Result<()> {
+    let segments = match parse_stages(path)?.as_slice() {
+        [Stage::Path(segments)] => segments.clone(),
+        _ => bail!(
+            InvalidQuery,
+            "rcli set takes a path like .a.b[0], not {}",
+            path
+        ),
+    };
+    if !matches!(
+        format,
+        DataFormat::Json | DataFormat::Yaml | DataFormat::Toml
+    ) {
+        bail!(
+            InvalidArgument,
+            "rcli set edits JSON, YAML and TOML, not {}",
+            format
+        );
+    }
+    let mut document = read_value(reader, format)?;
+    if assign(&mut document, &segments, &value)? == 0 {
+        bail!(InvalidQuery, "Nothing matches {}", path);
+    }
+    writer.write_all(write_value(&document, format)?.as_bytes())?;
+    Ok(())
+}
+
+// stdin is JSON unless told otherwise
+pub fn input_format(input: &str, from: Option<DataFormat>) -> Result<DataFormat> {
+    match (from, input) {
+        (Some(format), _) => Ok(format),
+        (None, "-") => Ok(DataFormat::Json),
+        (None, input) => DataFormat::from_path(input).ok_or_else(|| {
+            err!(
+                InvalidArgument,
+                "Cannot tell the format of {}, use --from",
+                input
+            )
+        }),
+    }
+}
+
+// unlike `rcli convert`, a document is read as is, without turning toml
+// arrays of tables into records
+pub fn read_value(mut reader: impl Read, format: DataFormat) -> Result<Value> {
+    let value = match format {
+        DataFormat::Yaml => serde_yaml::from_reader(reader)?,
+        DataFormat::Toml => {
+            let mut content = String::new();
+            reader.read_to_string(&mut content)?;
+            toml::from_str(&content)?
+        }
+        _ => serde_json::from_reader(reader)?,
+    };
+    Ok(value)
+}
+
+pub(crate) fn write_value(value: &Value, format: DataFormat) -> Result<String> {
+    let content = match format {
+        DataFormat::Yaml => serde_yaml::to_string(value)?,
+        DataFormat::Toml => toml::to_string_pretty(value).map_err(|e| {
+            err!(
+                InvalidInput,
+                source = e,
+                "Cannot write TOML, it has no null"
+            )
+        })?,
+        _ => serde_json::to_string_pretty(value)? + "\n",
+    };
+    Ok(content)
+}
+
+pub(crate) fn parse_stages(src: &str) -> Result<Vec<Stage>> {
+    split_top_level(src, '|')
+        .ok_or_else(|| err!(InvalidQuery, "Unbalanced brackets or quotes in {}", src))?
+        .into_iter()
+        .map(|stage| {
+            let stage = stage.trim();
+            if let Some(inner) = call_args(stage, "select") {
+                Ok(Stage::Select(Predicate::parse(inner)?))
+            } else if let Some(inner) = call_args(stage, "map") {
+                Ok(Stage::Map(parse_stages(inner)?))
+            } else if stage == "length" {
+                Ok(Stage::Length)
+            } else if stage == "keys" {
+                Ok(Stage::Keys)
+            } else if stage.starts_with('.') || stage.starts_with('[') {
+                Ok(Stage::Path(parse_path(stage)?))
+            } else if stage.is_empty() {
+                bail!(InvalidQuery, "Empty stage in {}", src)
+            } else {
+                bail!(
+                    InvalidQuery,
+                    "Unknown filter {}, expected a path, select, map, length or keys",
+                    stage
+                )
+            }
+        })
+        .collect()
+}
+
+fn call_args<'a>(stage: &'a str, name: &str) -> Option<&'a str> {
+    stage
+        .strip_prefix(name)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+fn parse_path(src: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '.' => {
+                rest = &rest[1..];
+                if rest.starts_with('"') {
+                    let (name, tail) = quoted(rest)?;
+                    segments.push(Segment::Field(name));
+                    rest = tail;
+                } else {
+                    let end = rest
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                        .unwrap_or(rest.len());
+                    if end > 0 {
+                        segments.push(Segment::Field(rest[..end].to_string()));
+                    } else if !(rest.is_empty() || rest.starts_with('[')) {
+                        bail!(InvalidQuery, "Expected a name after . in {}", src);
+                    }
+                    rest = &rest[end..];
+                }
+            }
+            '[' => {
+                let end = closing(rest)?;
+                let inner = rest[1..end].trim();
+                segments.push(if inner.is_empty() {
+                    Segment::Iterate
+                } else if let Some(predicate) = inner.strip_prefix('?') {
+                    Segment::Filter(Predicate::parse(predicate)?)
+                } else if inner.starts_with('"') {
+                    match quoted(inner)? {
+                        (name, "") => Segment::Field(name),
+                        _ => bail!(InvalidQuery, "Unexpected text after [{}] in {}", inner, src),
+                    }
+                } else {
+                    Segment::Index(
+                        inner.parse().map_err(|_| {
+                            err!(InvalidQuery, "Invalid index [{}] in {}", inner, src)
+                        })?,
+                    )
+                });
+                rest = &rest[end + 1..];
+            }
+            _ => bail!(InvalidQuery, "Unexpected {:?} in path {}", c, src),
+        }
+    }
+    Ok(segments)
+}
+
+// a "double quoted" name at the start of `s`, and what follows it
+fn quoted(s: &str) -> Result<(String, &str)> {
+    let mut name = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c)) => name.push(c),
+                None => break,
+            },
+            '"' => return Ok((name, &s[idx + 1..])),
+            c => name.push(c),
+        }
+    }
+    bail!(InvalidQuery, "Unterminated string in {}", s)
+}
+
+// the index of the bracket closing the one `s` starts with
+fn closing(s: &str) -> Result<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (idx, c) in s.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                c if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' | '`' => quote = Some(c),
+            '[' | '(' => depth += 1,
+            ']' | ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!(InvalidQuery, "Unclosed {} in {}", &s[..1], s)
+}
+
+impl Predicate {
+    fn parse(src: &str) -> Result<Self> {
+        let fields = identifiers(src)?;
+        let expr = parse(src, &fields)?;
+        Ok(Predicate { fields, expr })
+    }
+
+    // fields missing from `value`, or of values that aren't objects, are null
+    fn matches(&self, value: &Value) -> Result<bool> {
+        let row = self
+            .fields
+            .iter()
+            .map(|field| {
+                field
+                    .split('.')
+                    .try_fold(value, |value, key| value.get(key))
+                    .cloned()
+                    .unwrap_or(Value::Null)
+            })
+            .collect::<Vec<_>>();
+        self.expr.matches(&row)
+    }
+}
+
+pub(crate) fn run(stages: &[Stage], input: Value) -> Result<Vec<Value>> {
+    let mut values = vec![input];
+    for stage in stages {
+        let mut next = Vec::new();
+        for value in values {
+            next.extend(apply(stage, value)?);
+        }
+        values = next;
+    }
+    Ok(values)
+}
+
+fn apply(stage: &Stage, value: Value) -> Result<Vec<Value>> {
+    let values = match stage {
+        Stage::Path(segments) => {
+            let mut values = vec![value];
+            for segment in segments {
+                let mut next = Vec::new();
+                for value in values {
+                    next.extend(step(segment, value)?);
+                }
+                values = next;
+            }
+            values
+        }
+        Stage::Select(predicate) => {
+            if predicate.matches(&value)? {
+                vec![value]
+            } else {
+                vec![]
+            }
+        }
+        Stage::Map(stages) => match value {
+            Value::Array(items) => {
+                let mut mapped = Vec::new();
+                for item in items {
+                    mapped.extend(run(stages, item)?);
+                }
+                vec![Value::Array(mapped)]
+            }
+            value => bail!(InvalidQuery, "Cannot map over {}", kind(&value)),
+        },
+        Stage::Length => vec![Value::from(match &value {
+            Value::Array(items) => items.len(),
+            Value::Object(map) => map.len(),
+            Value::String(s) => s.chars().count(),
+            Value::Null => 0,
+            value => bail!(InvalidQuery, "{} has no length", kind(value)),
+        })],
+        Stage::Keys => vec![match value {
+            Value::Object(map) => {
+                let mut keys = map.keys().cloned().collect::<Vec<_>>();
+                keys.sort();
+                Value::from(keys)
+            }
+            Value::Array(items) => Value::from((0..items.len()).collect::<Vec<_>>()),
+            value => bail!(InvalidQuery, "{} has no keys", kind(&value)),
+        }],
+    };
+    Ok(values)
+}
+
+// missing fields and indexes are null, like in jq
+fn step(segment: &Segment, value: Value) -> Result<Vec<Value>> {
+    let values = match (segment, value) {
+        (Segment::Field(_) | Segment::Index(_), Value::Null) => vec![Value::Null],
+        (Segment::Field(name), Value::Object(mut map)) => {
+            vec![map.remove(name).unwrap_or(Value::Null)]
+        }
+        (Segment::Index(idx), Value::Array(mut items)) => match position(*idx, items.len()) {
+            Some(idx) => vec![items.swap_remove(idx)],
+            None => vec![Value::Null],
+        },
+        (Segment::Iterate | Segment::Filter(_), Value::Null) => vec![],
+        (Segment::Iterate, Value::Array(items)) => items,
+        (Segment::Iterate, Value::Object(map)) => map.into_iter().map(|(_, v)| v).collect(),
+        (Segment::Filter(predicate), Value::Array(items)) => {
+            let mut matched = Vec::new();
+            for item in items {
+                if predicate.matches(&item)? {
+                    matched.push(item);
+                }
+            }
+            matched
+        }
+        (Segment::Field(name), value) => {
+            bail!(InvalidQuery, "Cannot get .{} of {}", name, kind(&value))
+        }
+        (Segment::Index(idx), value) => {
+            bail!(InvalidQuery, "Cannot get [{}] of {}", idx, kind(&value))
+        }
+        (_, value) => bail!(InvalidQuery, "Cannot iterate over {}", kind(&value)),
+    };
+    Ok(values)
+}
+
+// sets every place `segments` leads to and returns how many there were.
+// Missing fields are created, and so are objects in place of nulls
+fn assign(value: &mut Value, segments: &[Segment], new: &Value) -> Result<usize> {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = new.clone();
+        return Ok(1);
+    };
+    if value.is_null() && matches!(segment, Segment::Field(_)) {
+        *value = Value::Object(Map::new());
+    }
+    match (segment, value) {
+        (Segment::Field(name), Value::Object(map)) => {
+            assign(map.entry(name.as_str()).or_insert(Value::Null), rest, new)
+        }
+        (Segment::Index(idx), Value::Array(items)) => {
+            let len = items.len();
+            // one past the end appends
+            if *idx == len as i64 {
+                items.push(Value::Null);
+            }
+            let idx = position(*idx, items.len()).ok_or_else(|| {
+                err!(
+                    InvalidQuery,
+                    "Index {} is out of range for {} items",
+                    idx,
+                    len
+                )
+            })?;
+            assign(&mut items[idx], rest, new)
+        }
+        (Segment::Iterate, Value::Array(items)) => {
+            items.iter_mut().map(|item| assign(item, rest, new)).sum()
+        }
+        (Segment::Iterate, Value::Object(map)) => {
+            map.values_mut().map(|item| assign(item, rest, new)).sum()
+        }
+        (Segment::Filter(predicate), Value::Array(items)) => {
+            let mut count = 0;
+            for item in items {
+                if predicate.matches(item)? {
+                    count += assign(item, rest, new)?;
+                }
+            }
+            Ok(count)
+        }
+        (Segment::Field(name), value) => {
+            bail!(InvalidQuery, "Cannot set .{} of {}", name, kind(value))
+        }
+        (Segment::Index(idx), value) => {
+            bail!(InvalidQuery, "Cannot set [{}] of {}", idx, kind(value))
+        }
+        (_, value) => bail!(InvalidQuery, "Cannot iterate over {}", kind(value)),
+    }
+}
+
+// negative indexes count from the end
+fn position(idx: i64, len: usize) -> Option<usize> {
+    let idx = if idx < 0 { len as i64 + idx } else { idx };
+    (0..len as i64).contains(&idx).then_some(idx as usize)
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(filter: &str, value: Value) -> Result<Vec<Value>> {
+        run(&parse_stages(filter)?, value)
+    }
+
+    fn doc() -> Value {
+        json!({
+            "users": [
+                {"name": "Buffon", "email": "gigi@example.com", "age": 46, "address": {"city": "Carrara"}},
+                {"name": "Perin", "email": "mattia@example.com", "age": 31},
+                {"name": "Pinsoglio", "age": 34, "address": {"city": "Moncalieri"}}
+            ],
+            "club": {"name": "Juventus", "founded": 1897, "stadium name": "Allianz"}
+        })
+    }
+
+    #[test]
+    fn test_paths() -> Result<()> {
+        assert_eq!(
+            query(".users[].email", doc())?,
+            [
+                json!("gigi@example.com"),
+                json!("mattia@example.com"),
+                json!(null)
+            ]
+        );
+        assert_eq!(query(".users[-1].name", doc())?, [json!("Pinsoglio")]);
+        assert_eq!(query(".users[9].name", doc())?, [json!(null)]);
+        assert_eq!(query(".club.\"stadium name\"", doc())?, [json!("Allianz")]);
+        assert_eq!(query(".club[\"founded\"]", doc())?, [json!(1897)]);
+        assert_eq!(query(".", json!(1))?, [json!(1)]);
+        assert!(query(".club.name.first", doc()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filters_and_stages() -> Result<()> {
+        assert_eq!(
+            query(".users[?age > 32 && address.city != null].name", doc())?,
+            [json!("Buffon"), json!("Pinsoglio")]
+        );
+        assert_eq!(
+            query(
+                ".users[] | select(starts_with(name, 'P') || age > 40) | .age",
+                doc()
+            )?,
+            [json!(46), json!(31), json!(34)]
+        );
+        assert_eq!(
+            query(".users | map(select(age < 40) | .name)", doc())?,
+            [json!(["Perin", "Pinsoglio"])]
+        );
+        assert_eq!(
+            query(".users | map(select(age > 32)) | length", doc())?,
+            [json!(2)]
+        );
+        assert_eq!(query(".users | length", doc())?, [json!(3)]);
+        assert_eq!(
+            query(".club | keys", doc())?,
+            [json!(["founded", "name", "stadium name"])]
+        );
+        assert!(parse_stages(".users | sort").is_err());
+        assert!(parse_stages(".users[?age >]").is_err());
+        assert!(parse_stages(".users[").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_assign() -> Result<()> {
+        let mut value = doc();
+        let set = |value: &mut Value, path: &str, new: Value| -> Result<usize> {
+            match parse_stages(path)?.as_slice() {
+                [Stage::Path(segments)] => assign(value, segments, &new),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(set(&mut value, ".club.coach.name", json!("Motta"))?, 1);
+        assert_eq!(value["club"]["coach"], json!({"name": "Motta"}));
+        assert_eq!(
+            set(&mut value, ".users[?age > 32].retired", json!(true))?,
+            2
+        );
+        assert_eq!(value["users"][1].get("retired"), None);
+        assert_eq!(
+            set(&mut value, ".users[3]", json!({"name": "Di Gregorio"}))?,
+            1
+        );
+        assert_eq!(value["users"].as_array().map(Vec::len), Some(4));
+        assert!(set(&mut value, ".users[9]", json!(1)).is_err());
+        assert!(set(&mut value, ".club.founded.year", json!(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_keeps_the_format() -> Result<()> {
+        let input = "[server]\nhost = \"localhost\"\nport = 8080\n";
+        let mut output = Vec::new();
+        process_set(
+            ".server.port",
+            json!(9090),
+            input.as_bytes(),
+            &mut output,
+            DataFormat::Toml,
+        )?;
+        let content = String::from_utf8_lossy(&output);
+        assert!(content.contains("port = 9090"));
+        assert!(content.contains("host = \"localhost\""));
+        assert_eq!(
+            process_query(".server.port", &output[..], DataFormat::Toml)?,
+            [json!(9090)]
+        );
+        let set_null = process_set(
+            ".server.port",
+            json!(null),
+            input.as_bytes(),
+            Vec::new(),
+            DataFormat::Toml,
+        );
+        assert!(set_null.is_err());
+        Ok(())
+    }
+}
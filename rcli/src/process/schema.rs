@@ -0,0 +1,243 @@
+use super::convert::{Document, read};
+use super::csv_convert::is_date;
+use crate::cli::DataFormat;
+use crate::error::{Result, err};
+use serde_json::{Map, Value, json};
+use std::fmt;
+use std::io::Read;
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub record: usize,
+    // a JSON pointer into the record
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pointer.as_str() {
+            "" => write!(f, "record {}: {}", self.record, self.message),
+            pointer => write!(f, "record {} at {}: {}", self.record, pointer, self.message),
+        }
+    }
+}
+
+// a schema for the records of the input, or for the whole document when it
+// isn't a list of records
+pub fn process_schema_infer(reader: impl Read, format: DataFormat) -> Result<Value> {
+    let mut shape = Shape::default();
+    for record in read_records(reader, format)? {
+        shape.add(&record);
+    }
+    let mut schema = Map::from_iter([("$schema".to_string(), Value::from(DRAFT))]);
+    if let Value::Object(rest) = shape.schema() {
+        schema.extend(rest);
+    }
+    Ok(Value::Object(schema))
+}
+
+// the number of records checked and every violation found
+pub fn process_schema_validate(
+    schema: &Value,
+    reader: impl Read,
+    format: DataFormat,
+) -> Result<(usize, Vec<Violation>)> {
+    let validator = jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+        .map_err(|e| err!(InvalidInput, source = e, "Invalid schema"))?;
+    let records = read_records(reader, format)?;
+    let violations = records
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, record)| {
+            validator.iter_errors(record).map(move |e| Violation {
+                record: idx,
+                pointer: e.instance_path().to_string(),
+                message: e.to_string(),
+            })
+        })
+        .collect();
+    Ok((records.len(), violations))
+}
+
+fn read_records(reader: impl Read, format: DataFormat) -> Result<Vec<Value>> {
+    let records = match read(reader, format)? {
+        Document::One(value) => vec![value],
+        Document::Many(records) => records,
+    };
+    Ok(records)
+}
+
+// what the values seen so far have in common
+#[derive(Debug, Default)]
+struct Shape {
+    null: bool,
+    boolean: bool,
+    integer: bool,
+    number: bool,
+    // whether every string is a date
+    strings: Option<bool>,
+    items: Option<Box<Shape>>,
+    // how many objects there were, and in how many each property was
+    objects: usize,
+    properties: Vec<(String, usize, Shape)>,
+}
+
+impl Shape {
+    fn add(&mut self, value: &Value) {
+        match value {
+            Value::Null => self.null = true,
+            Value::Bool(_) => self.boolean = true,
+            Value::Number(n) if n.is_f64() => self.number = true,
+            Value::Number(_) => self.integer = true,
+            Value::String(s) => self.strings = Some(self.strings.unwrap_or(true) && is_date(s)),
+            Value::Array(values) => {
+                let items = self.items.get_or_insert_default();
+                for value in values {
+                    items.add(value);
+                }
+            }
+            Value::Object(map) => {
+                self.objects += 1;
+                for (name, value) in map {
+                    let idx = match self.properties.iter().position(|(n, ..)| n == name) {
+                        Some(idx) => idx,
+                        None => {
+                            self.properties.push((name.clone(), 0, Shape::default()));
+                            self.properties.len() - 1
+                        }
+                    };
+                    let (_, count, shape) = &mut self.properties[idx];
+                    *count += 1;
+                    shape.add(value);
+                }
+            }
+        }
+    }
+
+    fn schema(&self) -> Value {
+        let mut types = Vec::new();
+        let mut schema = Map::new();
+        if self.objects > 0 {
+            types.push("object");
+            let properties = self
+                .properties
+                .iter()
+                .map(|(name, _, shape)| (name.clone(), shape.schema()));
+            schema.insert("properties".into(), Value::Object(properties.collect()));
+            // properties every object has
+            let required = self
+                .properties
+                .iter()
+                .filter(|(_, count, _)| *count == self.objects)
+                .map(|(name, ..)| name.as_str());
+            schema.insert("required".into(), json!(required.collect::<Vec<_>>()));
+        }
+        if let Some(items) = &self.items {
+            types.push("array");
+            if !items.is_empty() {
+                schema.insert("items".into(), items.schema());
+            }
+        }
+        if let Some(dates) = self.strings {
+            types.push("string");
+            if dates {
+                schema.insert("format".into(), "date".into());
+            }
+        }
+        match (self.integer, self.number) {
+            (true, false) => types.push("integer"),
+            (_, true) => types.push("number"),
+            _ => {}
+        }
+        if self.boolean {
+            types.push("boolean");
+        }
+        if self.null {
+            types.push("null");
+        }
+        let mut typed = Map::new();
+        match types.as_slice() {
+            [] => {}
+            [t] => {
+                typed.insert("type".into(), (*t).into());
+            }
+            types => {
+                typed.insert("type".into(), json!(types));
+            }
+        }
+        typed.extend(schema);
+        Value::Object(typed)
+    }
+
+    fn is_empty(&self) -> bool {
+        !(self.null || self.boolean || self.integer || self.number)
+            && self.strings.is_none()
+            && self.items.is_none()
+            && self.objects == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(records: &[Value]) -> Value {
+        let mut shape = Shape::default();
+        for record in records {
+            shape.add(record);
+        }
+        shape.schema()
+    }
+
+    #[test]
+    fn test_infer_merges_records() {
+        let schema = infer(&[
+            json!({"id": 1, "born": "1978-01-28", "score": 9, "tags": ["a"]}),
+            json!({"id": 2, "born": null, "score": 8.5, "tags": [], "team": "Juve"}),
+        ]);
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "integer"},
+                    "born": {"type": ["string", "null"], "format": "date"},
+                    "score": {"type": "number"},
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "team": {"type": "string"}
+                },
+                "required": ["id", "born", "score", "tags"]
+            })
+        );
+        assert_eq!(infer(&[json!([])]), json!({"type": "array"}));
+    }
+
+    #[test]
+    fn test_inferred_schema_validates_its_input() -> Result<()> {
+        let data = "{\"id\": 1, \"born\": \"1978-01-28\", \"address\": {\"zip\": 10100}}\n\
+                    {\"id\": 2, \"born\": \"1992-11-10\", \"address\": {\"zip\": 10121}}\n";
+        let schema = process_schema_infer(data.as_bytes(), DataFormat::Ndjson)?;
+        assert_eq!(
+            process_schema_validate(&schema, data.as_bytes(), DataFormat::Ndjson)?,
+            (2, vec![])
+        );
+
+        let bad = "{\"id\": 1, \"born\": \"1978-01-28\", \"address\": {\"zip\": 10100}}\n\
+                   {\"id\": \"2\", \"born\": \"1992-13-10\", \"address\": {}}\n";
+        let (count, violations) =
+            process_schema_validate(&schema, bad.as_bytes(), DataFormat::Ndjson)?;
+        assert_eq!(count, 2);
+        let mut found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.record, v.pointer.as_str()))
+            .collect();
+        found.sort();
+        assert_eq!(found, [(1, "/address"), (1, "/born"), (1, "/id")]);
+        Ok(())
+    }
+}
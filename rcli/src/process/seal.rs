@@ -0,0 +1,299 @@
+// public key encryption to one or more X25519 recipients, in the spirit of
+// age (https://age-encryption.org/v1). Version 1 of the format is
+//
+//   magic "RCLISEAL" | version | recipient count (u16 le)
+//   for every recipient: ephemeral public key (32) | wrapped file key (32 + 16 tag)
+//   nonce prefix (7) | chunk size (u32 le)
+//   chunk 0 | chunk 1 | ... | last chunk
+//
+// a random file key encrypts the payload with the same ChaCha20-Poly1305
+// STREAM chunks as `rcli text encrypt`, the header is their associated data.
+// For every recipient a fresh ephemeral key does ECDH with the recipient's
+// public key, and HKDF-SHA256 over the shared secret (salted with both public
+// keys) gives the key that wraps the file key. Stanzas don't name their
+// recipient, `open` tries each identity against each stanza.
+use super::encrypt::{
+    CHUNK_SIZE, KEY_SIZE, NONCE_PREFIX_SIZE, TAG_SIZE, armored, decrypt_chunks, encrypt_chunks,
+    parse_key, unarmored,
+};
+use crate::error::{RcliError, Result, bail, err};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"RCLISEAL";
+const VERSION: u8 = 1;
+const WRAP_INFO: &[u8] = b"rcli-seal-v1 wrap";
+const WRAPPED_SIZE: usize = KEY_SIZE + TAG_SIZE;
+const STANZA_SIZE: usize = KEY_SIZE + WRAPPED_SIZE;
+
+pub struct Recipient(PublicKey);
+
+pub struct Identity(StaticSecret);
+
+impl Recipient {
+    // a public key file, or the key itself in base64
+    pub fn load(spec: &str) -> Result<Self> {
+        let key = load_key(spec)
+            .map_err(|e| err!(InvalidKey, source = e, "Invalid recipient {}", spec))?;
+        Ok(Recipient(PublicKey::from(*key)))
+    }
+}
+
+impl Identity {
+    pub fn load(spec: &str) -> Result<Self> {
+        let key = load_key(spec)
+            .map_err(|e| err!(InvalidKey, source = e, "Invalid identity {}", spec))?;
+        Ok(Identity(StaticSecret::from(*key)))
+    }
+
+    pub fn generate() -> Self {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    pub fn to_bytes(&self) -> [u8; KEY_SIZE] {
+        self.0.to_bytes()
+    }
+
+    pub fn public_key(&self) -> [u8; KEY_SIZE] {
+        PublicKey::from(&self.0).to_bytes()
+    }
+}
+
+fn load_key(spec: &str) -> Result<Zeroizing<[u8; KEY_SIZE]>> {
+    let content = if Path::new(spec).is_file() {
+        Zeroizing::new(fs::read(spec)?)
+    } else {
+        Zeroizing::new(spec.as_bytes().to_vec())
+    };
+    parse_key(&content).ok_or_else(|| err!(InvalidKey, "expected 32 bytes, raw or base64"))
+}
+
+pub fn process_text_seal(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    recipients: &[Recipient],
+    base64: bool,
+) -> Result<()> {
+    armored(writer, base64, |writer| seal(reader, writer, recipients))
+}
+
+pub fn process_text_open(
+    reader: impl Read,
+    writer: &mut dyn Write,
+    identities: &[Identity],
+) -> Result<()> {
+    open(reader, writer, identities)
+}
+
+fn seal(reader: impl Read, mut writer: impl Write, recipients: &[Recipient]) -> Result<()> {
+    if recipients.is_empty() {
+        bail!(InvalidArgument, "At least one recipient is required");
+    }
+    let count = u16::try_from(recipients.len())
+        .map_err(|_| err!(InvalidArgument, "Too many recipients"))?;
+    let mut file_key = Zeroizing::new([0u8; KEY_SIZE]);
+    OsRng.fill_bytes(file_key.as_mut_slice());
+    let mut nonce = [0u8; NONCE_PREFIX_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = Vec::with_capacity(16 + recipients.len() * STANZA_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.extend_from_slice(&count.to_le_bytes());
+    for Recipient(public) in recipients {
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(public);
+        if !shared.was_contributory() {
+            bail!(InvalidKey, "Recipient key is not a valid X25519 public key");
+        }
+        let wrap_key = wrap_key(shared.as_bytes(), &ephemeral_public, public);
+        let wrapped = wrap_cipher(&wrap_key)
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(|e| err!(InvalidInput, source = e, "Encryption failed"))?;
+        header.extend_from_slice(ephemeral_public.as_bytes());
+        header.extend_from_slice(&wrapped);
+    }
+    header.extend_from_slice(&nonce);
+    header.extend_from_slice(&CHUNK_SIZE.to_le_bytes());
+    writer.write_all(&header)?;
+
+    encrypt_chunks(reader, writer, &file_key, &nonce, &header)
+}
+
+fn open(reader: impl Read, writer: impl Write, identities: &[Identity]) -> Result<()> {
+    let mut reader = unarmored(reader, MAGIC)?;
+    let mut header = Vec::new();
+    let mut take = |n: usize| -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; n];
+        reader.read_exact(&mut buf).map_err(|_| not_sealed())?;
+        header.extend_from_slice(&buf);
+        Ok(buf)
+    };
+
+    if take(MAGIC.len())? != MAGIC {
+        return Err(not_sealed());
+    }
+    let version = take(1)?[0];
+    if version != VERSION {
+        bail!(InvalidInput, "Unsupported sealed file version {}", version);
+    }
+    let count = u16::from_le_bytes(take(2)?.try_into().expect("2 bytes"));
+    let mut file_key = None;
+    for _ in 0..count {
+        let stanza = take(STANZA_SIZE)?;
+        if file_key.is_none() {
+            file_key = unwrap_file_key(&stanza, identities);
+        }
+    }
+    let nonce: [u8; NONCE_PREFIX_SIZE] = take(NONCE_PREFIX_SIZE)?.try_into().expect("nonce size");
+    let chunk_size = u32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+    if chunk_size == 0 || chunk_size > 16 * 1024 * 1024 {
+        bail!(InvalidInput, "Invalid chunk size {}", chunk_size);
+    }
+    let file_key = file_key.ok_or_else(|| {
+        err!(
+            DecryptionFailed,
+            "None of the identities matches the {} recipient(s)",
+            count
+        )
+    })?;
+
+    decrypt_chunks(
+        reader, writer, &file_key, &nonce, chunk_size, &header, tampered,
+    )
+}
+
+fn unwrap_file_key(stanza: &[u8], identities: &[Identity]) -> Option<Zeroizing<[u8; KEY_SIZE]>> {
+    let (ephemeral_public, wrapped) = stanza.split_at(KEY_SIZE);
+    let ephemeral_public = PublicKey::from(<[u8; KEY_SIZE]>::try_from(ephemeral_public).ok()?);
+    identities.iter().find_map(|Identity(secret)| {
+        let shared = secret.diffie_hellman(&ephemeral_public);
+        if !shared.was_contributory() {
+            return None;
+        }
+        let wrap_key = wrap_key(
+            shared.as_bytes(),
+            &ephemeral_public,
+            &PublicKey::from(secret),
+        );
+        let file_key = Zeroizing::new(
+            wrap_cipher(&wrap_key)
+                .decrypt(&Nonce::default(), wrapped)
+                .ok()?,
+        );
+        Some(Zeroizing::new(file_key.as_slice().try_into().ok()?))
+    })
+}
+
+fn wrap_key(
+    shared: &[u8; KEY_SIZE],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Zeroizing<[u8; KEY_SIZE]> {
+    let mut salt = [0u8; 2 * KEY_SIZE];
+    salt[..KEY_SIZE].copy_from_slice(ephemeral.as_bytes());
+    salt[KEY_SIZE..].copy_from_slice(recipient.as_bytes());
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, key.as_mut_slice())
+        .expect("32 bytes is a valid hkdf output length");
+    key
+}
+
+// every wrap key is used exactly once, so the all-zero nonce is fine
+fn wrap_cipher(key: &[u8; KEY_SIZE]) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+fn not_sealed() -> RcliError {
+    err!(InvalidInput, "Input is not a file sealed by rcli")
+}
+
+fn tampered() -> RcliError {
+    err!(DecryptionFailed, "Decryption failed: the data was modified")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient(identity: &Identity) -> Recipient {
+        Recipient(PublicKey::from(identity.public_key()))
+    }
+
+    fn sealed(data: &[u8], recipients: &[Recipient]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        seal(data, &mut out, recipients)?;
+        Ok(out)
+    }
+
+    fn opened(data: &[u8], identity: Identity) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        open(data, &mut out, &[identity])?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_each_recipient_opens_independently() -> Result<()> {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+        let data = (0..CHUNK_SIZE as usize * 2 + 3)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let sealed = sealed(&data, &[recipient(&alice), recipient(&bob)])?;
+
+        assert_eq!(opened(&sealed, alice)?, data);
+        assert_eq!(opened(&sealed, bob)?, data);
+        let err = opened(&sealed, eve).unwrap_err();
+        assert!(
+            err.to_string().contains("None of the identities"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampering_fails() -> Result<()> {
+        let alice = Identity::generate();
+        let sealed = sealed(b"hello world!", &[recipient(&alice)])?;
+        let header_len = MAGIC.len() + 3 + STANZA_SIZE + NONCE_PREFIX_SIZE + 4;
+
+        for idx in [header_len + 2, header_len - 1, MAGIC.len() + 3 + 40] {
+            let mut bad = sealed.clone();
+            bad[idx] ^= 1;
+            let identity = Identity(StaticSecret::from(alice.to_bytes()));
+            assert!(opened(&bad, identity).is_err(), "byte {}", idx);
+        }
+        assert!(opened(&sealed[..sealed.len() - 1], alice).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_and_key_loading() -> Result<()> {
+        let alice = Identity::generate();
+        let encoded = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            alice.public_key(),
+        );
+        let mut out = Vec::new();
+        armored(&mut out, true, |w| {
+            seal(&b"hi"[..], w, &[Recipient::load(&encoded)?])
+        })?;
+        assert!(out.starts_with(b"UkNMSVNFQUwB"));
+        assert_eq!(opened(&out, alice)?, b"hi");
+        assert!(Recipient::load("not a key").is_err());
+        Ok(())
+    }
+}
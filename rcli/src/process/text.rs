@@ -1,34 +1,52 @@
-use crate::cli::TextSignFormat;
+use crate::cli::{KeyGenFormat, TextSignFormat};
+use crate::error::{Result, bail, err};
+use crate::process::key::{Ed25519Key, invalid_key};
 use crate::process::process_genpass;
-use crate::utils::input_reader;
+use crate::process::seal::Identity;
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use base64::prelude::BASE64_URL_SAFE_NO_PAD;
-use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use ed25519_dalek::{Signature, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
-use std::fs;
-use std::io::Read;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 trait TextSign {
     // Sign the data from the reader and return the signature
     // &[u8] implements Read, so we can test with &[u8] instead of File
-    fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>>;
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>>;
+
+    // Sign `prefix` followed by the file's content, implementations may
+    // read the file faster than through a reader
+    fn sign_file(&self, prefix: &[u8], path: &Path) -> Result<Vec<u8>> {
+        let mut reader = Cursor::new(prefix).chain(File::open(path)?);
+        self.sign(&mut reader)
+    }
 }
 
 trait TextVerify {
     // Verify the data from the reader
-    fn verify(&self, reader: impl Read, sig: &[u8]) -> anyhow::Result<bool>;
+    fn verify(&self, reader: impl Read, sig: &[u8]) -> Result<bool>;
+
+    fn verify_file(&self, prefix: &[u8], path: &Path, sig: &[u8]) -> Result<bool> {
+        self.verify(Cursor::new(prefix).chain(File::open(path)?), sig)
+    }
 }
 
 trait KeyGenerator {
-    fn generate() -> anyhow::Result<Vec<Vec<u8>>>;
+    fn generate() -> Result<Vec<Vec<u8>>>;
 }
 
-pub trait KeyLoader {
-    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self>
-    where
-        Self: Sized;
+pub trait KeyLoader: Sized {
+    fn try_new(key: &[u8]) -> Result<Self>;
+
+    fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::try_new(&fs::read(path)?)
+    }
 }
 
 struct Blake3 {
@@ -39,35 +57,38 @@ impl Blake3 {
     pub fn new(key: [u8; 32]) -> Self {
         Self { key }
     }
-
-    pub fn try_new(key: &[u8]) -> anyhow::Result<Self> {
-        let key = &key[..32];
-        let key = key.try_into()?;
-        let signer = Blake3::new(key);
-        Ok(signer)
-    }
 }
 
 impl KeyLoader for Blake3 {
-    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self>
-    where
-        Self: Sized,
-    {
-        let key = fs::read(path)?;
-        Self::try_new(&key)
+    fn try_new(key: &[u8]) -> Result<Self> {
+        let key = key
+            .get(..32)
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| err!(InvalidKey, "Blake3 key must be at least 32 bytes"))?;
+        Ok(Blake3::new(key))
     }
 }
 
 impl KeyGenerator for Blake3 {
-    fn generate() -> anyhow::Result<Vec<Vec<u8>>> {
+    fn generate() -> Result<Vec<Vec<u8>>> {
         let key = process_genpass(32, true, true, true, true)?;
         let key = key.as_bytes().to_vec();
         Ok(vec![key])
     }
 }
 
+impl KeyGenerator for Identity {
+    fn generate() -> Result<Vec<Vec<u8>>> {
+        let identity = Identity::generate();
+        Ok(vec![
+            identity.to_bytes().to_vec(),
+            identity.public_key().to_vec(),
+        ])
+    }
+}
+
 impl KeyGenerator for Ed25519Singer {
-    fn generate() -> anyhow::Result<Vec<Vec<u8>>> {
+    fn generate() -> Result<Vec<Vec<u8>>> {
         let mut csprng = OsRng;
         let sk = SigningKey::generate(&mut csprng);
         let pk = sk.verifying_key().to_bytes().to_vec();
@@ -85,20 +106,17 @@ impl Ed25519Singer {
         Self { key }
     }
 
-    pub fn try_new(key: &[u8]) -> anyhow::Result<Self> {
-        let key = SigningKey::from_bytes(key.try_into()?);
-        let signer = Ed25519Singer::new(key);
-        Ok(signer)
+    pub(crate) fn signing_key(&self) -> &SigningKey {
+        &self.key
     }
 }
 
 impl KeyLoader for Ed25519Singer {
-    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self>
-    where
-        Self: Sized,
-    {
-        let key = fs::read(path)?;
-        Self::try_new(&key)
+    fn try_new(key: &[u8]) -> Result<Self> {
+        match Ed25519Key::parse(key, false)? {
+            Ed25519Key::Private(key) => Ok(Ed25519Singer::new(key)),
+            Ed25519Key::Public(_) => bail!(InvalidKey, "Can't sign with a public key"),
+        }
     }
 }
 
@@ -111,114 +129,341 @@ impl Ed25519Verifier {
         Self { key }
     }
 
-    pub fn try_new(key: &[u8]) -> anyhow::Result<Self> {
-        let key = VerifyingKey::from_bytes(key.try_into()?)?;
-        let verifier = Ed25519Verifier::new(key);
-        Ok(verifier)
+    pub(crate) fn verifying_key(&self) -> &VerifyingKey {
+        &self.key
     }
 }
 
+// also accepts private keys, their public half is used
 impl KeyLoader for Ed25519Verifier {
-    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self>
-    where
-        Self: Sized,
-    {
-        let key = fs::read(path)?;
-        Self::try_new(&key)
+    fn try_new(key: &[u8]) -> Result<Self> {
+        let key = Ed25519Key::parse(key, true)?.verifying_key();
+        Ok(Ed25519Verifier::new(key))
+    }
+}
+
+impl Blake3 {
+    fn hash(&self, reader: impl Read) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update_reader(reader)?;
+        Ok(hasher.finalize())
+    }
+
+    // memory mapped and hashed on all cores
+    fn hash_file(&self, prefix: &[u8], path: &Path) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update(prefix);
+        hasher.update_mmap_rayon(path)?;
+        Ok(hasher.finalize())
     }
 }
 
 impl TextSign for Blake3 {
-    fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        Ok(blake3::keyed_hash(&self.key, &buf).as_bytes().to_vec())
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        Ok(self.hash(reader)?.as_bytes().to_vec())
+    }
+
+    fn sign_file(&self, prefix: &[u8], path: &Path) -> Result<Vec<u8>> {
+        Ok(self.hash_file(prefix, path)?.as_bytes().to_vec())
     }
 }
 
+// blake3::Hash compares in constant time
 impl TextVerify for Blake3 {
-    fn verify(&self, mut reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let hash = blake3::keyed_hash(&self.key, &buf);
-        let hash = hash.as_bytes();
-        Ok(hash == sig)
+    fn verify(&self, reader: impl Read, sig: &[u8]) -> Result<bool> {
+        let Ok(sig) = sig.try_into() else {
+            return Ok(false);
+        };
+        Ok(self.hash(reader)? == blake3::Hash::from_bytes(sig))
+    }
+
+    fn verify_file(&self, prefix: &[u8], path: &Path, sig: &[u8]) -> Result<bool> {
+        let Ok(sig) = sig.try_into() else {
+            return Ok(false);
+        };
+        Ok(self.hash_file(prefix, path)? == blake3::Hash::from_bytes(sig))
     }
 }
 
-pub fn process_text_sign(
-    input: String,
-    key: String,
-    format: TextSignFormat,
-) -> anyhow::Result<String> {
-    let mut reader = input_reader(input)?;
-    let signed = match format {
-        TextSignFormat::Blake3 => {
-            let signer = Blake3::load(key)?;
-            signer.sign(&mut reader)
-        }
-        TextSignFormat::Ed25519 => {
-            let signer = Ed25519Singer::load(key)?;
-            signer.sign(&mut reader)
-        }
-    };
-    let signed = URL_SAFE_NO_PAD.encode(signed?);
-    Ok(signed)
+// Ed25519ph: sign the SHA-512 of the data, so it never has to be in memory
+fn prehash(mut reader: impl Read) -> Result<Sha512> {
+    let mut hasher = Sha512::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher)
 }
 
 impl TextSign for Ed25519Singer {
-    fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let sig = self.key.sign(&buf);
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let sig = self
+            .key
+            .sign_prehashed(prehash(reader)?, None)
+            .map_err(invalid_key)?;
         Ok(sig.to_bytes().to_vec())
     }
 }
 
 impl TextVerify for Ed25519Verifier {
-    fn verify(&self, mut reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
+    fn verify(&self, reader: impl Read, sig: &[u8]) -> Result<bool> {
+        let Ok(sig) = sig.try_into() else {
+            return Ok(false);
+        };
+        let sig = Signature::from_bytes(sig);
+        let ret = self
+            .key
+            .verify_prehashed(prehash(reader)?, None, &sig)
+            .is_ok();
+        Ok(ret)
+    }
+}
+
+// version 1 envelopes were signed with pure Ed25519, which needs the whole
+// message in memory. Only verifying them is supported
+struct PureEd25519Verifier<'a>(&'a Ed25519Verifier);
+
+impl TextVerify for PureEd25519Verifier<'_> {
+    fn verify(&self, mut reader: impl Read, sig: &[u8]) -> Result<bool> {
+        let Ok(sig) = sig.try_into() else {
+            return Ok(false);
+        };
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
-        let sig = Signature::from_bytes(sig.try_into()?);
-        let ret = self.key.verify(&buf, &sig).is_ok();
-        Ok(ret)
+        Ok(self.0.key.verify(&buf, &Signature::from_bytes(sig)).is_ok())
     }
 }
 
-pub fn process_text_verify(
-    input: String,
-    key: String,
+// what a signature covers. Files are read faster, blake3 memory maps them
+// and hashes on all cores
+pub enum SignedData<'a> {
+    Reader(Box<dyn Read + 'a>),
+    File(&'a Path),
+}
+
+// version 2 signs Ed25519 envelopes with Ed25519ph, blake3 is unchanged
+const ENVELOPE_VERSION: u8 = 2;
+
+// `rcli text sign` writes this as json next to the signed file. The
+// signature covers the metadata (see `signed_prefix`) followed by the data,
+// so neither the timestamp nor the trusted comment can be swapped out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignatureEnvelope {
+    pub version: u8,
+    pub algorithm: String,
+    pub key_id: String,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trusted_comment: Option<String>,
+    pub signature: String,
+}
+
+impl SignatureEnvelope {
+    fn signed_prefix(&self) -> Vec<u8> {
+        let mut prefix = format!(
+            "rcli-signature-v{}\0{}\0{}\0{}\0",
+            self.version, self.algorithm, self.key_id, self.timestamp
+        )
+        .into_bytes();
+        if let Some(comment) = &self.trusted_comment {
+            prefix.extend_from_slice(comment.as_bytes());
+        }
+        prefix.push(0);
+        prefix
+    }
+}
+
+trait KeyId {
+    fn key_id(&self) -> String;
+}
+
+// the first 8 bytes of a keyed blake3 hash of the public (or shared) key, in hex
+pub(crate) fn key_id(key: &[u8]) -> String {
+    blake3::derive_key("rcli 2024-05 key id", key)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl KeyId for Blake3 {
+    fn key_id(&self) -> String {
+        key_id(&self.key)
+    }
+}
+
+impl KeyId for Ed25519Singer {
+    fn key_id(&self) -> String {
+        key_id(self.key.verifying_key().as_bytes())
+    }
+}
+
+impl KeyId for Ed25519Verifier {
+    fn key_id(&self) -> String {
+        key_id(self.key.as_bytes())
+    }
+}
+
+pub fn process_text_sign(
+    data: SignedData,
+    key: &[u8],
     format: TextSignFormat,
-    sig: String,
-) -> anyhow::Result<bool> {
-    let mut reader = input_reader(input)?;
-    let sig = BASE64_URL_SAFE_NO_PAD.decode(sig)?;
-    let verified = match format {
+    trusted_comment: Option<String>,
+) -> Result<SignatureEnvelope> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is after 1970")
+        .as_secs();
+    match format {
+        TextSignFormat::Blake3 => sign_envelope(
+            &Blake3::try_new(key)?,
+            data,
+            format,
+            timestamp,
+            trusted_comment,
+        ),
+        TextSignFormat::Ed25519 => sign_envelope(
+            &Ed25519Singer::try_new(key)?,
+            data,
+            format,
+            timestamp,
+            trusted_comment,
+        ),
+    }
+}
+
+fn sign_envelope(
+    signer: &(impl TextSign + KeyId),
+    data: SignedData,
+    format: TextSignFormat,
+    timestamp: u64,
+    trusted_comment: Option<String>,
+) -> Result<SignatureEnvelope> {
+    let mut envelope = SignatureEnvelope {
+        version: ENVELOPE_VERSION,
+        algorithm: format.to_string(),
+        key_id: signer.key_id(),
+        timestamp,
+        trusted_comment,
+        signature: String::new(),
+    };
+    let prefix = envelope.signed_prefix();
+    let signature = match data {
+        SignedData::Reader(reader) => signer.sign(&mut Cursor::new(prefix).chain(reader))?,
+        SignedData::File(path) => signer.sign_file(&prefix, path)?,
+    };
+    envelope.signature = URL_SAFE_NO_PAD.encode(signature);
+    Ok(envelope)
+}
+
+// the key is `key` when given, otherwise the keyring is searched for a key
+// with the envelope's key id
+pub fn process_text_verify(
+    data: SignedData,
+    envelope: &SignatureEnvelope,
+    key: Option<&[u8]>,
+    keyring: &Path,
+) -> Result<bool> {
+    if envelope.version == 0 || envelope.version > ENVELOPE_VERSION {
+        bail!(
+            InvalidInput,
+            "Unsupported signature version {}",
+            envelope.version
+        );
+    }
+    match envelope.algorithm.parse()? {
         TextSignFormat::Blake3 => {
-            let verifier = Blake3::load(&key)?;
-            verifier.verify(&mut reader, &sig)?
+            let verifier = find_key::<Blake3>(key, keyring, &envelope.key_id)?;
+            verify_envelope(&verifier, data, envelope)
         }
         TextSignFormat::Ed25519 => {
-            let verifier = Ed25519Verifier::load(&key)?;
-            verifier.verify(&mut reader, &sig)?
+            let verifier = find_key::<Ed25519Verifier>(key, keyring, &envelope.key_id)?;
+            if envelope.version == 1 {
+                verify_envelope(&PureEd25519Verifier(&verifier), data, envelope)
+            } else {
+                verify_envelope(&verifier, data, envelope)
+            }
         }
-    };
-    println!("{}", verified);
-    Ok(verified)
+    }
+}
+
+fn verify_envelope(
+    verifier: &impl TextVerify,
+    data: SignedData,
+    envelope: &SignatureEnvelope,
+) -> Result<bool> {
+    let sig = BASE64_URL_SAFE_NO_PAD
+        .decode(&envelope.signature)
+        .map_err(|e| err!(InvalidInput, source = e, "Invalid signature"))?;
+    let prefix = envelope.signed_prefix();
+    match data {
+        SignedData::Reader(reader) => verifier.verify(Cursor::new(prefix).chain(reader), &sig),
+        SignedData::File(path) => verifier.verify_file(&prefix, path, &sig),
+    }
+}
+
+fn find_key<K: KeyLoader + KeyId>(key: Option<&[u8]>, keyring: &Path, key_id: &str) -> Result<K> {
+    if let Some(key) = key {
+        let key = K::try_new(key)?;
+        if key.key_id() != key_id {
+            bail!(
+                InvalidKey,
+                "The key has id {}, but the signature was made by {}",
+                key.key_id(),
+                key_id
+            );
+        }
+        return Ok(key);
+    }
+    let entries = fs::read_dir(keyring).map_err(|e| {
+        err!(
+            InvalidKey,
+            source = e,
+            "Cannot read keyring {}",
+            keyring.display()
+        )
+    })?;
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        // files holding other kinds of keys just don't load or don't match
+        if let Ok(key) = K::load(&path)
+            && key.key_id() == key_id
+        {
+            return Ok(key);
+        }
+    }
+    bail!(
+        InvalidKey,
+        "No key with id {} in keyring {}",
+        key_id,
+        keyring.display()
+    )
+}
+
+// ~/.config/rcli/keyring, used when neither --keyring nor $RCLI_KEYRING (read
+// by clap) names one
+pub fn default_keyring() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    Path::new(&home)
+        .join(".config")
+        .join("rcli")
+        .join("keyring")
 }
 
-pub fn process_text_generate(format: TextSignFormat) -> anyhow::Result<Vec<Vec<u8>>> {
+pub fn process_text_generate(format: KeyGenFormat) -> Result<Vec<Vec<u8>>> {
     match format {
-        TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed25519 => Ed25519Singer::generate(),
+        KeyGenFormat::Blake3 => Blake3::generate(),
+        KeyGenFormat::Ed25519 => Ed25519Singer::generate(),
+        KeyGenFormat::X25519 => <Identity as KeyGenerator>::generate(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ed25519_dalek::Signer;
+
     #[test]
-    fn test_blake3_sign_verify() -> anyhow::Result<()> {
+    fn test_blake3_sign_verify() -> Result<()> {
         let blake3 = Blake3::load("fixtures/blake3.txt")?;
         let data = b"hello world!";
         let sig = blake3.sign(&mut &data[..])?;
@@ -227,7 +472,7 @@ mod tests {
     }
 
     #[test]
-    fn test_ed25519_sign_verify() -> anyhow::Result<()> {
+    fn test_ed25519_sign_verify() -> Result<()> {
         let sk = Ed25519Singer::load("fixtures/ed25519.sk")?;
         let pk = Ed25519Verifier::load("fixtures/ed25519.pk")?;
 
@@ -236,4 +481,119 @@ mod tests {
         assert!(pk.verify(&data[..], &sig)?);
         Ok(())
     }
+
+    #[test]
+    fn test_file_and_reader_agree() -> Result<()> {
+        let path = Path::new("fixtures/urlsafe_base64.txt");
+        let mut data = b"prefix".to_vec();
+        data.extend(fs::read(path)?);
+
+        let blake3 = Blake3::load("fixtures/blake3.txt")?;
+        let sig = blake3.sign_file(b"prefix", path)?;
+        assert_eq!(sig, blake3.sign(&mut &data[..])?);
+        assert!(blake3.verify(&data[..], &sig)?);
+
+        let sk = Ed25519Singer::load("fixtures/ed25519.sk")?;
+        let pk = Ed25519Verifier::load("fixtures/ed25519.pk")?;
+        let sig = sk.sign_file(b"prefix", path)?;
+        assert!(pk.verify(&data[..], &sig)?);
+        assert!(!pk.verify_file(b"other", path, &sig)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_round_trip() -> Result<()> {
+        let sk = Ed25519Singer::load("fixtures/ed25519.sk")?;
+        let comment = Some("release 1.0".to_string());
+        let path = Path::new("fixtures/urlsafe_base64.txt");
+        let envelope = sign_envelope(
+            &sk,
+            SignedData::File(path),
+            TextSignFormat::Ed25519,
+            1714000000,
+            comment,
+        )?;
+        assert_eq!(
+            envelope.key_id,
+            Ed25519Verifier::load("fixtures/ed25519.pk")?.key_id()
+        );
+
+        // the key is found in the keyring by its id
+        let keyring = Path::new("fixtures");
+        let envelope = process_text_sign(
+            SignedData::File(path),
+            &fs::read("fixtures/ed25519.sk")?,
+            TextSignFormat::Ed25519,
+            None,
+        )?;
+        assert!(process_text_verify(
+            SignedData::File(path),
+            &envelope,
+            None,
+            keyring
+        )?);
+
+        let key = fs::read("fixtures/blake3.txt")?;
+        let envelope =
+            process_text_sign(SignedData::File(path), &key, TextSignFormat::Blake3, None)?;
+        let data = fs::read(path)?;
+        assert!(process_text_verify(
+            SignedData::Reader(Box::new(&data[..])),
+            &envelope,
+            Some(&key),
+            keyring
+        )?);
+
+        // metadata is covered by the signature
+        let mut forged = envelope.clone();
+        forged.timestamp += 1;
+        assert!(!process_text_verify(
+            SignedData::File(path),
+            &forged,
+            None,
+            keyring
+        )?);
+
+        let mut unknown = envelope;
+        unknown.key_id = "0000000000000000".into();
+        let err = process_text_verify(SignedData::File(path), &unknown, None, keyring).unwrap_err();
+        assert!(err.to_string().starts_with("No key with id"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_1_envelope_verifies() -> Result<()> {
+        let sk = Ed25519Singer::load("fixtures/ed25519.sk")?;
+        let data = b"hello world!";
+        // what rcli wrote before Ed25519ph: pure Ed25519 over prefix and data
+        let mut envelope = SignatureEnvelope {
+            version: 1,
+            algorithm: "ed25519".into(),
+            key_id: sk.key_id(),
+            timestamp: 1714000000,
+            trusted_comment: None,
+            signature: String::new(),
+        };
+        let mut message = envelope.signed_prefix();
+        message.extend_from_slice(data);
+        envelope.signature = URL_SAFE_NO_PAD.encode(sk.key.sign(&message).to_bytes());
+
+        let pk = fs::read("fixtures/ed25519.pk")?;
+        let verify = |envelope: &SignatureEnvelope| {
+            process_text_verify(
+                SignedData::Reader(Box::new(&data[..])),
+                envelope,
+                Some(&pk),
+                Path::new("fixtures"),
+            )
+        };
+        assert!(verify(&envelope)?);
+
+        // the same signature doesn't pass as Ed25519ph
+        envelope.version = 2;
+        assert!(!verify(&envelope)?);
+        envelope.version = 3;
+        assert!(verify(&envelope).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,331 @@
+use crate::cli::{EpochUnit, TimeFormat, Zone};
+use crate::error::{Result, bail, err};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, TimeZone, Utc,
+};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+// the time in every zone, or only in the format asked for
+pub fn process_time(
+    input: &str,
+    unit: Option<EpochUnit>,
+    from_tz: Zone,
+    zones: &[Zone],
+    to: Option<TimeFormat>,
+) -> Result<String> {
+    let now = Utc::now();
+    let time = parse_time(input, unit, from_tz, now)?;
+    let lines: Vec<(String, String)> = match to {
+        Some(format @ (TimeFormat::Rfc3339 | TimeFormat::Rfc2822)) => zones
+            .iter()
+            .map(|zone| (String::new(), format_time(time, format, *zone, now)))
+            .collect(),
+        Some(format) => vec![(String::new(), format_time(time, format, Zone::Local, now))],
+        None => {
+            let epochs = [EpochUnit::Seconds, EpochUnit::Millis, EpochUnit::Nanos]
+                .map(TimeFormat::Epoch)
+                .map(|format| {
+                    (
+                        format.to_string(),
+                        format_time(time, format, Zone::Local, now),
+                    )
+                });
+            let zones = zones.iter().map(|zone| {
+                let time = format_time(time, TimeFormat::Rfc3339, *zone, now);
+                (zone.to_string(), time)
+            });
+            let human = (
+                "human".to_string(),
+                format_time(time, TimeFormat::Human, Zone::Local, now),
+            );
+            epochs.into_iter().chain(zones).chain([human]).collect()
+        }
+    };
+    let width = lines
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    Ok(lines
+        .into_iter()
+        .map(|(label, value)| match label.as_str() {
+            "" => format!("{}\n", value),
+            label => format!("{:<width$}  {}\n", label, value),
+        })
+        .collect())
+}
+
+pub fn parse_time(
+    input: &str,
+    unit: Option<EpochUnit>,
+    zone: Zone,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>> {
+    let input = input.trim();
+    if let Some(offsets) = input.strip_prefix("now") {
+        return relative(now, offsets);
+    }
+    let numeric = input.strip_prefix('-').unwrap_or(input);
+    if let Some(unit) = unit {
+        return from_epoch(input, unit);
+    }
+    if !numeric.is_empty() && numeric.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        // seconds until 5138, then ms, us and ns
+        let digits = numeric.split('.').next().unwrap_or_default().len();
+        let unit = match digits {
+            0..=11 => EpochUnit::Seconds,
+            12..=14 => EpochUnit::Millis,
+            15..=17 => EpochUnit::Micros,
+            _ => EpochUnit::Nanos,
+        };
+        return from_epoch(input, unit);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(input)
+        .or_else(|_| DateTime::parse_from_rfc2822(input))
+        .or_else(|_| DateTime::parse_from_str(input, "%Y-%m-%d %H:%M:%S%.f%:z"))
+    {
+        return Ok(time.to_utc());
+    }
+    let naive = [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(input, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    })
+    .ok_or_else(|| err!(InvalidInput, "Cannot read {:?} as a time", input))?;
+    localize(zone, naive).ok_or_else(|| err!(InvalidInput, "{} doesn't exist in {}", naive, zone))
+}
+
+pub fn format_time(
+    time: DateTime<Utc>,
+    format: TimeFormat,
+    zone: Zone,
+    now: DateTime<Utc>,
+) -> String {
+    match format {
+        TimeFormat::Epoch(unit) => {
+            let nanos =
+                time.timestamp() as i128 * NANOS_PER_SEC + time.timestamp_subsec_nanos() as i128;
+            nanos.div_euclid(nanos_per(unit)).to_string()
+        }
+        TimeFormat::Rfc3339 => in_zone(time, zone).to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        TimeFormat::Rfc2822 => in_zone(time, zone).to_rfc2822(),
+        TimeFormat::Human => human(time - now),
+    }
+}
+
+// offsets like -7d+2h30m from now
+fn relative(now: DateTime<Utc>, offsets: &str) -> Result<DateTime<Utc>> {
+    let mut time = now;
+    let mut rest = offsets.trim();
+    while !rest.is_empty() {
+        let sign = match rest.as_bytes()[0] {
+            b'+' => 1,
+            b'-' => -1,
+            _ => bail!(InvalidInput, "Expected + or - in now{}", offsets),
+        };
+        rest = &rest[1..];
+        loop {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let units = rest[digits..]
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .map_or(rest.len(), |n| digits + n);
+            let (n, unit) = (&rest[..digits], &rest[digits..units]);
+            let n: i64 = n
+                .parse()
+                .map_err(|_| err!(InvalidInput, "Expected a number in now{}", offsets))?;
+            let ms = match unit {
+                "ms" => 1,
+                "s" => 1_000,
+                "m" | "min" => 60_000,
+                "h" => 3_600_000,
+                "d" => 86_400_000,
+                "w" => 604_800_000,
+                _ => bail!(
+                    InvalidInput,
+                    "Unknown unit {:?}, use ms, s, m, h, d or w",
+                    unit
+                ),
+            };
+            let delta = n
+                .checked_mul(ms * sign)
+                .and_then(TimeDelta::try_milliseconds)
+                .ok_or_else(|| err!(InvalidInput, "now{} is out of range", offsets))?;
+            time = time
+                .checked_add_signed(delta)
+                .ok_or_else(|| err!(InvalidInput, "now{} is out of range", offsets))?;
+            rest = &rest[units..];
+            // 2h30m keeps the sign
+            if !rest.starts_with(|c: char| c.is_ascii_digit()) {
+                break;
+            }
+        }
+    }
+    Ok(time)
+}
+
+// decimals are read exactly, floats can't hold nanoseconds
+fn from_epoch(input: &str, unit: EpochUnit) -> Result<DateTime<Utc>> {
+    let invalid = || err!(InvalidInput, "Cannot read {:?} as epoch {}", input, unit);
+    let (negative, digits) = match input.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, input),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let whole: i128 = whole.parse().map_err(|_| invalid())?;
+    let fraction = format!("{:0<9}", fraction)
+        .parse::<i128>()
+        .map_err(|_| invalid())?;
+    let nanos = whole
+        .checked_mul(nanos_per(unit))
+        .and_then(|n| n.checked_add(fraction * nanos_per(unit) / NANOS_PER_SEC))
+        .ok_or_else(invalid)?;
+    let nanos = if negative { -nanos } else { nanos };
+    i64::try_from(nanos.div_euclid(NANOS_PER_SEC))
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, nanos.rem_euclid(NANOS_PER_SEC) as u32))
+        .ok_or_else(|| err!(InvalidInput, "{} {} is out of range", input, unit))
+}
+
+fn nanos_per(unit: EpochUnit) -> i128 {
+    match unit {
+        EpochUnit::Seconds => NANOS_PER_SEC,
+        EpochUnit::Millis => 1_000_000,
+        EpochUnit::Micros => 1_000,
+        EpochUnit::Nanos => 1,
+    }
+}
+
+fn in_zone(time: DateTime<Utc>, zone: Zone) -> DateTime<FixedOffset> {
+    match zone {
+        Zone::Local => time.with_timezone(&Local).fixed_offset(),
+        Zone::Named(tz) => time.with_timezone(&tz).fixed_offset(),
+        Zone::Fixed(offset) => time.with_timezone(&offset),
+    }
+}
+
+// the earlier one when clocks go back, none when they skip it
+fn localize(zone: Zone, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match zone {
+        Zone::Local => Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.to_utc()),
+        Zone::Named(tz) => tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.to_utc()),
+        Zone::Fixed(offset) => offset
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.to_utc()),
+    }
+}
+
+// months are 30 days and years 365
+fn human(delta: TimeDelta) -> String {
+    let secs = delta.num_seconds();
+    let (n, unit) = match secs.unsigned_abs() {
+        0 => return "now".to_string(),
+        s @ 1..60 => (s, "second"),
+        s @ 60..3_600 => (s / 60, "minute"),
+        s @ 3_600..86_400 => (s / 3_600, "hour"),
+        s @ 86_400..2_592_000 => (s / 86_400, "day"),
+        s @ 2_592_000..31_536_000 => (s / 2_592_000, "month"),
+        s => (s / 31_536_000, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    if secs < 0 {
+        format!("{} {}{} ago", n, unit, plural)
+    } else {
+        format!("in {} {}{}", n, unit, plural)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    fn parse(input: &str) -> Result<DateTime<Utc>> {
+        parse_time(input, None, "Asia/Shanghai".parse()?, now())
+    }
+
+    #[test]
+    fn test_parse_time() -> Result<()> {
+        let expected = now();
+        for input in [
+            "1700000000",
+            "1700000000000",
+            "1700000000000000",
+            "1700000000000000000",
+            "2023-11-14T22:13:20Z",
+            "2023-11-15T06:13:20+08:00",
+            "2023-11-15 06:13:20+08:00",
+            "Tue, 14 Nov 2023 22:13:20 +0000",
+            "2023-11-15 06:13:20",
+            "now",
+            "now+1d-24h",
+            "now-1h30m+90m",
+        ] {
+            assert_eq!(parse(input)?, expected, "{}", input);
+        }
+        assert_eq!(parse("1700000000.25")?.timestamp_subsec_millis(), 250);
+        assert_eq!(
+            parse("2023-11-15")?.to_rfc3339(),
+            "2023-11-14T16:00:00+00:00"
+        );
+        assert_eq!(parse("now-7d")?, expected - TimeDelta::days(7));
+        assert!(parse("now-7y").is_err());
+        assert!(parse("yesterday").is_err());
+        // 02:30 doesn't exist in New York on 2024-03-10
+        let zone = "America/New_York".parse()?;
+        assert!(parse_time("2024-03-10 02:30", None, zone, now()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_time() -> Result<()> {
+        let time = parse("1700000000123")?;
+        let utc = "utc".parse()?;
+        let format =
+            |f: &str, zone| -> Result<String> { Ok(format_time(time, f.parse()?, zone, now())) };
+        assert_eq!(format("s", utc)?, "1700000000");
+        assert_eq!(format("ms", utc)?, "1700000000123");
+        assert_eq!(format("ns", utc)?, "1700000000123000000");
+        assert_eq!(format("rfc3339", utc)?, "2023-11-14T22:13:20.123Z");
+        assert_eq!(
+            format("rfc3339", "Asia/Kolkata".parse()?)?,
+            "2023-11-15T03:43:20.123+05:30"
+        );
+        assert_eq!(
+            format("rfc2822", "-03:00".parse()?)?,
+            "Tue, 14 Nov 2023 19:13:20 -0300"
+        );
+        assert_eq!(format("human", utc)?, "now");
+        assert_eq!(
+            format_time(now() - TimeDelta::days(7), TimeFormat::Human, utc, now()),
+            "7 days ago"
+        );
+        assert_eq!(
+            format_time(now() + TimeDelta::hours(1), TimeFormat::Human, utc, now()),
+            "in 1 hour"
+        );
+        Ok(())
+    }
+}
@@ -1,11 +0,0 @@
-use std::fs::File;
-use std::io::Read;
-
-pub fn input_reader(input: String) -> anyhow::Result<Box<dyn Read>> {
-    let reader: Box<dyn Read> = if input == "-" {
-        Box::new(std::io::stdin())
-    } else {
-        Box::new(File::open(input)?)
-    };
-    Ok(reader)
-}
@@ -0,0 +1,391 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+
+// the binary with an empty HOME and working directory, so no config file
+// or keyring of the machine running the tests gets in the way
+fn rcli(dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.env("HOME", dir)
+        .env_remove("RUST_BACKTRACE")
+        .env_remove("RCLI_KEYRING")
+        .env_remove("RCLI_PASSPHRASE")
+        .current_dir(dir);
+    cmd
+}
+
+fn fixture(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(name)
+        .display()
+        .to_string()
+}
+
+#[test]
+fn test_convert_and_csv_query() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    rcli(dir)
+        .args(["convert", "-i", &fixture("tests/fixtures/players.csv")])
+        .args(["--to", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"number\": 21"))
+        .stdout(predicate::str::contains("\"born\": \"1974-11-09\""));
+
+    rcli(dir)
+        .args(["csv", "query", "-i", &fixture("tests/fixtures/players.csv")])
+        .args([
+            "--where",
+            "number > 2",
+            "--select",
+            "name",
+            "--sort",
+            "name",
+        ])
+        .assert()
+        .success()
+        .stdout("name\nChiellini\nDel Piero\nPirlo\n");
+
+    rcli(dir)
+        .args(["convert", "-i", &fixture("tests/fixtures/players.csv")])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("use --to"));
+}
+
+#[test]
+fn test_encode_and_decode_stdin() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    rcli(dir)
+        .args(["encode", "-c", "hex"])
+        .write_stdin("hello")
+        .assert()
+        .success()
+        .stdout("68656c6c6f\n");
+    rcli(dir)
+        .args(["decode", "-c", "base32"])
+        .write_stdin("MZXW6YTBOI======\n")
+        .assert()
+        .success()
+        .stdout("foobar");
+    rcli(dir)
+        .args(["base64", "encode"])
+        .write_stdin("hello")
+        .assert()
+        .success()
+        .stdout("aGVsbG8=\n");
+    rcli(dir)
+        .args(["decode", "-c", "hex"])
+        .write_stdin("6g")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_query_and_set() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let config = dir.join("config.toml");
+    fs::copy(fixture("tests/fixtures/config.toml"), &config).unwrap();
+    rcli(dir)
+        .args(["query", ".server.routes[0].path", "config.toml", "-r"])
+        .assert()
+        .success()
+        .stdout("/\n");
+
+    rcli(dir)
+        .args(["set", ".server.port", "9090", "config.toml"])
+        .assert()
+        .success()
+        .stdout("");
+    let content = fs::read_to_string(&config).unwrap();
+    assert!(content.contains("port = 9090"));
+    assert!(content.contains("host = \"localhost\""));
+
+    // editing in place keeps the file's permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&config, fs::Permissions::from_mode(0o600)).unwrap();
+        rcli(dir)
+            .args(["set", ".server.host", "example.com", "config.toml"])
+            .assert()
+            .success();
+        let mode = fs::metadata(&config).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    rcli(dir)
+        .args(["set", ".nope[3]", "1", "config.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cannot set [3] of null"));
+}
+
+#[test]
+fn test_diff_and_patch() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let (old, new) = (
+        fixture("tests/fixtures/old.json"),
+        fixture("tests/fixtures/new.yaml"),
+    );
+    rcli(dir)
+        .args(["diff", &old, &old])
+        .assert()
+        .success()
+        .stdout("");
+    rcli(dir)
+        .args(["diff", &old, &new])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(".version"));
+
+    let output = rcli(dir)
+        .args(["diff", &old, &new, "-f", "json-patch"])
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+    fs::write(dir.join("changes.json"), output).unwrap();
+    rcli(dir)
+        .args(["patch", &old, "changes.json", "-o", "patched.json"])
+        .assert()
+        .success();
+    rcli(dir)
+        .args(["diff", "patched.json", &new])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_schema_validate() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    rcli(dir)
+        .args([
+            "schema",
+            "validate",
+            "-s",
+            &fixture("tests/fixtures/schema.json"),
+        ])
+        .arg(fixture("tests/fixtures/players.ndjson"))
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("record 1 at /number"))
+        .stdout(predicate::str::contains("record 2"))
+        .stderr("2 violations in 3 records\n");
+
+    rcli(dir)
+        .args(["schema", "infer", &fixture("tests/fixtures/players.csv")])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"format\": \"date\""));
+}
+
+#[test]
+fn test_encrypt_and_decrypt() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    fs::write(dir.join("secret.key"), [7u8; 32]).unwrap();
+    fs::write(dir.join("plain.txt"), "attack at dawn\n").unwrap();
+    rcli(dir)
+        .args(["text", "encrypt", "-k", "secret.key", "-i", "plain.txt"])
+        .args(["-o", "cipher.bin"])
+        .assert()
+        .success();
+    rcli(dir)
+        .args(["text", "decrypt", "-k", "secret.key", "-i", "cipher.bin"])
+        .assert()
+        .success()
+        .stdout("attack at dawn\n");
+
+    // a tampered file leaves no output behind
+    let mut cipher = fs::read(dir.join("cipher.bin")).unwrap();
+    let last = cipher.len() - 1;
+    cipher[last] ^= 1;
+    fs::write(dir.join("tampered.bin"), cipher).unwrap();
+    rcli(dir)
+        .args(["text", "decrypt", "-k", "secret.key", "-i", "tampered.bin"])
+        .args(["-o", "out.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Decryption failed"));
+    assert!(!dir.join("out.txt").exists());
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    fs::write(dir.join("release.txt"), "rcli 1.0\n").unwrap();
+    rcli(dir)
+        .args(["text", "sign", "-i", "release.txt", "--format", "ed25519"])
+        .args(["-k", &fixture("fixtures/ed25519.sk"), "-c", "release 1.0"])
+        .assert()
+        .success();
+    rcli(dir)
+        .args(["text", "verify", "-i", "release.txt"])
+        .args(["-k", &fixture("fixtures/ed25519.pk")])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("verified: true"))
+        .stdout(predicate::str::contains("trusted comment: release 1.0"));
+
+    // x25519 keys only seal and open
+    rcli(dir)
+        .args(["text", "sign", "-i", "release.txt", "--format", "x25519"])
+        .args(["-k", &fixture("fixtures/ed25519.sk")])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Unsupported format: x25519"));
+
+    fs::write(dir.join("release.txt"), "rcli 1.1\n").unwrap();
+    rcli(dir)
+        .args(["text", "verify", "-i", "release.txt"])
+        .args(["-k", &fixture("fixtures/ed25519.pk")])
+        .assert()
+        .code(1)
+        .stdout("verified: false\n");
+}
+
+#[test]
+fn test_broken_config_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    fs::write(
+        dir.join(".rcli.toml"),
+        "[time]\ntz = \"UTC\"\n\n[csv]\nnope = 1\n",
+    )
+    .unwrap();
+    rcli(dir).arg("--help").assert().success();
+    rcli(dir)
+        .args(["config", "show"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("time.tz = \"UTC\""))
+        .stderr(predicate::str::contains(
+            ".rcli.toml:5: rcli csv has no option nope",
+        ));
+    rcli(dir)
+        .args(["time", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no option nope"));
+}
+
+#[test]
+fn test_config_secrets_are_not_printed() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    fs::write(
+        dir.join(".rcli.toml"),
+        "[text.encrypt]\npassphrase = \"hunter2\"\n",
+    )
+    .unwrap();
+    rcli(dir)
+        .args(["text", "encrypt", "--help"])
+        .env("RCLI_HTTP_AUTH", "me:secret")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hunter2").not());
+    rcli(dir)
+        .args(["http", "serve", "--help"])
+        .env("RCLI_HTTP_AUTH", "me:secret")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("secret").not());
+    rcli(dir)
+        .args(["config", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "text.encrypt.passphrase = \"<redacted>\"",
+        ))
+        .stdout(predicate::str::contains("hunter2").not());
+}
+
+#[test]
+fn test_generated_secret_keys_are_private() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    for format in ["blake3", "ed25519", "x25519"] {
+        rcli(dir)
+            .args(["text", "generate", "-f", format, "-o", "."])
+            .assert()
+            .success();
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |name: &str| fs::metadata(dir.join(name)).unwrap().permissions().mode() & 0o777;
+        for name in ["blake3.txt", "ed25519.sk", "x25519.sk"] {
+            assert_eq!(mode(name), 0o600, "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_jwt_sign_and_verify() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let key = fixture("fixtures/blake3.txt");
+    let token = rcli(dir)
+        .args(["jwt", "sign", "-k", &key, "--alg", "hs256", "--sub", "1"])
+        .args(["--aud", "web", "--exp", "1h"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let token = String::from_utf8(token).unwrap();
+    rcli(dir)
+        .args([
+            "jwt", "verify", "-k", &key, "--alg", "hs256", "--aud", "web",
+        ])
+        .arg(token.trim())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"sub\": \"1\""));
+    rcli(dir)
+        .args([
+            "jwt", "verify", "-k", &key, "--alg", "hs256", "--aud", "app",
+        ])
+        .arg(token.trim())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("aud: is \"web\""));
+}
+
+#[test]
+fn test_hash_id_and_time() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    rcli(dir)
+        .args(["hash", "-"])
+        .write_stdin("abc")
+        .assert()
+        .success()
+        .stdout("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  -\n");
+    rcli(dir)
+        .args(["id", "inspect", "01890a5d-ac96-774b-bcce-b302099a8057"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "timestamp  2023-06-30T03:34:18.518Z",
+        ));
+    rcli(dir)
+        .args(["time", "1700000000", "-t", "rfc3339"])
+        .assert()
+        .success()
+        .stdout("2023-11-14T22:13:20Z\n");
+    rcli(dir)
+        .args(["time", "yesterday-ish"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cannot read"));
+}
@@ -1,5 +1,16 @@
-#[allow(unused)]
-#[derive(Debug)]
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::compute::concat_batches;
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::dataframe::DataFrame;
+use datafusion::functions_aggregate::expr_fn::{
+    approx_percentile_cont, avg, count, max, median, min, stddev, sum,
+};
+use datafusion::logical_expr::{Expr, cast, ident, lit};
+use datafusion::scalar::ScalarValue;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy)]
 pub enum DescribeMethod {
     Total,
     NullTotal,
@@ -11,13 +22,116 @@ pub enum DescribeMethod {
     Percentile(u8),
 }
 
+// DataFrame::describe() looks columns up with `col`, which lowercases them,
+// so it fails on datasets like juventus.csv; this one quotes every column
 #[derive(Debug)]
 pub struct DataFrameDescriber {
     original: DataFrame,
-    transformed: DataFrame,
     methods: Vec<DescribeMethod>,
 }
 
 impl DataFrameDescriber {
+    pub fn new(df: DataFrame) -> Self {
+        let methods = vec![
+            DescribeMethod::Total,
+            DescribeMethod::NullTotal,
+            DescribeMethod::Mean,
+            DescribeMethod::Stddev,
+            DescribeMethod::Min,
+            DescribeMethod::Max,
+            DescribeMethod::Median,
+            DescribeMethod::Percentile(25),
+            DescribeMethod::Percentile(75),
+        ];
+        Self {
+            original: df,
+            methods,
+        }
+    }
+
+    // one row per method, every value cast to a string so the rows share a schema
+    pub async fn describe(&self) -> anyhow::Result<RecordBatch> {
+        let fields = self.original.schema().fields().clone();
+        let mut batches = Vec::with_capacity(self.methods.len());
+        for method in &self.methods {
+            // the row count keeps the aggregate valid when the method fits no column
+            let aggs = fields
+                .iter()
+                .filter_map(|field| Some(method.expr(field)?.alias(field.name())))
+                .chain(std::iter::once(count(lit(1)).alias("__rows")))
+                .collect::<Vec<_>>();
+            // columns the method doesn't apply to get a null
+            let columns = std::iter::once(lit(method.to_string()).alias("describe"))
+                .chain(fields.iter().map(|field| {
+                    let value = match method.expr(field) {
+                        Some(_) => cast(ident(field.name()), DataType::Utf8),
+                        None => lit(ScalarValue::Utf8(None)),
+                    };
+                    value.alias(field.name())
+                }))
+                .collect::<Vec<_>>();
+            let df = self
+                .original
+                .clone()
+                .aggregate(vec![], aggs)?
+                .select(columns)?;
+            batches.extend(df.collect().await?);
+        }
+        // a count is never null but a mean can be, so the rows only agree on nullable columns
+        let schema = batches
+            .first()
+            .map(|batch| batch.schema())
+            .ok_or_else(|| anyhow::anyhow!("Nothing to describe"))?;
+        let schema = Schema::new(
+            schema
+                .fields()
+                .iter()
+                .map(|field| field.as_ref().clone().with_nullable(true))
+                .collect::<Vec<_>>(),
+        );
+        Ok(concat_batches(&Arc::new(schema), &batches)?)
+    }
+}
 
-}
\ No newline at end of file
+impl DescribeMethod {
+    fn expr(&self, field: &Field) -> Option<Expr> {
+        let column = ident(field.name());
+        let data_type = field.data_type();
+        let numeric = data_type.is_numeric();
+        let comparable = numeric
+            || data_type.is_temporal()
+            || matches!(
+                data_type,
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Boolean
+            );
+        let expr = match self {
+            DescribeMethod::Total => count(column),
+            DescribeMethod::NullTotal => sum(cast(column.is_null(), DataType::Int64)),
+            DescribeMethod::Mean if numeric => avg(column),
+            DescribeMethod::Stddev if numeric => stddev(column),
+            DescribeMethod::Min if comparable => min(column),
+            DescribeMethod::Max if comparable => max(column),
+            DescribeMethod::Median if numeric => median(column),
+            DescribeMethod::Percentile(p) if numeric => {
+                approx_percentile_cont(cast(column, DataType::Float64), lit(*p as f64 / 100.0))
+            }
+            _ => return None,
+        };
+        Some(expr)
+    }
+}
+
+impl fmt::Display for DescribeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescribeMethod::Total => write!(f, "count"),
+            DescribeMethod::NullTotal => write!(f, "null_count"),
+            DescribeMethod::Mean => write!(f, "mean"),
+            DescribeMethod::Stddev => write!(f, "std"),
+            DescribeMethod::Min => write!(f, "min"),
+            DescribeMethod::Max => write!(f, "max"),
+            DescribeMethod::Median => write!(f, "median"),
+            DescribeMethod::Percentile(p) => write!(f, "{}%", p),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+mod describe;
+
+use crate::cli::connect::{ConnectOpts, DatasetConn};
+use crate::ctx::repl::{Backend, ReplDisplay};
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::Field;
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::dataframe::DataFrame;
+use datafusion::prelude::{CsvReadOptions, NdJsonReadOptions, SessionConfig, SessionContext};
+use describe::DataFrameDescriber;
+use std::sync::Arc;
+
+pub struct DataFusionBackend(SessionContext);
+
+impl DataFusionBackend {
+    pub fn new() -> Self {
+        // `list` reads the registered tables from information_schema
+        let mut config = SessionConfig::new();
+        config.options_mut().catalog.information_schema = true;
+        Self(SessionContext::new_with_config(config))
+    }
+}
+
+impl Default for DataFusionBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for DataFusionBackend {
+    async fn connect(&mut self, opts: &ConnectOpts) -> anyhow::Result<()> {
+        match &opts.conn {
+            DatasetConn::Postgres(_) => {
+                anyhow::bail!("Postgres connection is not supported yet");
+            }
+            DatasetConn::Csv(file_opts) => {
+                let options = CsvReadOptions::new()
+                    .file_extension(&file_opts.ext)
+                    .file_compression_type(file_opts.compression);
+                self.0
+                    .register_csv(&opts.name, &file_opts.filename, options)
+                    .await?;
+            }
+            DatasetConn::Parquet(filename) => {
+                self.0
+                    .register_parquet(&opts.name, filename, Default::default())
+                    .await?;
+            }
+            DatasetConn::NdJson(file_opts) => {
+                let options = NdJsonReadOptions::default()
+                    .file_extension(&file_opts.ext)
+                    .file_compression_type(file_opts.compression);
+                self.0
+                    .register_json(&opts.name, &file_opts.filename, options)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> anyhow::Result<impl ReplDisplay> {
+        let sql = "SELECT table_name, table_type FROM information_schema.tables WHERE table_schema = 'public'";
+        Ok(self.0.sql(sql).await?)
+    }
+
+    // built from the table itself, `name` never ends up in sql
+    async fn schema(&self, name: &str) -> anyhow::Result<impl ReplDisplay> {
+        let df = self.0.table(name).await?;
+        let fields = df.schema().fields();
+        let column = |value: fn(&Field) -> String| {
+            let values = fields.iter().map(|f| value(f)).collect::<Vec<_>>();
+            Arc::new(StringArray::from(values)) as ArrayRef
+        };
+        let batch = RecordBatch::try_from_iter([
+            ("column_name", column(|f| f.name().to_string())),
+            ("data_type", column(|f| f.data_type().to_string())),
+            (
+                "is_nullable",
+                column(|f| if f.is_nullable() { "YES" } else { "NO" }.to_string()),
+            ),
+        ])?;
+        Ok(batch)
+    }
+
+    async fn describe(&self, name: &str) -> anyhow::Result<String> {
+        let df = self.0.table(name).await?;
+        let batch = DataFrameDescriber::new(df).describe().await?;
+        batch.display().await
+    }
+
+    async fn head(&self, name: &str, size: usize) -> anyhow::Result<impl ReplDisplay> {
+        Ok(self.0.table(name).await?.limit(0, Some(size))?)
+    }
+
+    async fn sql(&self, sql: &str) -> anyhow::Result<impl ReplDisplay> {
+        Ok(self.0.sql(sql).await?)
+    }
+}
+
+impl ReplDisplay for DataFrame {
+    async fn display(self) -> anyhow::Result<String> {
+        let batches = self.collect().await?;
+        let data = pretty_format_batches(&batches)?;
+        Ok(data.to_string())
+    }
+}
+
+impl ReplDisplay for RecordBatch {
+    async fn display(self) -> anyhow::Result<String> {
+        let data = pretty_format_batches(&[self])?;
+        Ok(data.to_string())
+    }
+}
@@ -1 +1,3 @@
-pub mod backend;
\ No newline at end of file
+pub mod fusion;
+
+pub use fusion::DataFusionBackend;
@@ -1,4 +1,5 @@
 use clap::Parser;
+use enum_dispatch::enum_dispatch;
 use crate::cli::connect::ConnectOpts;
 use crate::cli::describe::DescribeOpts;
 use crate::cli::head::HeadOpts;
@@ -7,7 +8,7 @@ use crate::cli::schema::SchemaOpts;
 use crate::cli::sql::SqlOpts;
 
 #[derive(Debug, Parser)]
-#[enum_dispatch::CmdExecutor]
+#[enum_dispatch(CmdExecutor)]
 pub enum ReplCommand {
     #[command(name =  "connect", about = "Connect to a dataset and register it ot rdata")]
     Connect(ConnectOpts),
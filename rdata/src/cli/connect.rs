@@ -1,3 +1,4 @@
+use std::path::Path;
 use clap::{ArgMatches, Parser};
 use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
 use crate::ctx::repl::{Backend, CmdExecutor, ReplContext, ReplMsg};
@@ -24,7 +25,7 @@ pub enum DatasetConn {
 pub struct ConnectOpts {
     #[arg(value_parser= verify_conn_str, help = "Connection to the dataset, could be postgres of local file(support: csv, parquet, json)")]
     pub conn: DatasetConn,
-    #[arg(short, long, helpc = "The name of table if connect to a database")]
+    #[arg(short, long, help = "The name of table if connect to a database")]
     pub table: Option<String>,
     #[arg(short, long, help = "The name of the database")]
     pub name: String,
@@ -55,7 +56,7 @@ impl ConnectOpts {
 
 impl CmdExecutor for ConnectOpts {
     async fn execute<T: Backend>(&self, backend: &mut T) -> anyhow::Result<String> {
-        backend.connect(&self).await?;
+        backend.connect(self).await?;
         Ok(format!("Connected to dataset: {}", self.name))
     }
 }
@@ -65,45 +66,96 @@ fn verify_conn_str(conn_str: &str) -> Result<DatasetConn, String> {
         return Ok(DatasetConn::Postgres(conn_str));
     }
 
-    // process .csv, .csv.gz, .csv.bz2, .csv.xz, .csv.zstd
-    let exts = conn_str.split('.').collect::<Vec<_>>();
-    let len = exts.len();
-    let mut exts = exts.into_iter().take(len -1);
-    let ext1 = exts.next();
-    let ext2 = exts.next();
-
-    match (ext1, ext2) {
-        (Some(ext1), Some(ext2)) => {
-            let compression = match ext1 {
-                "gz" => FileCompressionType::GZIP,
-                "bz2" => FileCompressionType::BZIP2,
-                "xz" => FileCompressionType::XZ,
-                "zstd" => FileCompressionType::ZSTD,
-                invalid => return Err(format!("Unrecognized compression type: {}", invalid))
-            };
-            let opts: FileOpts = FileOpts {
-                filename: conn_str.to_string(),
-                ext: ext2.to_string(),
-                compression,
-            };
-            match ext1 {
-                "csv" => Ok(DatasetConn::Csv(opts)),
-                "json" | "jsonl" | "ndjson" => Ok(DatasetConn::NdJson(opts)),
-                invalid => Err(format!("Unrecognized file extension: {}", invalid))
-            }
+    // process .csv, .csv.gz, .csv.bz2, .csv.xz, .csv.zstd, only the file name is looked at
+    // so that dots in the directories don't count as extensions
+    let filename = Path::new(&conn_str)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let exts = filename.split('.').skip(1).collect::<Vec<_>>();
+    let (ext, compression) = match exts.as_slice() {
+        [.., ext, "gz"] => (*ext, FileCompressionType::GZIP),
+        [.., ext, "bz2"] => (*ext, FileCompressionType::BZIP2),
+        [.., ext, "xz"] => (*ext, FileCompressionType::XZ),
+        [.., ext, "zst" | "zstd"] => (*ext, FileCompressionType::ZSTD),
+        [.., ext] => (*ext, FileCompressionType::UNCOMPRESSED),
+        [] => {
+            return Err(format!(
+                "Unrecognized database connection string: {}",
+                conn_str
+            ));
         }
-        (Some(ext1), None) => {
-            let opts: FileOpts = FileOpts {
-                filename: conn_str.to_string(),
-                ext: ext1.to_string(),
-                compression: FileCompressionType::UNCOMPRESSED,
-            };
-            match ext1 {
-                "csv" => Ok(DatasetConn::Csv(opts)),
-                "json" | "jsonl" | "ndjson" => Ok(DatasetConn::NdJson(opts)),
-                invalid => Err(format!("Unrecognized file extension: {}", invalid))
-            }
+    };
+    // datafusion only picks up files ending with the extension, compression suffix included
+    let suffix = if compression.is_compressed() { 2 } else { 1 };
+    let opts = FileOpts {
+        filename: conn_str.clone(),
+        ext: format!(".{}", exts[exts.len() - suffix..].join(".")),
+        compression,
+    };
+    match ext {
+        "csv" => Ok(DatasetConn::Csv(opts)),
+        "json" | "jsonl" | "ndjson" => Ok(DatasetConn::NdJson(opts)),
+        "parquet" if suffix == 1 => Ok(DatasetConn::Parquet(conn_str)),
+        // parquet compresses its own pages, datafusion won't read a compressed file
+        "parquet" => Err(format!(
+            "Compressed parquet files are not supported: {}",
+            conn_str
+        )),
+        invalid => Err(format!("Unrecognized file extension: {}", invalid)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_opts(conn_str: &str) -> (String, FileCompressionType) {
+        match verify_conn_str(conn_str) {
+            Ok(DatasetConn::Csv(opts) | DatasetConn::NdJson(opts)) => (opts.ext, opts.compression),
+            other => panic!("unexpected connection {:?}", other),
         }
-        _ => Err(format!("Unrecognized database connection string: {}", conn_str)),
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_verify_conn_str() {
+        assert!(matches!(verify_conn_str("a.csv"), Ok(DatasetConn::Csv(_))));
+        assert_eq!(
+            file_opts("a.csv"),
+            (".csv".to_string(), FileCompressionType::UNCOMPRESSED)
+        );
+        // dots in directories are not extensions
+        assert!(matches!(
+            verify_conn_str("dir.v1/a.csv.gz"),
+            Ok(DatasetConn::Csv(_))
+        ));
+        assert_eq!(
+            file_opts("dir.v1/a.csv.gz"),
+            (".csv.gz".to_string(), FileCompressionType::GZIP)
+        );
+        assert!(matches!(
+            verify_conn_str("a.ndjson.zst"),
+            Ok(DatasetConn::NdJson(_))
+        ));
+        assert_eq!(
+            file_opts("a.ndjson.zst"),
+            (".ndjson.zst".to_string(), FileCompressionType::ZSTD)
+        );
+        assert!(matches!(
+            verify_conn_str("a.parquet"),
+            Ok(DatasetConn::Parquet(name)) if name == "a.parquet"
+        ));
+        assert_eq!(
+            verify_conn_str("a.parquet.gz").unwrap_err(),
+            "Compressed parquet files are not supported: a.parquet.gz"
+        );
+        assert_eq!(
+            verify_conn_str("noext").unwrap_err(),
+            "Unrecognized database connection string: noext"
+        );
+        assert!(matches!(
+            verify_conn_str("postgresql://localhost/db"),
+            Ok(DatasetConn::Postgres(_))
+        ));
+    }
+}
@@ -26,7 +26,6 @@ impl DescribeOpts {
 
 impl CmdExecutor for DescribeOpts {
     async fn execute<T: Backend>(&self, backend: &mut T) -> anyhow::Result<String> {
-        let df = backend.schema(&self.name).await?;
-        df.display().await
+        backend.describe(&self.name).await
     }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use clap::{ArgMatches, Parser};
-use crate::ctx::repl::{Backend, CmdExecutor, ReplContext, ReplMsg};
+use crate::ctx::repl::{Backend, CmdExecutor, ReplContext, ReplDisplay, ReplMsg};
 use crate::ctx::ReplResult;
 
 #[derive(Debug, Parser)]
@@ -2,27 +2,36 @@ use std::thread;
 use crossbeam_channel as mpsc;
 use enum_dispatch::enum_dispatch;
 use reedline_repl_rs::CallBackMap;
-use reedline_repl_rs::crossterm::ExecutableCommand;
+use crate::backend::DataFusionBackend;
 use crate::cli;
 use crate::cli::command::ReplCommand;
 use crate::cli::connect::ConnectOpts;
+// enum_dispatch generates the ReplCommand impl here, next to the trait
+use crate::cli::describe::DescribeOpts;
+use crate::cli::head::HeadOpts;
+use crate::cli::list::ListOpts;
+use crate::cli::schema::SchemaOpts;
+use crate::cli::sql::SqlOpts;
 
-pub type DisplayResult = anyhow::Result<impl ReplDisplay>;
 pub type ReplCallBacks = CallBackMap<ReplContext, reedline_repl_rs::Error>;
+// the backend runs on its own thread with block_on, so the futures don't need to be Send
+#[allow(async_fn_in_trait)]
 pub trait Backend {
     async fn connect(&mut self, opts: &ConnectOpts) -> anyhow::Result<()>;
-    async fn list(&self) -> DisplayResult;
-    async fn schema(&self, name: &str) -> DisplayResult;
+    async fn list(&self) -> anyhow::Result<impl ReplDisplay>;
+    async fn schema(&self, name: &str) -> anyhow::Result<impl ReplDisplay>;
     async fn describe(&self, name: &str) -> anyhow::Result<String>;
-    async fn head(&self, name: &str, size: usize) -> DisplayResult;
-    async fn sql(&self, sql: &str) -> DisplayResult;
+    async fn head(&self, name: &str, size: usize) -> anyhow::Result<impl ReplDisplay>;
+    async fn sql(&self, sql: &str) -> anyhow::Result<impl ReplDisplay>;
 }
 
+#[allow(async_fn_in_trait)]
 pub trait ReplDisplay {
     async fn display(self) -> anyhow::Result<String>;
 }
 
 #[enum_dispatch]
+#[allow(async_fn_in_trait)]
 pub trait CmdExecutor {
     async fn execute<T: Backend>(&self, backend: &mut T) -> anyhow::Result<String>;
 }
@@ -60,6 +69,12 @@ pub fn get_callbacks() -> ReplCallBacks {
     callbacks
 }
 
+impl Default for ReplContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ReplContext {
     pub fn new() -> Self {
         let (tx, rx) = mpsc::unbounded::<ReplMsg>();
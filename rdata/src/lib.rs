@@ -1,2 +1,6 @@
+pub mod backend;
 pub mod cli;
-pub mod ctx;
\ No newline at end of file
+pub mod ctx;
+
+pub use cli::command::ReplCommand;
+pub use ctx::repl::{ReplContext, get_callbacks};
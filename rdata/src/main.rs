@@ -1,3 +1,19 @@
-fn main() {
-    println!("Hello, world!");
+use rdata::{ReplCommand, ReplContext, get_callbacks};
+use reedline_repl_rs::Repl;
+
+const HISTORY_SIZE: usize = 1024;
+
+fn main() -> anyhow::Result<()> {
+    let mut repl = Repl::new(ReplContext::new())
+        .with_name("rdata")
+        .with_version(env!("CARGO_PKG_VERSION"))
+        .with_banner("Welcome to rdata, type `help` to list the commands")
+        .with_prompt("rdata")
+        .with_derived::<ReplCommand>(get_callbacks());
+    // without a home directory (e.g. HOME unset) the session just isn't remembered
+    if let Some(home) = dirs::home_dir() {
+        repl = repl.with_history(home.join(".rdata_history"), HISTORY_SIZE);
+    }
+    repl.run()?;
+    Ok(())
 }